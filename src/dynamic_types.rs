@@ -1,14 +1,32 @@
-use std::{sync::Arc, any::{TypeId, Any}, ptr::drop_in_place, mem::MaybeUninit};
+use std::{sync::{Arc, atomic::{AtomicU64, Ordering}}, marker::PhantomData, any::{TypeId, Any}, ptr::{drop_in_place, NonNull}, mem::MaybeUninit, alloc::{self, Layout}, ops::{Deref, DerefMut}, fmt, hash::{Hash, Hasher}};
 
 use ahash::AHashMap;
 use smartstring::alias::String;
 use parking_lot::{RwLock, Mutex};
 use thiserror::Error;
 
+use visit::{DynamicVisitor, Visitable};
+
+pub mod codec;
+pub mod field_path;
+pub mod object_property;
+pub mod type_expr;
+pub mod type_names;
+pub mod visit;
+#[cfg(feature = "serde")]
+pub mod serde_support;
+#[cfg(feature = "serde")]
+pub mod type_dump;
+
 #[derive(Default)]
 pub struct TypeRegistry {
     static_types: RwLock<AHashMap<TypeId, Arc<StaticTypeLayout>>>,
     dynamic_types: RwLock<AHashMap<String, Arc<DynamicTypeLayout>>>,
+    dynamic_enums: RwLock<AHashMap<String, Arc<DynamicEnumLayout>>>,
+    prototypes: RwLock<AHashMap<String, DynamicStruct>>,
+    codecs: RwLock<AHashMap<TypeId, codec::Codec>>,
+    property_values: RwLock<AHashMap<TypeId, object_property::PropertyEncoder>>,
+    type_names: RwLock<type_names::TypeNameResolver>,
 }
 
 impl TypeRegistry {
@@ -63,10 +81,33 @@ impl TypeRegistry {
         add_type!(this, Arc<Mutex<T>>);
     }
 
-    pub fn add_dyn(&self, layout: DynamicTypeLayout) {
-        self.dynamic_types
+    /// Registers a prebuilt layout, e.g. one extended with `StaticTypeLayout::with_clone`, replacing
+    /// whatever layout was registered for the same type.
+    pub fn add_layout(&self, layout: StaticTypeLayout) {
+        self.static_types
             .write()
-            .insert(layout.name.clone(), Arc::new(layout));
+            .insert(layout.type_id, Arc::new(layout));
+    }
+
+    /// Registers `layout` under its name, replacing any previous layout of that name unless it is
+    /// still held outside the registry, by instances, nested layouts or anything else. Fails if the
+    /// name is already taken by a dynamic enum.
+    pub fn add_dyn(&self, layout: DynamicTypeLayout) -> Result<(), TypeRegistryError> {
+        let mut dynamic_types = self.dynamic_types.write();
+        if self.dynamic_enums.read().contains_key(&layout.name) {
+            return Err(TypeRegistryError::DuplicateTypeName { name: layout.name });
+        }
+        if let Some(existing) = dynamic_types.get(&layout.name) {
+            let instances = Arc::strong_count(existing) - 1;
+            if instances > 0 {
+                return Err(TypeRegistryError::LayoutInUse {
+                    name: layout.name,
+                    instances,
+                });
+            }
+        }
+        dynamic_types.insert(layout.name.clone(), Arc::new(layout));
+        Ok(())
     }
 
     pub fn get_static_layout<T: 'static + DefaultBytes>(&self) -> Arc<StaticTypeLayout> {
@@ -87,11 +128,166 @@ impl TypeRegistry {
         self.dynamic_types.read().get(name).cloned()
     }
 
+    /// Whether the registered layout `name` is `base` or derives from it, false if it isn't registered.
+    pub fn is_a(&self, name: &str, base: &str) -> bool {
+        self.dynamic_types.read().get(name).is_some_and(|layout| layout.is_a(base))
+    }
+
+    /// Creates an instance of the layout `name`, cloning its prototype if one was registered with
+    /// `add_prototype`, otherwise with every field at its default.
     pub fn create_dynamic(&self, name: &str) -> DynamicStruct {
-        DynamicStruct::new(
-            self.get_dynamic_layout(name)
-                .unwrap_or_else(|| panic!("No dynamic type with name {}", name)),
-        )
+        self.try_create_dynamic(name).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_create_dynamic(&self, name: &str) -> Result<DynamicStruct, TypeRegistryError> {
+        if let Some(prototype) = self.prototypes.read().get(name) {
+            return Self::clone_prototype(prototype);
+        }
+        self.get_dynamic_layout(name)
+            .map(DynamicStruct::new)
+            .ok_or_else(|| TypeRegistryError::UnknownDynamicType { name: name.into() })
+    }
+
+    /// Clones the prototype of `name`, unlike `create_dynamic` this fails if none was registered.
+    pub fn create_from_prototype(&self, name: &str) -> DynamicStruct {
+        self.try_create_from_prototype(name).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_create_from_prototype(&self, name: &str) -> Result<DynamicStruct, TypeRegistryError> {
+        match self.prototypes.read().get(name) {
+            Some(prototype) => Self::clone_prototype(prototype),
+            None => Err(TypeRegistryError::NoPrototype { name: name.into() }),
+        }
+    }
+
+    fn clone_prototype(prototype: &DynamicStruct) -> Result<DynamicStruct, TypeRegistryError> {
+        prototype.as_ref().clone_cloneable().map_err(|(field, type_name)| TypeRegistryError::PrototypeNotCloneable {
+            layout: prototype.layout().name.clone(),
+            field,
+            type_name: type_name.into(),
+        })
+    }
+
+    /// Registers a populated instance that `create_dynamic` clones for its layout, replacing any
+    /// previous prototype. The layout must be the one registered under its name and every field
+    /// must be cloneable. The prototype keeps its layout in use, see `remove_prototype`.
+    pub fn add_prototype(&self, prototype: DynamicStruct) -> Result<(), TypeRegistryError> {
+        let layout = prototype.layout();
+        match self.get_dynamic_layout(&layout.name) {
+            Some(registered) if Arc::ptr_eq(&registered, layout) => {}
+            Some(_) => return Err(TypeRegistryError::PrototypeLayoutMismatch { name: layout.name.clone() }),
+            None => return Err(TypeRegistryError::UnknownDynamicType { name: layout.name.clone() }),
+        }
+        if let Some((field, type_name)) = layout.find_field_without(|layout| layout.clone_fn.is_some()) {
+            return Err(TypeRegistryError::PrototypeNotCloneable {
+                layout: layout.name.clone(),
+                field,
+                type_name: type_name.into(),
+            });
+        }
+
+        let name = layout.name.clone();
+        self.prototypes.write().insert(name, prototype);
+        Ok(())
+    }
+
+    pub fn remove_prototype(&self, name: &str) -> Option<DynamicStruct> {
+        self.prototypes.write().remove(name)
+    }
+
+    /// Registers `layout` under its name, with the same replacement rules as `add_dyn`. Fails if
+    /// the name is already taken by a dynamic struct.
+    pub fn add_dyn_enum(&self, layout: DynamicEnumLayout) -> Result<(), TypeRegistryError> {
+        // Same lock order as `add_dyn`, so the two can't deadlock against each other.
+        let dynamic_types = self.dynamic_types.read();
+        let mut dynamic_enums = self.dynamic_enums.write();
+        if dynamic_types.contains_key(&layout.name) {
+            return Err(TypeRegistryError::DuplicateTypeName { name: layout.name });
+        }
+        if let Some(existing) = dynamic_enums.get(&layout.name) {
+            let instances = Arc::strong_count(existing) - 1;
+            if instances > 0 {
+                return Err(TypeRegistryError::LayoutInUse {
+                    name: layout.name,
+                    instances,
+                });
+            }
+        }
+        dynamic_enums.insert(layout.name.clone(), Arc::new(layout));
+        Ok(())
+    }
+
+    pub fn get_dynamic_enum_layout(&self, name: &str) -> Option<Arc<DynamicEnumLayout>> {
+        self.dynamic_enums.read().get(name).cloned()
+    }
+
+    pub fn create_dynamic_enum(&self, name: &str) -> DynamicEnum {
+        self.try_create_dynamic_enum(name).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_create_dynamic_enum(&self, name: &str) -> Result<DynamicEnum, TypeRegistryError> {
+        self.get_dynamic_enum_layout(name)
+            .map(DynamicEnum::new)
+            .ok_or_else(|| TypeRegistryError::UnknownDynamicType { name: name.into() })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TypeRegistryError {
+    #[error("No dynamic type with name {name}")]
+    UnknownDynamicType {
+        name: String
+    },
+    #[error("Same field name {field} declared multiple times in {layout}.")]
+    DuplicateFieldName {
+        layout: String,
+        field: String
+    },
+    #[error("Name {name} is already registered as a different kind of dynamic type.")]
+    DuplicateTypeName {
+        name: String
+    },
+    #[error("Dynamic type {layout} has no fields with a size.")]
+    ZeroSizedLayout {
+        layout: String
+    },
+    #[error("Dynamic type {layout} is too large to allocate.")]
+    SizeOverflow {
+        layout: String
+    },
+    #[error("Dynamic type {name} can't be replaced, it is still used in {instances} places.")]
+    LayoutInUse {
+        name: String,
+        instances: usize
+    },
+    #[error("Same variant name {variant} declared multiple times in {layout}.")]
+    DuplicateVariantName {
+        layout: String,
+        variant: String
+    },
+    #[error("Dynamic enum {layout} has no variants.")]
+    NoVariants {
+        layout: String
+    },
+    #[error("Field {field} of {layout} shadows the field of the same name in its base {base}.")]
+    ShadowedField {
+        layout: String,
+        field: String,
+        base: String
+    },
+    #[error("No prototype registered for dynamic type {name}")]
+    NoPrototype {
+        name: String
+    },
+    #[error("Prototype for {name} does not use the layout registered under that name.")]
+    PrototypeLayoutMismatch {
+        name: String
+    },
+    #[error("Prototype for {layout} can't be cloned, field {field} of type {type_name} has no clone function.")]
+    PrototypeNotCloneable {
+        layout: String,
+        field: String,
+        type_name: String
     }
 }
 
@@ -125,511 +321,4111 @@ pub enum DynamicFieldError<T> {
         value: T,
         type_requested: String,
         actual_type: String
+    },
+    #[error("Field {name} of type {type_name} cannot be cloned.")]
+    FieldNotCloneable {
+        name: String,
+        type_name: String
+    },
+    #[error("Field {name} of type {type_name} cannot be compared.")]
+    FieldNotComparable {
+        name: String,
+        type_name: String
+    },
+    #[error("Field {name} of type {type_name} cannot be hashed.")]
+    FieldNotHashable {
+        name: String,
+        type_name: String
+    },
+    #[error("Invalid field path {path}, unexpected input at byte {position}.")]
+    InvalidPath {
+        path: String,
+        position: usize
+    },
+    #[error("Field {segment} does not exist.")]
+    PathFieldNotFound {
+        segment: String
+    },
+    #[error("Index {segment} requested was out of bounds, length is {len}.")]
+    PathIndexOutOfBounds {
+        segment: String,
+        len: usize
+    },
+    #[error("Field {segment} has no value.")]
+    PathValueMissing {
+        segment: String
+    },
+    #[error("Field {segment} of type {type_name} cannot be traversed.")]
+    PathNotTraversable {
+        segment: String,
+        type_name: String
+    },
+    #[error("Field {segment} is shared and cannot be borrowed mutably.")]
+    PathNotMutable {
+        segment: String
+    },
+    #[error("Field {segment} requested with incorrect type: requested: {type_requested}, expected: {actual_type}")]
+    PathInvalidType {
+        segment: String,
+        type_requested: String,
+        actual_type: String
+    },
+    #[error("Field handle used on {layout} was created from a different layout.")]
+    HandleLayoutMismatch {
+        layout: String
+    },
+    #[error("Buffer used with {layout} was initialised by {found}.")]
+    BufferLayoutMismatch {
+        layout: String,
+        found: String
+    },
+    #[error("Dynamic type {layout} does not derive from {base}.")]
+    NotDerivedFrom {
+        layout: String,
+        base: String
+    },
+    #[error("Variant {name} not found.")]
+    VariantNameNotFound {
+        name: String
+    },
+    #[error("Variant {requested} requested but {active} is active.")]
+    VariantNotActive {
+        requested: String,
+        active: String
+    },
+    #[error("Field {name} of type {type_name} cannot be serialized.")]
+    FieldNotSerializable {
+        name: String,
+        type_name: String
+    },
+    #[error("Field {name} of type {type_name} cannot be deserialized.")]
+    FieldNotDeserializable {
+        name: String,
+        type_name: String
     }
 }
 
-pub struct DynamicTypeLayout {
-    pub name: String,
-    pub field_types: Vec<TypeId>,
-    pub field_offsets: Vec<usize>,
-    pub field_sizes: Vec<usize>,
-    pub field_defaults: Vec<unsafe fn() -> Vec<u8>>,
-    pub field_drop_fns: Vec<Option<fn(*const u8)>>,
-    pub name_to_index: AHashMap<std::string::String, usize>,
-    pub total_size: usize,
-    pub field_type_names: Vec<&'static str>,
+/// How a `DynamicTypeLayout` arranges its fields in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LayoutStrategy {
+    /// Declaration order with `#[repr(C)]` padding, allows `DynamicStruct::cast` to a mirror struct.
+    #[default]
+    ReprC,
+    /// Fields sorted by alignment to minimise padding. Field indices still follow declaration order.
+    Optimized,
 }
 
-impl DynamicTypeLayout {
-    pub fn new(name: String, fields: &[(&str, &StaticTypeLayout)]) -> Self {
-        let mut field_types = Vec::with_capacity(fields.len());
-        let mut field_offsets = Vec::with_capacity(fields.len());
-        let mut field_sizes = Vec::with_capacity(fields.len());
-        let mut name_to_index = AHashMap::with_capacity(fields.len());
-        let mut field_type_names = Vec::with_capacity(fields.len());
-        let mut field_defaults = Vec::with_capacity(fields.len());
-        let mut field_drop_fns = Vec::with_capacity(fields.len());
-        let mut total_size = 0;
+/// What a single field of a `DynamicTypeLayout` stores.
+#[derive(Clone)]
+pub enum FieldKind {
+    /// A Rust value described by its `StaticTypeLayout`.
+    Static(StaticTypeLayout),
+    /// Another dynamic struct stored inline, by value.
+    Dynamic(Arc<DynamicTypeLayout>),
+    /// `len` elements stored inline back to back, each `element.size()` bytes apart.
+    Array { element: Box<FieldKind>, len: usize },
+}
 
-        let mut offset = 0;
-        for (index, field) in fields.iter().enumerate() {
-            if field_type_names.contains(&field.0) {
-                panic!("Same field name {} declared multiple times.", field.0);
-            }
-            field_types.push(field.1.type_id);
-            let remainder = offset % field.1.align;
-            if remainder != 0 {
-                offset += field.1.align - remainder;
-            }
-            field_offsets.push(offset);
-            total_size += field.1.size;
-            field_sizes.push(field.1.size);
-            name_to_index.insert(field.0.into(), index);
-            offset += field.1.size;
+/// Marker used as the `TypeId` of nested dynamic fields, so no Rust type passes their type checks.
+struct NestedDynamicStruct;
 
-            field_type_names.push(field.1.name);
-            field_defaults.push(field.1.default);
-            field_drop_fns.push(field.1.drop_fn);
-        }
-        total_size = total_size + (offset % total_size);
+/// Marker used as the `TypeId` of array fields, they are only reachable through the array accessors.
+struct InlineArray;
 
-        Self {
-            name,
-            field_types,
-            field_offsets,
-            field_sizes,
-            name_to_index,
-            total_size,
-            field_type_names,
-            field_defaults,
-            field_drop_fns,
+impl FieldKind {
+    #[inline]
+    pub fn size(&self) -> usize {
+        match self {
+            FieldKind::Static(layout) => layout.size,
+            FieldKind::Dynamic(layout) => layout.total_size,
+            FieldKind::Array { element, len } => element.size().saturating_mul(*len),
         }
     }
 
     #[inline]
-    pub fn set_field<T: 'static>(&self, data: &mut [u8], name: &str, val: T) {
-        let index = self.name_to_index[name];
-        self.set_field_by_index(data, index, val);
+    pub fn align(&self) -> usize {
+        match self {
+            FieldKind::Static(layout) => layout.align,
+            FieldKind::Dynamic(layout) => layout.align,
+            FieldKind::Array { element, .. } => element.align(),
+        }
     }
 
     #[inline]
-    pub fn set_field_by_index<T: 'static>(&self, data: &mut [u8], index: usize, val: T) {
-        self.check_type::<T>(index);
-        unsafe {
-            self.set_field_unchecked_by_index(data, index, val);
+    pub fn type_id(&self) -> TypeId {
+        match self {
+            FieldKind::Static(layout) => layout.type_id,
+            FieldKind::Dynamic(_) => TypeId::of::<NestedDynamicStruct>(),
+            FieldKind::Array { .. } => TypeId::of::<InlineArray>(),
         }
     }
 
     #[inline]
-    pub fn try_set_field<T: 'static>(&self, data: &mut [u8], name: &str, val: T) -> Result<(), DynamicFieldError<T>> {
-        if let Some(index) = self.name_to_index.get(name) {
-            self.try_set_field_by_index(data, *index, val)
-        } else {
-            Err(DynamicFieldError::SetFieldNameNotFound { name: name.into(), value: val })
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            FieldKind::Static(layout) => layout.name,
+            FieldKind::Dynamic(_) => std::any::type_name::<DynamicStruct>(),
+            FieldKind::Array { .. } => "[_]",
         }
     }
 
-    #[inline]
-    pub fn try_set_field_by_index<T: 'static>(&self, data: &mut [u8], index: usize, val: T) -> Result<(), DynamicFieldError<T>> {
-        if self.type_is::<T>(index) {
-            if self.field_offsets.len() < index {
-                Err(DynamicFieldError::FieldSetIndexOutOfBounds { index, value: val })
-            } else {
-                unsafe {
-                    self.set_field_unchecked_by_index(data, index, val);
-                }
-                Ok(())
-            }
-        } else {
-            Err(DynamicFieldError::SetInvalidTypeOfField { value: val, type_requested: std::any::type_name::<T>().into(), actual_type: self.field_type_names[index].to_string().into() })
+    /// Views a value of this kind, `data` must be exactly the value's initialised bytes.
+    fn value_ref<'a>(&'a self, data: &'a [u8]) -> FieldValue<'a> {
+        match self {
+            FieldKind::Static(layout) => FieldValue::Value(unsafe { &*(layout.as_any_fn)(data.as_ptr()) }),
+            FieldKind::Dynamic(layout) => FieldValue::Struct(DynamicStructRef { type_layout: layout, data }),
+            FieldKind::Array { element, len } => FieldValue::Array(DynamicArrayRef { element, data, len: *len }),
         }
     }
 
-    #[inline]
-    fn check_type<T: 'static>(&self, index: usize) {
-        if !self.type_is::<T>(index) {
-            panic!(
-                "Invalid type, expected: {:?}, but found {:?}",
-                self.field_type_names[index],
-                std::any::type_name::<T>()
-            );
+    fn value_mut<'a>(&'a self, data: &'a mut [u8]) -> FieldValueMut<'a> {
+        match self {
+            FieldKind::Static(layout) => FieldValueMut::Value(unsafe { &mut *(layout.as_any_mut_fn)(data.as_mut_ptr()) }),
+            FieldKind::Dynamic(layout) => FieldValueMut::Struct(DynamicStructMut { type_layout: layout, data }),
+            FieldKind::Array { element, len } => FieldValueMut::Array(DynamicArrayMut { element, data, len: *len }),
         }
     }
 
-    #[inline]
-    fn type_is<T: 'static>(&self, index: usize) -> bool {
-        self.field_types[index] == TypeId::of::<T>()
+    /// # Safety
+    /// `data` must hold an initialised value of this kind.
+    unsafe fn visit_data(&self, data: &[u8], visitor: &mut dyn DynamicVisitor) {
+        match self {
+            FieldKind::Static(layout) => match layout.visit_fn {
+                Some(visit) => visit(data.as_ptr(), visitor),
+                None => visitor.visit_opaque(layout.name, &*(layout.as_any_fn)(data.as_ptr())),
+            },
+            FieldKind::Dynamic(layout) => DynamicStructRef { type_layout: layout, data }.visit(visitor),
+            FieldKind::Array { element, len } => {
+                let size = element.size();
+                visitor.enter_seq(*len);
+                for index in 0..*len {
+                    element.visit_data(&data[index * size..(index + 1) * size], visitor);
+                }
+                visitor.exit_seq();
+            }
+        }
     }
 
-    #[inline]
-    pub fn clone_field<T: 'static + Clone>(&self, data: &[u8], name: &str) -> T {
-        let index = self.name_to_index[name];
-        self.clone_field_by_index(data, index)
+    fn is_repr_c(&self) -> bool {
+        match self {
+            FieldKind::Static(_) => true,
+            FieldKind::Dynamic(layout) => layout.is_repr_c(),
+            FieldKind::Array { element, .. } => element.is_repr_c(),
+        }
     }
 
-    #[inline]
-    pub fn clone_field_by_index<T: 'static + Clone>(&self, data: &[u8], index: usize) -> T {
-        self.check_type::<T>(index);
-        unsafe { self.clone_field_unchecked_by_index(data, index) }
+    fn is_structurally_equal(&self, other: &FieldKind) -> bool {
+        match (self, other) {
+            (FieldKind::Static(a), FieldKind::Static(b)) => a.type_id == b.type_id,
+            (FieldKind::Dynamic(a), FieldKind::Dynamic(b)) => a.is_structurally_equal(b),
+            (FieldKind::Array { element: a, len: a_len }, FieldKind::Array { element: b, len: b_len }) => {
+                a_len == b_len && a.is_structurally_equal(b)
+            }
+            _ => false,
+        }
     }
 
-    #[inline]
-    //TODO: add error type
-    pub fn try_clone_field<T: 'static + Clone>(&self, data: &[u8], name: &str) -> Result<T, DynamicFieldError<()>> {
-        if let Some(index) = self.name_to_index.get(name) {
-            self.try_clone_field_by_index(data, *index)
-        } else {
-            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
+    /// Returns the path below this field, starting with `.` or `[`, and type name of the first
+    /// static value whose layout lacks the capability checked by `has`.
+    fn find_without(&self, has: fn(&StaticTypeLayout) -> bool) -> Option<(String, &'static str)> {
+        match self {
+            FieldKind::Static(layout) => (!has(layout)).then(|| (String::new(), layout.name)),
+            FieldKind::Dynamic(layout) => layout.find_field_without(has).map(|(path, type_name)| {
+                let mut name = String::from(".");
+                name.push_str(&path);
+                (name, type_name)
+            }),
+            FieldKind::Array { element, len } => {
+                if *len == 0 {
+                    return None;
+                }
+                element.find_without(has).map(|(path, type_name)| {
+                    let mut name = String::from("[0]");
+                    name.push_str(&path);
+                    (name, type_name)
+                })
+            }
         }
     }
 
-    #[inline]
-    //TODO: add error type
-    pub fn try_clone_field_by_index<T: 'static + Clone>(&self, data: &[u8], index: usize) -> Result<T, DynamicFieldError<()>> {
-        if self.type_is::<T>(index) {
-            if self.field_offsets.len() < index {
-                Err(DynamicFieldError::FieldGetIndexOutOfBounds { index })
-            } else {
-                Ok(unsafe { self.clone_field_unchecked_by_index(data, index) })
+    /// # Safety
+    /// `dst` must be valid for writing `size` bytes and aligned to `align`.
+    unsafe fn init_data(&self, dst: *mut u8) {
+        match self {
+            FieldKind::Static(layout) => {
+                let bytes = (layout.default)();
+                dst.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+            }
+            FieldKind::Dynamic(layout) => layout.init_data(dst),
+            FieldKind::Array { element, len } => {
+                for index in 0..*len {
+                    element.init_data(dst.add(index * element.size()));
+                }
             }
-        } else {
-            Err(DynamicFieldError::GetInvalidTypeOfField { type_requested: std::any::type_name::<T>().into(), actual_type: self.field_type_names[index].to_string().into() })
         }
     }
 
-    #[inline]
-    pub fn get_field_ref<T: 'static>(&self, data: &[u8], name: &str) -> &T {
-        let index = self.name_to_index[name];
-        self.get_field_ref_by_index(data, index)
+    /// # Safety
+    /// `data` must hold an initialised value of this kind, which must not be used afterwards.
+    unsafe fn drop_data(&self, data: *const u8) {
+        match self {
+            FieldKind::Static(layout) => {
+                if let Some(drop) = layout.drop_fn {
+                    drop(data);
+                }
+            }
+            FieldKind::Dynamic(layout) => layout.drop_data(data),
+            FieldKind::Array { element, len } => {
+                for index in 0..*len {
+                    element.drop_data(data.add(index * element.size()));
+                }
+            }
+        }
     }
 
-    #[inline]
-    pub fn get_field_ref_by_index<T: 'static>(&self, data: &[u8], index: usize) -> &T {
-        self.check_type::<T>(index);
-        unsafe { self.get_field_ref_unchecked_by_index(data, index) }
+    /// # Safety
+    /// Same as `DynamicTypeLayout::clone_data`, for a single value of this kind.
+    unsafe fn clone_data(&self, src: *const u8, dst: *mut u8) {
+        match self {
+            FieldKind::Static(layout) => {
+                if let Some(clone) = layout.clone_fn {
+                    clone(src, dst);
+                }
+            }
+            FieldKind::Dynamic(layout) => layout.clone_data(src, dst),
+            FieldKind::Array { element, len } => {
+                for index in 0..*len {
+                    let offset = index * element.size();
+                    element.clone_data(src.add(offset), dst.add(offset));
+                }
+            }
+        }
     }
 
-    #[inline]
-    //TODO: add error type
-    pub fn try_get_field_ref<T: 'static>(&self, data: &[u8], name: &str) -> Result<&T, DynamicFieldError<()>> {
-        if let Some(index) = self.name_to_index.get(name) {
-            self.try_get_field_ref_by_index(data, *index)
-        } else {
-            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
+    /// # Safety
+    /// Same as `DynamicTypeLayout::eq_data`, for a single value of this kind.
+    unsafe fn eq_data(&self, a: *const u8, other: &FieldKind, b: *const u8) -> bool {
+        match (self, other) {
+            (FieldKind::Static(layout), _) => layout.eq_fn.is_some_and(|eq| eq(a, b)),
+            (FieldKind::Dynamic(layout), FieldKind::Dynamic(other)) => layout.eq_data(a, other, b),
+            (FieldKind::Array { element, len }, FieldKind::Array { element: other, .. }) => (0..*len)
+                .all(|index| element.eq_data(a.add(index * element.size()), other, b.add(index * other.size()))),
+            _ => false,
         }
     }
 
-    #[inline]
-    //TODO: add error type
-    pub fn try_get_field_ref_by_index<T: 'static>(&self, data: &[u8], index: usize) -> Result<&T, DynamicFieldError<()>> {
-        if self.type_is::<T>(index) {
-            if self.field_offsets.len() < index {
-                Err(DynamicFieldError::FieldGetIndexOutOfBounds { index })
-            } else {
-                Ok(unsafe { self.get_field_ref_unchecked_by_index(data, index) })
+    /// # Safety
+    /// Same as `DynamicTypeLayout::hash_data`, for a single value of this kind.
+    unsafe fn hash_data(&self, data: *const u8, state: &mut dyn Hasher) {
+        match self {
+            FieldKind::Static(layout) => {
+                if let Some(hash) = layout.hash_fn {
+                    hash(data, state);
+                }
+            }
+            FieldKind::Dynamic(layout) => layout.hash_data(data, state),
+            FieldKind::Array { element, len } => {
+                for index in 0..*len {
+                    element.hash_data(data.add(index * element.size()), &mut *state);
+                }
             }
-        } else {
-            Err(DynamicFieldError::GetInvalidTypeOfField { type_requested: std::any::type_name::<T>().into(), actual_type: self.field_type_names[index].to_string().into() })
         }
     }
+}
 
-    #[inline]
-    pub fn get_field_mut<T: 'static>(&self, data: &mut [u8], name: &str) -> &mut T {
-        let index = self.name_to_index[name];
-        self.get_field_mut_by_index(data, index)
+/// Describes the fields of a dynamic type.
+///
+/// The field accessors taking `data` read it as a value this layout initialised, so they stay
+/// private to `DynamicStruct` and its views, which always pair the two. Storage managed elsewhere
+/// goes through `LayoutBuffer` and `DynamicTypeLayout::buffer_ref`.
+pub struct DynamicTypeLayout {
+    pub name: String,
+    pub field_types: Vec<TypeId>,
+    pub field_offsets: Vec<usize>,
+    pub field_sizes: Vec<usize>,
+    pub field_kinds: Vec<FieldKind>,
+    pub field_names: Vec<String>,
+    pub name_to_index: AHashMap<std::string::String, usize>,
+    pub total_size: usize,
+    pub align: usize,
+    pub strategy: LayoutStrategy,
+    /// The layout this one derives from, its fields come first at the same offsets as in `base`.
+    pub base: Option<Arc<DynamicTypeLayout>>,
+    pub field_type_names: Vec<&'static str>,
+    field_defaults: Vec<Option<DefaultFn>>,
+    id: u64,
+}
+
+/// Source of `DynamicTypeLayout::id`, ids are never reused so a stale `FieldHandle` can't match a
+/// new layout that happens to be allocated at the same address.
+static NEXT_LAYOUT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Collects the fields of a `DynamicTypeLayout`, for layouts that need more than
+/// `DynamicTypeLayout::new` offers such as nested dynamic structs.
+pub struct DynamicTypeLayoutBuilder {
+    name: String,
+    fields: Vec<(String, FieldKind, Option<DefaultFn>)>,
+    strategy: LayoutStrategy,
+    base: Option<Arc<DynamicTypeLayout>>,
+}
+
+impl DynamicTypeLayoutBuilder {
+    pub fn field(mut self, name: &str, layout: &StaticTypeLayout) -> Self {
+        self.fields.push((name.into(), FieldKind::Static(layout.clone()), None));
+        self
     }
 
-    #[allow(clippy::mut_from_ref)]
-    #[inline]
-    pub fn get_field_mut_by_index<T: 'static>(&self, data: &mut [u8], index: usize) -> &mut T {
-        self.check_type::<T>(index);
-        unsafe { self.get_field_mut_unchecked_by_index(data, index) }
+    /// Adds a field which new instances initialise with a clone of `value` instead of the layout's
+    /// default. Panics if `layout` does not describe `T`.
+    pub fn field_with_default<T: Any + Clone + Send + Sync>(self, name: &str, layout: &StaticTypeLayout, value: T) -> Self {
+        self.field_with_default_fn(name, layout, move || value.clone())
     }
 
-    #[inline]
-    //TODO: add error type
-    pub fn try_get_field_mut<T: 'static>(&self, data: &mut [u8], name: &str) -> Result<&mut T, DynamicFieldError<()>> {
-        if let Some(index) = self.name_to_index.get(name) {
-            self.try_get_field_mut_by_index(data, *index)
-        } else {
-            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
-        }
+    /// Adds a field which new instances initialise by calling `default`. Panics if `layout` does
+    /// not describe `T`.
+    pub fn field_with_default_fn<T: Any>(
+        mut self,
+        name: &str,
+        layout: &StaticTypeLayout,
+        default: impl Fn() -> T + Send + Sync + 'static,
+    ) -> Self {
+        layout.check_type::<T>();
+        let default: DefaultFn = Arc::new(move |dst| unsafe { dst.cast::<T>().write(default()) });
+        self.fields.push((name.into(), FieldKind::Static(layout.clone()), Some(default)));
+        self
     }
 
-    #[inline]
-    //TODO: add error type
-    pub fn try_get_field_mut_by_index<T: 'static>(&self, data: &mut [u8], index: usize) -> Result<&mut T, DynamicFieldError<()>> {
-        if self.type_is::<T>(index) {
-            if self.field_offsets.len() < index {
-                Err(DynamicFieldError::FieldGetIndexOutOfBounds { index })
-            } else {
-                Ok(unsafe { self.get_field_mut_unchecked_by_index(data, index) })
-            }
-        } else {
-            Err(DynamicFieldError::GetInvalidTypeOfField { type_requested: std::any::type_name::<T>().into(), actual_type: self.field_type_names[index].to_string().into() })
-        }
+    /// Embeds `layout` by value, its fields live inside this struct's storage.
+    pub fn nested(mut self, name: &str, layout: &Arc<DynamicTypeLayout>) -> Self {
+        self.fields.push((name.into(), FieldKind::Dynamic(layout.clone()), None));
+        self
     }
 
-    #[inline]
-    /// # Safety
-    /// The field's type must match the generic type `T`
-    pub unsafe fn set_field_unchecked_by_index<T: 'static>(
-        &self,
-        data: &mut [u8],
-        index: usize,
-        val: T,
-    ) {
-        let mut ptr = data.as_mut_ptr();
-        ptr = ptr.add(self.field_offsets[index]);
-        let ptr = ptr.cast::<T>();
-        *ptr = val;
+    /// Stores `len` values of `layout` inline, like a Rust `[T; N]`.
+    pub fn array(mut self, name: &str, layout: &StaticTypeLayout, len: usize) -> Self {
+        let element = Box::new(FieldKind::Static(layout.clone()));
+        self.fields.push((name.into(), FieldKind::Array { element, len }, None));
+        self
     }
 
-    #[inline]
-    /// # Safety
-    /// The field's type must match the generic type `T`
-    pub unsafe fn clone_field_unchecked_by_index<T: 'static + Clone>(
-        &self,
-        data: &[u8],
-        index: usize,
-    ) -> T {
-        let offset = self.field_offsets[index];
-        let data = data.as_ptr().add(offset);
-        (*(std::mem::transmute::<*const u8, *const T>(data))).clone()
+    /// Stores `len` dynamic structs of `layout` inline, back to back.
+    pub fn nested_array(mut self, name: &str, layout: &Arc<DynamicTypeLayout>, len: usize) -> Self {
+        let element = Box::new(FieldKind::Dynamic(layout.clone()));
+        self.fields.push((name.into(), FieldKind::Array { element, len }, None));
+        self
     }
 
-    #[inline]
-    /// # Safety
-    /// The field's type must match the generic type `T`
-    pub unsafe fn get_field_ref_unchecked_by_index<T: 'static>(
-        &self,
-        data: &[u8],
-        index: usize,
-    ) -> &T {
-        let offset = self.field_offsets[index];
-        let data = data.as_ptr().add(offset);
-        &*std::mem::transmute::<*const u8, *const T>(data)
+    /// Derives from `base`, its fields come first and keep their offsets so a derived struct can be
+    /// viewed as its base. Declaring a field with the name of a base field is an error.
+    pub fn base(mut self, base: &Arc<DynamicTypeLayout>) -> Self {
+        self.base = Some(base.clone());
+        self
     }
 
-    #[inline]
-    #[allow(clippy::mut_from_ref)]
-    /// # Safety
-    /// The field's type must match the generic type `T`
-    pub unsafe fn get_field_mut_unchecked_by_index<T: 'static>(
-        &self,
-        data: &mut [u8],
-        index: usize,
-    ) -> &mut T {
-        let offset = self.field_offsets[index];
-        let data = data.as_ptr().add(offset);
-        &mut *std::mem::transmute::<*const u8, *mut T>(data)
+    pub fn strategy(mut self, strategy: LayoutStrategy) -> Self {
+        self.strategy = strategy;
+        self
     }
-}
 
-pub struct DynamicStruct {
-    type_layout: Arc<DynamicTypeLayout>,
-    data: Vec<u8>,
-}
+    /// Panics on duplicate field names, empty layouts or layouts too large to allocate, see `try_build`.
+    pub fn build(self) -> DynamicTypeLayout {
+        self.try_build().unwrap_or_else(|err| panic!("{}", err))
+    }
 
-impl Drop for DynamicStruct {
-    fn drop(&mut self) {
-        //Data has been taken or it is a 0 sized type.
-        if self.data.is_empty() {
-            return;
+    /// Reports duplicate or shadowed field names, layouts too large to allocate and layouts with no
+    /// size instead of panicking.
+    pub fn try_build(self) -> Result<DynamicTypeLayout, TypeRegistryError> {
+        let layout = self.build_sized()?;
+        if layout.total_size == 0 {
+            return Err(TypeRegistryError::ZeroSizedLayout { layout: layout.name });
         }
-        for (index, field) in self.type_layout.field_drop_fns.iter().enumerate() {
-            if let Some(drop) = field {
-                let offset = self.type_layout.field_offsets[index];
-                // Make sure we are not double dropping data in the default.
-                let ptr = self.data[offset..].as_ptr();
-                drop(ptr);
+        Ok(layout)
+    }
+
+    /// `try_build` without rejecting zero sized layouts, which only unit enum variants may have.
+    fn build_sized(self) -> Result<DynamicTypeLayout, TypeRegistryError> {
+        let Self { name, fields, strategy, base } = self;
+        // Base fields are copied in first, they are laid out already so only their offsets are kept.
+        let base_fields = base.iter().flat_map(|base| {
+            (0..base.field_kinds.len()).map(|index| {
+                (base.field_names[index].clone(), base.field_kinds[index].clone(), base.field_defaults[index].clone())
+            })
+        });
+        let fields: Vec<_> = base_fields.chain(fields).collect();
+        let base_len = base.as_ref().map_or(0, |base| base.field_kinds.len());
+
+        let mut field_types = Vec::with_capacity(fields.len());
+        let mut field_offsets = vec![0; fields.len()];
+        let mut field_sizes = Vec::with_capacity(fields.len());
+        let mut name_to_index = AHashMap::with_capacity(fields.len());
+        let mut field_type_names = Vec::with_capacity(fields.len());
+        let mut field_kinds = Vec::with_capacity(fields.len());
+        let mut field_names = Vec::with_capacity(fields.len());
+        let mut field_defaults = Vec::with_capacity(fields.len());
+        let mut align = base.as_ref().map_or(1, |base| base.align);
+
+        for (index, (field_name, kind, default)) in fields.into_iter().enumerate() {
+            if let Some(existing) = name_to_index.get(field_name.as_str()) {
+                if *existing < base_len {
+                    let base = base
+                        .as_ref()
+                        .map(|base| base.declaring_layout(*existing).name.clone())
+                        .unwrap_or_default();
+                    return Err(TypeRegistryError::ShadowedField {
+                        layout: name,
+                        field: field_name,
+                        base,
+                    });
+                }
+                return Err(TypeRegistryError::DuplicateFieldName {
+                    layout: name,
+                    field: field_name,
+                });
             }
+            field_types.push(kind.type_id());
+            align = align.max(kind.align());
+            field_sizes.push(kind.size());
+            name_to_index.insert(field_name.as_str().into(), index);
+            field_names.push(field_name);
+
+            field_type_names.push(kind.type_name());
+            field_kinds.push(kind);
+            field_defaults.push(default);
         }
+
+        // Fields are only ever reordered in memory, indices always follow declaration order.
+        let mut placement: Vec<usize> = (base_len..field_kinds.len()).collect();
+        if strategy == LayoutStrategy::Optimized {
+            // Sizes are multiples of their alignment, so placing the most aligned fields first
+            // leaves no padding between fields, only at the end.
+            placement.sort_by(|a, b| field_kinds[*b].align().cmp(&field_kinds[*a].align()));
+        }
+
+        let overflow = || TypeRegistryError::SizeOverflow { layout: name.clone() };
+        let mut offset: usize = 0;
+        if let Some(base) = &base {
+            field_offsets[..base_len].copy_from_slice(&base.field_offsets);
+            offset = base.total_size;
+        }
+        for index in placement {
+            let field = &field_kinds[index];
+            offset = offset.checked_next_multiple_of(field.align()).ok_or_else(overflow)?;
+            field_offsets[index] = offset;
+            offset = offset.checked_add(field.size()).ok_or_else(overflow)?;
+        }
+        // Same trailing padding rules as `#[repr(C)]`, the size is the end of the last field rounded
+        // up to the alignment of the whole struct.
+        let total_size = offset.checked_next_multiple_of(align).ok_or_else(overflow)?;
+        if Layout::from_size_align(total_size, align).is_err() {
+            return Err(overflow());
+        }
+
+        Ok(DynamicTypeLayout {
+            name,
+            field_types,
+            field_offsets,
+            field_sizes,
+            name_to_index,
+            total_size,
+            align,
+            strategy,
+            base,
+            field_type_names,
+            field_kinds,
+            field_names,
+            field_defaults,
+            id: NEXT_LAYOUT_ID.fetch_add(1, Ordering::Relaxed),
+        })
     }
 }
 
-impl DynamicStruct {
-    pub fn new(type_layout: Arc<DynamicTypeLayout>) -> Self {
-        let mut data = vec![0u8; type_layout.total_size];
+impl DynamicTypeLayout {
+    pub fn new(name: String, fields: &[(&str, &StaticTypeLayout)]) -> Self {
+        Self::with_strategy(name, fields, LayoutStrategy::ReprC)
+    }
 
-        for (create, offset) in type_layout
-            .field_defaults
+    pub fn with_strategy(
+        name: String,
+        fields: &[(&str, &StaticTypeLayout)],
+        strategy: LayoutStrategy,
+    ) -> Self {
+        fields
             .iter()
-            .zip(type_layout.field_offsets.iter())
-        {
-            let bytes = unsafe { create() };
-            let slice = &mut data[*offset..];
-            for (index, byte) in bytes.iter().enumerate() {
-                slice[index] = *byte;
-            }
-        }
+            .fold(Self::builder(name).strategy(strategy), |builder, (name, layout)| {
+                builder.field(name, layout)
+            })
+            .build()
+    }
 
-        Self { data, type_layout }
+    /// Like `new` but reports duplicate field names, layouts too large to allocate and layouts with
+    /// no size instead of panicking, for definitions that come from outside the program.
+    pub fn try_new(name: String, fields: &[(&str, &StaticTypeLayout)]) -> Result<Self, TypeRegistryError> {
+        Self::try_with_strategy(name, fields, LayoutStrategy::ReprC)
     }
 
-    pub fn size_of(&self) -> usize {
-        self.type_layout.total_size
+    pub fn try_with_strategy(
+        name: String,
+        fields: &[(&str, &StaticTypeLayout)],
+        strategy: LayoutStrategy,
+    ) -> Result<Self, TypeRegistryError> {
+        fields
+            .iter()
+            .fold(Self::builder(name).strategy(strategy), |builder, (name, layout)| {
+                builder.field(name, layout)
+            })
+            .try_build()
     }
 
-    /// # Safety
-    /// Only call this if the type is identical to the dynamic types byte layout.
+    /// Unique identity of this layout, `FieldHandle`s only work on structs of the layout they came from.
     #[inline]
-    pub unsafe fn cast<T>(mut self) -> T {
-        if self.data.len() != std::mem::size_of::<T>() {
-            panic!("Invalid sized type, data is {} bytes large and type attempted to cast to is {} bytes large.", self.data.len(), std::mem::size_of::<T>());
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Resolves the field `name` once, checking it holds a `T`, for repeated access without lookups.
+    #[inline]
+    pub fn field_handle<T: 'static>(&self, name: &str) -> Result<FieldHandle<T>, DynamicFieldError<()>> {
+        if let Some(index) = self.name_to_index.get(name) {
+            self.field_handle_by_index(*index)
+        } else {
+            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
         }
-        let bytes = self.data.clone();
-        self.data.clear();
-        bytes.cast()
+    }
+
+    pub fn field_handle_by_index<T: 'static>(&self, index: usize) -> Result<FieldHandle<T>, DynamicFieldError<()>> {
+        self.try_check_type::<T>(index)?;
+        Ok(FieldHandle {
+            layout_id: self.id,
+            index,
+            offset: self.field_offsets[index],
+            _marker: PhantomData,
+        })
     }
 
     #[inline]
-    pub fn set_field<T: 'static>(&mut self, name: &str, val: T) {
-        self.type_layout.set_field(&mut self.data, name, val);
+    fn check_handle<T>(&self, handle: &FieldHandle<T>) {
+        if handle.layout_id != self.id {
+            panic!("Field handle for {} was created from a different layout.", self.name);
+        }
     }
 
     #[inline]
-    pub fn clone_field<T: 'static + Clone>(&self, name: &str) -> T {
-        self.type_layout.clone_field(&self.data, name)
+    fn try_check_handle<T>(&self, handle: &FieldHandle<T>) -> Result<(), DynamicFieldError<()>> {
+        if handle.layout_id != self.id {
+            Err(DynamicFieldError::HandleLayoutMismatch { layout: self.name.clone() })
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn builder(name: String) -> DynamicTypeLayoutBuilder {
+        DynamicTypeLayoutBuilder {
+            name,
+            fields: Vec::new(),
+            strategy: LayoutStrategy::ReprC,
+            base: None,
+        }
+    }
+
+    /// Whether this layout is `name` or derives from it, directly or through its bases.
+    pub fn is_a(&self, name: &str) -> bool {
+        self.name == name || self.base_layout(name).is_some()
+    }
+
+    /// Finds the base named `name` anywhere in the inheritance chain, not counting this layout.
+    pub fn base_layout(&self, name: &str) -> Option<&Arc<DynamicTypeLayout>> {
+        let mut current = self.base.as_ref();
+        while let Some(layout) = current {
+            if layout.name == name {
+                return Some(layout);
+            }
+            current = layout.base.as_ref();
+        }
+        None
+    }
+
+    /// Whether this layout, its bases and every nested layout keep `#[repr(C)]` declaration order.
+    pub fn is_repr_c(&self) -> bool {
+        self.strategy == LayoutStrategy::ReprC
+            && self.base.as_ref().is_none_or(|base| base.is_repr_c())
+            && self.field_kinds.iter().all(FieldKind::is_repr_c)
+    }
+
+    /// The layout in this one's base chain, or this one itself, that declares field `index`.
+    fn declaring_layout(&self, index: usize) -> &DynamicTypeLayout {
+        let mut layout = self;
+        while let Some(base) = layout.base.as_deref().filter(|base| index < base.field_kinds.len()) {
+            layout = base;
+        }
+        layout
+    }
+
+    /// Returns the dotted path and type name of the first static field, searching nested layouts
+    /// too, whose layout lacks the capability checked by `has`.
+    fn find_field_without(&self, has: fn(&StaticTypeLayout) -> bool) -> Option<(String, &'static str)> {
+        self.field_kinds.iter().enumerate().find_map(|(index, kind)| {
+            kind.find_without(has).map(|(path, type_name)| {
+                let mut name = self.field_names[index].clone();
+                name.push_str(&path);
+                (name, type_name)
+            })
+        })
     }
 
+    /// Whether every field captured a clone function, making `DynamicStruct::try_clone` succeed.
     #[inline]
-    pub fn get_field_ref<T: 'static>(&self, name: &str) -> &T {
-        self.type_layout.get_field_ref(self.data.as_slice(), name)
+    pub fn is_cloneable(&self) -> bool {
+        self.find_field_without(|layout| layout.clone_fn.is_some()).is_none()
     }
 
+    /// Whether every field captured an eq function, making `DynamicStruct::try_eq` succeed.
     #[inline]
-    pub fn get_field_mut<T: 'static>(&mut self, name: &str) -> &mut T {
-        self.type_layout
-            .get_field_mut(self.data.as_mut_slice(), name)
+    pub fn is_comparable(&self) -> bool {
+        self.find_field_without(|layout| layout.eq_fn.is_some()).is_none()
     }
 
+    /// Whether every field captured a hash function, making `DynamicStruct::try_hash` succeed.
     #[inline]
-    pub fn set_field_by_index<T: 'static>(&mut self, val: T, index: usize) {
-        self.type_layout
-            .set_field_by_index(&mut self.data, index, val);
+    pub fn is_hashable(&self) -> bool {
+        self.find_field_without(|layout| layout.hash_fn.is_some()).is_none()
+    }
+
+    /// Layouts are structurally equal when they share a name and declare the same field types,
+    /// even if they are separate allocations or place their fields differently.
+    pub fn is_structurally_equal(&self, other: &DynamicTypeLayout) -> bool {
+        std::ptr::eq(self, other)
+            || (self.name == other.name
+                && self.field_types == other.field_types
+                && self.field_kinds.iter().zip(other.field_kinds.iter()).all(|(a, b)| a.is_structurally_equal(b)))
+    }
+
+    /// Writes the default value of every field, overridden defaults take precedence.
+    ///
+    /// # Safety
+    /// `dst` must be valid for writing `total_size` bytes and aligned to `align`.
+    unsafe fn init_data(&self, dst: *mut u8) {
+        for (index, kind) in self.field_kinds.iter().enumerate() {
+            let dst = dst.add(self.field_offsets[index]);
+            match &self.field_defaults[index] {
+                Some(default) => default(dst),
+                None => kind.init_data(dst),
+            }
+        }
+    }
+
+    /// # Safety
+    /// `data` must hold initialised fields of this layout, which must not be used afterwards.
+    unsafe fn drop_data(&self, data: *const u8) {
+        for (kind, offset) in self.field_kinds.iter().zip(self.field_offsets.iter()) {
+            kind.drop_data(data.add(*offset));
+        }
+    }
+
+    /// # Safety
+    /// `src` must hold initialised fields of this layout, `dst` must be valid for writing
+    /// `total_size` bytes aligned to `align` and the layout must be cloneable.
+    unsafe fn clone_data(&self, src: *const u8, dst: *mut u8) {
+        for (kind, offset) in self.field_kinds.iter().zip(self.field_offsets.iter()) {
+            kind.clone_data(src.add(*offset), dst.add(*offset));
+        }
+    }
+
+    /// # Safety
+    /// `a` must hold initialised fields of this layout, `b` of the structurally equal `other`,
+    /// and the layout must be comparable.
+    unsafe fn eq_data(&self, a: *const u8, other: &DynamicTypeLayout, b: *const u8) -> bool {
+        self.field_kinds.iter().enumerate().all(|(index, kind)| {
+            let a = a.add(self.field_offsets[index]);
+            let b = b.add(other.field_offsets[index]);
+            kind.eq_data(a, &other.field_kinds[index], b)
+        })
+    }
+
+    /// # Safety
+    /// `data` must hold initialised fields of this layout and the layout must be hashable.
+    unsafe fn hash_data(&self, data: *const u8, mut state: &mut dyn Hasher) {
+        self.name.hash(&mut state);
+        for (kind, offset) in self.field_kinds.iter().zip(self.field_offsets.iter()) {
+            kind.hash_data(data.add(*offset), &mut *state);
+        }
+    }
+
+    /// # Safety
+    /// `data` must hold initialised fields of this layout.
+    unsafe fn fmt_data(&self, data: *const u8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_data_as(&self.name, data, f)
+    }
+
+    /// # Safety
+    /// `data` must hold initialised fields of this layout.
+    unsafe fn fmt_data_as(&self, name: &str, data: *const u8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut output = f.debug_struct(name);
+        for (index, name) in self.field_names.iter().enumerate() {
+            output.field(
+                name,
+                &FieldDebug {
+                    ptr: data.add(self.field_offsets[index]),
+                    kind: &self.field_kinds[index],
+                },
+            );
+        }
+        output.finish()
     }
 
     #[inline]
-    pub fn clone_field_by_index<T: 'static + Clone>(&self, index: usize) -> T {
-        self.type_layout.clone_field_by_index(&self.data, index)
+    fn set_field<T: 'static>(&self, data: &mut [u8], name: &str, val: T) {
+        let index = self.name_to_index[name];
+        self.set_field_by_index(data, index, val);
     }
 
     #[inline]
-    pub fn get_field_ref_by_index<T: 'static>(&self, index: usize) -> &T {
-        self.type_layout
-            .get_field_ref_by_index(self.data.as_slice(), index)
+    fn set_field_by_index<T: 'static>(&self, data: &mut [u8], index: usize, val: T) {
+        self.check_type::<T>(index);
+        unsafe {
+            self.set_field_unchecked_by_index(data, index, val);
+        }
     }
 
     #[inline]
-    pub fn get_field_mut_by_index<T: 'static>(&mut self, index: usize) -> &mut T {
-        self.type_layout
-            .get_field_mut_by_index(self.data.as_mut_slice(), index)
+    fn try_set_field<T: 'static>(&self, data: &mut [u8], name: &str, val: T) -> Result<(), DynamicFieldError<T>> {
+        if let Some(index) = self.name_to_index.get(name) {
+            self.try_set_field_by_index(data, *index, val)
+        } else {
+            Err(DynamicFieldError::SetFieldNameNotFound { name: name.into(), value: val })
+        }
     }
 
     #[inline]
-    pub fn try_set_field<T: 'static>(&mut self, name: &str, val: T) -> Result<(), DynamicFieldError<T>> {
-        self.type_layout.try_set_field(&mut self.data, name, val)
+    fn try_set_field_by_index<T: 'static>(&self, data: &mut [u8], index: usize, val: T) -> Result<(), DynamicFieldError<T>> {
+        match self.try_check_type::<T>(index) {
+            Ok(()) => {
+                unsafe {
+                    self.set_field_unchecked_by_index(data, index, val);
+                }
+                Ok(())
+            }
+            Err(DynamicFieldError::GetInvalidTypeOfField { type_requested, actual_type }) => {
+                Err(DynamicFieldError::SetInvalidTypeOfField { value: val, type_requested, actual_type })
+            }
+            Err(_) => Err(DynamicFieldError::FieldSetIndexOutOfBounds { index, value: val }),
+        }
     }
 
     #[inline]
-    pub fn try_clone_field<T: 'static + Clone>(&self, name: &str) -> Result<T, DynamicFieldError<()>> {
-        self.type_layout.try_clone_field(&self.data, name)
+    fn check_type<T: 'static>(&self, index: usize) {
+        if !self.type_is::<T>(index) {
+            panic!(
+                "Invalid type, expected: {:?}, but found {:?}",
+                self.field_type_names[index],
+                std::any::type_name::<T>()
+            );
+        }
     }
 
     #[inline]
-    pub fn try_get_field_ref<T: 'static>(&self, name: &str) -> Result<&T, DynamicFieldError<()>> {
-        self.type_layout.try_get_field_ref(self.data.as_slice(), name)
+    fn type_is<T: 'static>(&self, index: usize) -> bool {
+        self.field_types[index] == TypeId::of::<T>()
     }
 
+    /// Borrows `buffer` as a view of this layout, panics if another layout initialised it.
     #[inline]
-    pub fn try_get_field_mut<T: 'static>(&mut self, name: &str) -> Result<&mut T, DynamicFieldError<()>> {
-        self.type_layout
-            .try_get_field_mut(self.data.as_mut_slice(), name)
+    pub fn buffer_ref<'a>(&self, buffer: &'a LayoutBuffer) -> DynamicStructRef<'a> {
+        self.try_buffer_ref(buffer).unwrap_or_else(|err| panic!("{}", err))
     }
 
+    /// Borrows `buffer` as a view of this layout, which offers the field accessors of `DynamicStruct`.
+    /// Fails if `buffer` was initialised by a different layout.
     #[inline]
-    pub fn try_set_field_by_index<T: 'static>(&mut self, val: T, index: usize) -> Result<(), DynamicFieldError<T>> {
-        self.type_layout
-            .try_set_field_by_index(&mut self.data, index, val)
+    pub fn try_buffer_ref<'a>(&self, buffer: &'a LayoutBuffer) -> Result<DynamicStructRef<'a>, DynamicFieldError<()>> {
+        self.try_check_buffer(buffer)?;
+        Ok(DynamicStructRef { type_layout: &buffer.type_layout, data: &buffer.data })
     }
 
     #[inline]
-    pub fn try_clone_field_by_index<T: 'static + Clone>(&self, index: usize) -> Result<T, DynamicFieldError<()>> {
-        self.type_layout.try_clone_field_by_index(&self.data, index)
+    pub fn buffer_mut<'a>(&self, buffer: &'a mut LayoutBuffer) -> DynamicStructMut<'a> {
+        self.try_buffer_mut(buffer).unwrap_or_else(|err| panic!("{}", err))
     }
 
     #[inline]
-    pub fn try_get_field_ref_by_index<T: 'static>(&self, index: usize) -> Result<&T, DynamicFieldError<()>> {
-        self.type_layout
-            .try_get_field_ref_by_index(self.data.as_slice(), index)
+    pub fn try_buffer_mut<'a>(&self, buffer: &'a mut LayoutBuffer) -> Result<DynamicStructMut<'a>, DynamicFieldError<()>> {
+        self.try_check_buffer(buffer)?;
+        Ok(DynamicStructMut { type_layout: &buffer.type_layout, data: &mut buffer.data })
     }
 
     #[inline]
-    pub fn try_get_field_mut_by_index<T: 'static>(&mut self, index: usize) -> Result<&mut T, DynamicFieldError<()>> {
-        self.type_layout
-            .try_get_field_mut_by_index(self.data.as_mut_slice(), index)
+    fn try_check_buffer(&self, buffer: &LayoutBuffer) -> Result<(), DynamicFieldError<()>> {
+        if buffer.layout_id() != self.id {
+            Err(DynamicFieldError::BufferLayoutMismatch {
+                layout: self.name.clone(),
+                found: buffer.type_layout.name.clone(),
+            })
+        } else {
+            Ok(())
+        }
     }
 
-}
+    /// Checks `index` is in bounds before looking at its type, so it never panics.
+    #[inline]
+    fn try_check_type<T: 'static>(&self, index: usize) -> Result<(), DynamicFieldError<()>> {
+        match self.field_types.get(index) {
+            Some(type_id) if *type_id == TypeId::of::<T>() => Ok(()),
+            Some(_) => Err(DynamicFieldError::GetInvalidTypeOfField {
+                type_requested: std::any::type_name::<T>().into(),
+                actual_type: self.field_type_names[index].into(),
+            }),
+            None => Err(DynamicFieldError::FieldGetIndexOutOfBounds { index }),
+        }
+    }
 
-#[derive(Debug, Clone)]
-pub struct StaticTypeLayout {
-    type_id: TypeId,
-    size: usize,
-    align: usize,
-    default: unsafe fn() -> Vec<u8>,
-    drop_fn: Option<fn(*const u8)>,
-    name: &'static str,
-}
+    #[inline]
+    fn clone_field<T: 'static + Clone>(&self, data: &[u8], name: &str) -> T {
+        let index = self.name_to_index[name];
+        self.clone_field_by_index(data, index)
+    }
 
-impl StaticTypeLayout {
-    pub fn of<T: Any + Default + DefaultBytes>() -> Self {
-        StaticTypeLayout {
-            type_id: TypeId::of::<T>(),
-            size: std::mem::size_of::<T>(),
-            align: std::mem::align_of::<T>(),
-            default: { T::default_bytes },
-            name: std::any::type_name::<T>(),
-            drop_fn: {
-                if std::mem::needs_drop::<T>() {
-                    let func = unsafe {
-                        std::mem::transmute::<unsafe fn(*mut T), fn(*const u8)>(drop_in_place::<T>)
-                    };
-                    Some(func)
-                } else {
-                    None
-                }
-            },
-        }
+    #[inline]
+    fn clone_field_by_index<T: 'static + Clone>(&self, data: &[u8], index: usize) -> T {
+        self.check_type::<T>(index);
+        unsafe { self.clone_field_unchecked_by_index(data, index) }
     }
-}
 
-pub trait DefaultBytes: Default {
-    /// # Safety
-    /// If this type is not `Copy` then remember to cast it back to its original type and drop it when you're done with it.
-    unsafe fn default_bytes() -> Vec<u8> {
-        let new = Self::default();
-        let new_bytes = unsafe {
-            std::slice::from_raw_parts(
-                &new as *const Self as *const u8,
-                std::mem::size_of::<Self>(),
-            )
-        };
-        let mut output = Vec::with_capacity(std::mem::size_of::<Self>());
-        for b in new_bytes {
-            output.push(*b);
-        }
-        if std::mem::needs_drop::<Self>() {
-            std::mem::forget(new);
+    #[inline]
+    fn try_clone_field<T: 'static + Clone>(&self, data: &[u8], name: &str) -> Result<T, DynamicFieldError<()>> {
+        if let Some(index) = self.name_to_index.get(name) {
+            self.try_clone_field_by_index(data, *index)
+        } else {
+            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
         }
-        output
     }
-}
 
-impl<T: Default> DefaultBytes for T {}
+    #[inline]
+    fn try_clone_field_by_index<T: 'static + Clone>(&self, data: &[u8], index: usize) -> Result<T, DynamicFieldError<()>> {
+        self.try_check_type::<T>(index)?;
+        Ok(unsafe { self.clone_field_unchecked_by_index(data, index) })
+    }
 
-/// # Safety
-/// Only a valid trait for sequential collections of bytes.
-pub unsafe trait VecToType {
+    #[inline]
+    fn get_field_ref<'a, T: 'static>(&self, data: &'a [u8], name: &str) -> &'a T {
+        let index = self.name_to_index[name];
+        self.get_field_ref_by_index(data, index)
+    }
+
+    #[inline]
+    fn get_field_ref_by_index<'a, T: 'static>(&self, data: &'a [u8], index: usize) -> &'a T {
+        self.check_type::<T>(index);
+        unsafe { self.get_field_ref_unchecked_by_index(data, index) }
+    }
+
+    #[inline]
+    fn try_get_field_ref<'a, T: 'static>(&self, data: &'a [u8], name: &str) -> Result<&'a T, DynamicFieldError<()>> {
+        if let Some(index) = self.name_to_index.get(name) {
+            self.try_get_field_ref_by_index(data, *index)
+        } else {
+            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
+        }
+    }
+
+    #[inline]
+    fn try_get_field_ref_by_index<'a, T: 'static>(&self, data: &'a [u8], index: usize) -> Result<&'a T, DynamicFieldError<()>> {
+        self.try_check_type::<T>(index)?;
+        Ok(unsafe { self.get_field_ref_unchecked_by_index(data, index) })
+    }
+
+    #[inline]
+    fn get_field_mut<'a, T: 'static>(&self, data: &'a mut [u8], name: &str) -> &'a mut T {
+        let index = self.name_to_index[name];
+        self.get_field_mut_by_index(data, index)
+    }
+
+    #[inline]
+    fn get_field_mut_by_index<'a, T: 'static>(&self, data: &'a mut [u8], index: usize) -> &'a mut T {
+        self.check_type::<T>(index);
+        unsafe { self.get_field_mut_unchecked_by_index(data, index) }
+    }
+
+    #[inline]
+    fn try_get_field_mut<'a, T: 'static>(&self, data: &'a mut [u8], name: &str) -> Result<&'a mut T, DynamicFieldError<()>> {
+        if let Some(index) = self.name_to_index.get(name) {
+            self.try_get_field_mut_by_index(data, *index)
+        } else {
+            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
+        }
+    }
+
+    #[inline]
+    fn try_get_field_mut_by_index<'a, T: 'static>(&self, data: &'a mut [u8], index: usize) -> Result<&'a mut T, DynamicFieldError<()>> {
+        self.try_check_type::<T>(index)?;
+        Ok(unsafe { self.get_field_mut_unchecked_by_index(data, index) })
+    }
+
+    #[inline]
+    /// # Safety
+    /// The field's type must match the generic type `T`, `data` must be at least `total_size` long and
+    /// aligned to `align`.
+    pub unsafe fn set_field_unchecked_by_index<T: 'static>(
+        &self,
+        data: &mut [u8],
+        index: usize,
+        val: T,
+    ) {
+        let mut ptr = data.as_mut_ptr();
+        ptr = ptr.add(self.field_offsets[index]);
+        let ptr = ptr.cast::<T>();
+        *ptr = val;
+    }
+
+    #[inline]
+    /// # Safety
+    /// The field's type must match the generic type `T`, `data` must be at least `total_size` long and
+    /// aligned to `align`.
+    pub unsafe fn clone_field_unchecked_by_index<T: 'static + Clone>(
+        &self,
+        data: &[u8],
+        index: usize,
+    ) -> T {
+        let offset = self.field_offsets[index];
+        let data = data.as_ptr().add(offset);
+        (*(std::mem::transmute::<*const u8, *const T>(data))).clone()
+    }
+
+    #[inline]
+    /// # Safety
+    /// The field's type must match the generic type `T`, `data` must be at least `total_size` long and
+    /// aligned to `align`.
+    pub unsafe fn get_field_ref_unchecked_by_index<'a, T: 'static>(
+        &self,
+        data: &'a [u8],
+        index: usize,
+    ) -> &'a T {
+        let offset = self.field_offsets[index];
+        let data = data.as_ptr().add(offset);
+        &*std::mem::transmute::<*const u8, *const T>(data)
+    }
+
+    #[inline]
+    /// # Safety
+    /// The field's type must match the generic type `T`, `data` must be at least `total_size` long and
+    /// aligned to `align`.
+    pub unsafe fn get_field_mut_unchecked_by_index<'a, T: 'static>(
+        &self,
+        data: &'a mut [u8],
+        index: usize,
+    ) -> &'a mut T {
+        let offset = self.field_offsets[index];
+        let data = data.as_mut_ptr().add(offset);
+        &mut *std::mem::transmute::<*mut u8, *mut T>(data)
+    }
+
+    #[inline]
+    fn get_struct_ref<'a>(&'a self, data: &'a [u8], name: &str) -> DynamicStructRef<'a> {
+        let index = self.name_to_index[name];
+        self.get_struct_ref_by_index(data, index)
+    }
+
+    #[inline]
+    fn get_struct_ref_by_index<'a>(&'a self, data: &'a [u8], index: usize) -> DynamicStructRef<'a> {
+        self.try_get_struct_ref_by_index(data, index)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[inline]
+    fn try_get_struct_ref<'a>(&'a self, data: &'a [u8], name: &str) -> Result<DynamicStructRef<'a>, DynamicFieldError<()>> {
+        if let Some(index) = self.name_to_index.get(name) {
+            self.try_get_struct_ref_by_index(data, *index)
+        } else {
+            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
+        }
+    }
+
+    #[inline]
+    fn try_get_struct_ref_by_index<'a>(&'a self, data: &'a [u8], index: usize) -> Result<DynamicStructRef<'a>, DynamicFieldError<()>> {
+        match self.field_kinds.get(index) {
+            Some(FieldKind::Dynamic(layout)) => {
+                let offset = self.field_offsets[index];
+                Ok(DynamicStructRef { type_layout: layout, data: &data[offset..offset + layout.total_size] })
+            }
+            Some(kind) => Err(DynamicFieldError::GetInvalidTypeOfField {
+                type_requested: std::any::type_name::<DynamicStruct>().into(),
+                actual_type: kind.type_name().into(),
+            }),
+            None => Err(DynamicFieldError::FieldGetIndexOutOfBounds { index }),
+        }
+    }
+
+    #[inline]
+    fn get_struct_mut<'a>(&'a self, data: &'a mut [u8], name: &str) -> DynamicStructMut<'a> {
+        let index = self.name_to_index[name];
+        self.get_struct_mut_by_index(data, index)
+    }
+
+    #[inline]
+    fn get_struct_mut_by_index<'a>(&'a self, data: &'a mut [u8], index: usize) -> DynamicStructMut<'a> {
+        self.try_get_struct_mut_by_index(data, index)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[inline]
+    fn try_get_struct_mut<'a>(&'a self, data: &'a mut [u8], name: &str) -> Result<DynamicStructMut<'a>, DynamicFieldError<()>> {
+        if let Some(index) = self.name_to_index.get(name) {
+            self.try_get_struct_mut_by_index(data, *index)
+        } else {
+            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
+        }
+    }
+
+    #[inline]
+    fn try_get_struct_mut_by_index<'a>(&'a self, data: &'a mut [u8], index: usize) -> Result<DynamicStructMut<'a>, DynamicFieldError<()>> {
+        match self.field_kinds.get(index) {
+            Some(FieldKind::Dynamic(layout)) => {
+                let offset = self.field_offsets[index];
+                Ok(DynamicStructMut { type_layout: layout, data: &mut data[offset..offset + layout.total_size] })
+            }
+            Some(kind) => Err(DynamicFieldError::GetInvalidTypeOfField {
+                type_requested: std::any::type_name::<DynamicStruct>().into(),
+                actual_type: kind.type_name().into(),
+            }),
+            None => Err(DynamicFieldError::FieldGetIndexOutOfBounds { index }),
+        }
+    }
+
+    /// Borrows a static field without knowing its type, nested dynamic structs are not `Any`.
+    #[inline]
+    fn try_get_field_any_by_index<'a>(&self, data: &'a [u8], index: usize) -> Result<&'a dyn Any, DynamicFieldError<()>> {
+        match self.field_kinds.get(index) {
+            Some(FieldKind::Static(layout)) => {
+                let offset = self.field_offsets[index];
+                Ok(unsafe { &*(layout.as_any_fn)(data.as_ptr().add(offset)) })
+            }
+            Some(FieldKind::Dynamic(layout)) => Err(DynamicFieldError::GetInvalidTypeOfField {
+                type_requested: std::any::type_name::<dyn Any>().into(),
+                actual_type: layout.name.clone(),
+            }),
+            Some(kind) => Err(DynamicFieldError::GetInvalidTypeOfField {
+                type_requested: std::any::type_name::<dyn Any>().into(),
+                actual_type: kind.type_name().into(),
+            }),
+            None => Err(DynamicFieldError::FieldGetIndexOutOfBounds { index }),
+        }
+    }
+
+    #[inline]
+    fn try_get_field_any_mut_by_index<'a>(&self, data: &'a mut [u8], index: usize) -> Result<&'a mut dyn Any, DynamicFieldError<()>> {
+        match self.field_kinds.get(index) {
+            Some(FieldKind::Static(layout)) => {
+                let offset = self.field_offsets[index];
+                Ok(unsafe { &mut *(layout.as_any_mut_fn)(data.as_mut_ptr().add(offset)) })
+            }
+            Some(FieldKind::Dynamic(layout)) => Err(DynamicFieldError::GetInvalidTypeOfField {
+                type_requested: std::any::type_name::<dyn Any>().into(),
+                actual_type: layout.name.clone(),
+            }),
+            Some(kind) => Err(DynamicFieldError::GetInvalidTypeOfField {
+                type_requested: std::any::type_name::<dyn Any>().into(),
+                actual_type: kind.type_name().into(),
+            }),
+            None => Err(DynamicFieldError::FieldGetIndexOutOfBounds { index }),
+        }
+    }
+
+    /// Finds the offset and length of an array field of `T`s.
+    #[inline]
+    fn try_check_array<T: 'static>(&self, index: usize) -> Result<(usize, usize), DynamicFieldError<()>> {
+        match self.field_kinds.get(index) {
+            Some(FieldKind::Array { element, len }) if FieldKind::type_id(element) == TypeId::of::<T>() => {
+                Ok((self.field_offsets[index], *len))
+            }
+            Some(FieldKind::Array { element, .. }) => Err(DynamicFieldError::GetInvalidTypeOfField {
+                type_requested: std::any::type_name::<T>().into(),
+                actual_type: element.type_name().into(),
+            }),
+            Some(kind) => Err(DynamicFieldError::GetInvalidTypeOfField {
+                type_requested: std::any::type_name::<[T]>().into(),
+                actual_type: kind.type_name().into(),
+            }),
+            None => Err(DynamicFieldError::FieldGetIndexOutOfBounds { index }),
+        }
+    }
+
+    #[inline]
+    fn get_array_ref<'a, T: 'static>(&self, data: &'a [u8], name: &str) -> &'a [T] {
+        let index = self.name_to_index[name];
+        self.get_array_ref_by_index(data, index)
+    }
+
+    #[inline]
+    fn get_array_ref_by_index<'a, T: 'static>(&self, data: &'a [u8], index: usize) -> &'a [T] {
+        self.try_get_array_ref_by_index(data, index)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[inline]
+    fn try_get_array_ref<'a, T: 'static>(&self, data: &'a [u8], name: &str) -> Result<&'a [T], DynamicFieldError<()>> {
+        if let Some(index) = self.name_to_index.get(name) {
+            self.try_get_array_ref_by_index(data, *index)
+        } else {
+            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
+        }
+    }
+
+    #[inline]
+    fn try_get_array_ref_by_index<'a, T: 'static>(&self, data: &'a [u8], index: usize) -> Result<&'a [T], DynamicFieldError<()>> {
+        let (offset, len) = self.try_check_array::<T>(index)?;
+        Ok(unsafe { std::slice::from_raw_parts(data.as_ptr().add(offset).cast::<T>(), len) })
+    }
+
+    #[inline]
+    fn get_array_mut<'a, T: 'static>(&self, data: &'a mut [u8], name: &str) -> &'a mut [T] {
+        let index = self.name_to_index[name];
+        self.get_array_mut_by_index(data, index)
+    }
+
+    #[inline]
+    fn get_array_mut_by_index<'a, T: 'static>(&self, data: &'a mut [u8], index: usize) -> &'a mut [T] {
+        self.try_get_array_mut_by_index(data, index)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[inline]
+    fn try_get_array_mut<'a, T: 'static>(&self, data: &'a mut [u8], name: &str) -> Result<&'a mut [T], DynamicFieldError<()>> {
+        if let Some(index) = self.name_to_index.get(name) {
+            self.try_get_array_mut_by_index(data, *index)
+        } else {
+            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
+        }
+    }
+
+    #[inline]
+    fn try_get_array_mut_by_index<'a, T: 'static>(&self, data: &'a mut [u8], index: usize) -> Result<&'a mut [T], DynamicFieldError<()>> {
+        let (offset, len) = self.try_check_array::<T>(index)?;
+        Ok(unsafe { std::slice::from_raw_parts_mut(data.as_mut_ptr().add(offset).cast::<T>(), len) })
+    }
+
+    /// Finds the element layout, offset and length of an array field of nested dynamic structs.
+    #[inline]
+    fn try_check_struct_array(&self, index: usize) -> Result<(&Arc<DynamicTypeLayout>, usize, usize), DynamicFieldError<()>> {
+        match self.field_kinds.get(index) {
+            Some(FieldKind::Array { element, len }) => match &**element {
+                FieldKind::Dynamic(layout) => Ok((layout, self.field_offsets[index], *len)),
+                element => Err(DynamicFieldError::GetInvalidTypeOfField {
+                    type_requested: std::any::type_name::<DynamicStruct>().into(),
+                    actual_type: element.type_name().into(),
+                }),
+            },
+            Some(kind) => Err(DynamicFieldError::GetInvalidTypeOfField {
+                type_requested: std::any::type_name::<[DynamicStruct]>().into(),
+                actual_type: kind.type_name().into(),
+            }),
+            None => Err(DynamicFieldError::FieldGetIndexOutOfBounds { index }),
+        }
+    }
+
+    #[inline]
+    fn get_struct_array_ref<'a>(&'a self, data: &'a [u8], name: &str) -> DynamicStructArrayRef<'a> {
+        let index = self.name_to_index[name];
+        self.get_struct_array_ref_by_index(data, index)
+    }
+
+    #[inline]
+    fn get_struct_array_ref_by_index<'a>(&'a self, data: &'a [u8], index: usize) -> DynamicStructArrayRef<'a> {
+        self.try_get_struct_array_ref_by_index(data, index)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[inline]
+    fn try_get_struct_array_ref<'a>(&'a self, data: &'a [u8], name: &str) -> Result<DynamicStructArrayRef<'a>, DynamicFieldError<()>> {
+        if let Some(index) = self.name_to_index.get(name) {
+            self.try_get_struct_array_ref_by_index(data, *index)
+        } else {
+            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
+        }
+    }
+
+    #[inline]
+    fn try_get_struct_array_ref_by_index<'a>(&'a self, data: &'a [u8], index: usize) -> Result<DynamicStructArrayRef<'a>, DynamicFieldError<()>> {
+        let (element, offset, len) = self.try_check_struct_array(index)?;
+        Ok(DynamicStructArrayRef { element, data: &data[offset..offset + element.total_size * len], len })
+    }
+
+    #[inline]
+    fn get_struct_array_mut<'a>(&'a self, data: &'a mut [u8], name: &str) -> DynamicStructArrayMut<'a> {
+        let index = self.name_to_index[name];
+        self.get_struct_array_mut_by_index(data, index)
+    }
+
+    #[inline]
+    fn get_struct_array_mut_by_index<'a>(&'a self, data: &'a mut [u8], index: usize) -> DynamicStructArrayMut<'a> {
+        self.try_get_struct_array_mut_by_index(data, index)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[inline]
+    fn try_get_struct_array_mut<'a>(&'a self, data: &'a mut [u8], name: &str) -> Result<DynamicStructArrayMut<'a>, DynamicFieldError<()>> {
+        if let Some(index) = self.name_to_index.get(name) {
+            self.try_get_struct_array_mut_by_index(data, *index)
+        } else {
+            Err(DynamicFieldError::GetFieldNameNotFound { name: name.into() })
+        }
+    }
+
+    #[inline]
+    fn try_get_struct_array_mut_by_index<'a>(&'a self, data: &'a mut [u8], index: usize) -> Result<DynamicStructArrayMut<'a>, DynamicFieldError<()>> {
+        let (element, offset, len) = self.try_check_struct_array(index)?;
+        Ok(DynamicStructArrayMut { element, data: &mut data[offset..offset + element.total_size * len], len })
+    }
+}
+
+/// Storage for a value of one layout, for callers that manage buffers themselves such as pools.
+///
+/// It records the layout that initialised it, and that layout's `buffer_ref`/`buffer_mut` reject
+/// buffers of any other layout, so a buffer is never read as a layout it doesn't hold.
+pub struct LayoutBuffer {
+    type_layout: Arc<DynamicTypeLayout>,
+    /// Always initialised by `type_layout`.
+    data: AlignedBytes,
+}
+
+impl Drop for LayoutBuffer {
+    fn drop(&mut self) {
+        unsafe { self.type_layout.drop_data(self.data.as_ptr()) };
+    }
+}
+
+impl LayoutBuffer {
+    /// Allocates a buffer holding the default value of `type_layout`.
+    pub fn new(type_layout: Arc<DynamicTypeLayout>) -> Self {
+        let mut data = AlignedBytes::zeroed(type_layout.total_size, type_layout.align);
+        unsafe { type_layout.init_data(data.as_mut_ptr()) };
+
+        Self { type_layout, data }
+    }
+
+    /// The id of the layout that initialised this buffer, see `DynamicTypeLayout::id`.
+    #[inline]
+    pub fn layout_id(&self) -> u64 {
+        self.type_layout.id
+    }
+
+    /// Drops the held value and puts back the layout's defaults, so the buffer can be reused.
+    pub fn reset(&mut self) {
+        unsafe {
+            self.type_layout.drop_data(self.data.as_ptr());
+            self.type_layout.init_data(self.data.as_mut_ptr());
+        }
+    }
+}
+
+pub struct DynamicStruct {
+    type_layout: Arc<DynamicTypeLayout>,
+    /// Always initialised by `type_layout`, as the layout's field accessors require.
+    data: AlignedBytes,
+}
+
+impl Drop for DynamicStruct {
+    fn drop(&mut self) {
+        //Data has been taken or it is a 0 sized type.
+        if self.data.is_empty() {
+            return;
+        }
+        unsafe { self.type_layout.drop_data(self.data.as_ptr()) };
+    }
+}
+
+impl fmt::Debug for DynamicStruct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_ref(), f)
+    }
+}
+
+/// Formats a single field through its captured `DebugFn`, or as `<type_name, N bytes>` without one.
+struct FieldDebug<'a> {
+    ptr: *const u8,
+    kind: &'a FieldKind,
+}
+
+impl fmt::Debug for FieldDebug<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            FieldKind::Static(layout) => match layout.debug_fn {
+                Some(debug) => unsafe { debug(self.ptr, f) },
+                None => write!(f, "<{}, {} bytes>", layout.name, layout.size),
+            },
+            FieldKind::Dynamic(layout) => unsafe { layout.fmt_data(self.ptr, f) },
+            FieldKind::Array { element, len } => f
+                .debug_list()
+                .entries((0..*len).map(|index| FieldDebug {
+                    ptr: unsafe { self.ptr.add(index * element.size()) },
+                    kind: element,
+                }))
+                .finish(),
+        }
+    }
+}
+
+impl Clone for DynamicStruct {
+    /// Panics if any field is not cloneable, see `DynamicStruct::try_clone`.
+    fn clone(&self) -> Self {
+        self.try_clone().unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl DynamicStruct {
+    pub fn new(type_layout: Arc<DynamicTypeLayout>) -> Self {
+        let mut data = AlignedBytes::zeroed(type_layout.total_size, type_layout.align);
+        unsafe { type_layout.init_data(data.as_mut_ptr()) };
+
+        Self { data, type_layout }
+    }
+
+    /// Clones every field into a new instance, fails if any field's layout was created without
+    /// `StaticTypeLayout::with_clone`.
+    #[inline]
+    pub fn try_clone(&self) -> Result<Self, DynamicFieldError<()>> {
+        self.as_ref().try_clone()
+    }
+
+    /// Compares field by field, structs of structurally different layouts are never equal.
+    /// Fails if any field's layout was created without `StaticTypeLayout::with_eq`.
+    #[inline]
+    pub fn try_eq(&self, other: &Self) -> Result<bool, DynamicFieldError<()>> {
+        self.as_ref().try_eq(&other.as_ref())
+    }
+
+    /// Hashes the layout name and every field, consistent with `DynamicStruct::try_eq`.
+    /// Fails if any field's layout was created without `StaticTypeLayout::with_hash`.
+    #[inline]
+    pub fn try_hash<H: Hasher>(&self, state: &mut H) -> Result<(), DynamicFieldError<()>> {
+        self.as_ref().try_hash(state)
+    }
+
+    #[inline]
+    pub fn layout(&self) -> &Arc<DynamicTypeLayout> {
+        &self.type_layout
+    }
+
+    /// Borrows the whole struct as a view, the same type nested struct accessors return.
+    #[inline]
+    pub fn as_ref(&self) -> DynamicStructRef<'_> {
+        DynamicStructRef { type_layout: &self.type_layout, data: &self.data }
+    }
+
+    /// Walks the struct and every field, see `DynamicStructRef::visit`.
+    #[inline]
+    pub fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        self.as_ref().visit(visitor);
+    }
+
+    /// Iterates every field in declaration order, see `DynamicStructRef::fields`.
+    #[inline]
+    pub fn fields(&self) -> impl ExactSizeIterator<Item = DynamicField<'_>> {
+        self.as_ref().fields()
+    }
+
+    #[inline]
+    pub fn fields_mut(&mut self) -> impl ExactSizeIterator<Item = DynamicFieldMut<'_>> {
+        self.as_mut().into_fields_mut()
+    }
+
+    /// Borrows the struct as a view of its base `name`, see `DynamicStructRef::try_as_base`.
+    #[inline]
+    pub fn as_base(&self, name: &str) -> DynamicStructRef<'_> {
+        self.as_ref().as_base(name)
+    }
+
+    #[inline]
+    pub fn try_as_base(&self, name: &str) -> Result<DynamicStructRef<'_>, DynamicFieldError<()>> {
+        self.as_ref().try_as_base(name)
+    }
+
+    #[inline]
+    pub fn as_base_mut(&mut self, name: &str) -> DynamicStructMut<'_> {
+        self.try_as_base_mut(name).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[inline]
+    pub fn try_as_base_mut(&mut self, name: &str) -> Result<DynamicStructMut<'_>, DynamicFieldError<()>> {
+        self.as_mut().try_into_base(name)
+    }
+
+    #[inline]
+    pub fn as_mut(&mut self) -> DynamicStructMut<'_> {
+        DynamicStructMut { type_layout: &self.type_layout, data: &mut self.data }
+    }
+
+    pub fn size_of(&self) -> usize {
+        self.type_layout.total_size
+    }
+
+    /// # Safety
+    /// Only call this if the type is identical to the dynamic types byte layout.
+    ///
+    /// Panics if the layout was not built with `LayoutStrategy::ReprC`.
+    #[inline]
+    pub unsafe fn cast<T>(mut self) -> T {
+        if !self.type_layout.is_repr_c() {
+            panic!("Cannot cast {}, its fields have been reordered by an optimized layout.", self.type_layout.name);
+        }
+        if self.data.len() != std::mem::size_of::<T>() {
+            panic!("Invalid sized type, data is {} bytes large and type attempted to cast to is {} bytes large.", self.data.len(), std::mem::size_of::<T>());
+        }
+        let output = self.data.as_ptr().cast::<T>().read_unaligned();
+        self.data.clear();
+        output
+    }
+
+    #[inline]
+    pub fn set_field<T: 'static>(&mut self, name: &str, val: T) {
+        self.type_layout.set_field(&mut self.data, name, val);
+    }
+
+    #[inline]
+    pub fn clone_field<T: 'static + Clone>(&self, name: &str) -> T {
+        self.type_layout.clone_field(&self.data, name)
+    }
+
+    #[inline]
+    pub fn get_field_ref<T: 'static>(&self, name: &str) -> &T {
+        self.type_layout.get_field_ref(&self.data, name)
+    }
+
+    #[inline]
+    pub fn get_field_mut<T: 'static>(&mut self, name: &str) -> &mut T {
+        self.type_layout.get_field_mut(&mut self.data, name)
+    }
+
+    #[inline]
+    pub fn set_field_by_index<T: 'static>(&mut self, val: T, index: usize) {
+        self.type_layout.set_field_by_index(&mut self.data, index, val);
+    }
+
+    #[inline]
+    pub fn clone_field_by_index<T: 'static + Clone>(&self, index: usize) -> T {
+        self.type_layout.clone_field_by_index(&self.data, index)
+    }
+
+    #[inline]
+    pub fn get_field_ref_by_index<T: 'static>(&self, index: usize) -> &T {
+        self.type_layout.get_field_ref_by_index(&self.data, index)
+    }
+
+    #[inline]
+    pub fn get_field_mut_by_index<T: 'static>(&mut self, index: usize) -> &mut T {
+        self.type_layout.get_field_mut_by_index(&mut self.data, index)
+    }
+
+    #[inline]
+    pub fn try_set_field<T: 'static>(&mut self, name: &str, val: T) -> Result<(), DynamicFieldError<T>> {
+        self.type_layout.try_set_field(&mut self.data, name, val)
+    }
+
+    #[inline]
+    pub fn try_clone_field<T: 'static + Clone>(&self, name: &str) -> Result<T, DynamicFieldError<()>> {
+        self.type_layout.try_clone_field(&self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_field_ref<T: 'static>(&self, name: &str) -> Result<&T, DynamicFieldError<()>> {
+        self.type_layout.try_get_field_ref(&self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_field_mut<T: 'static>(&mut self, name: &str) -> Result<&mut T, DynamicFieldError<()>> {
+        self.type_layout.try_get_field_mut(&mut self.data, name)
+    }
+
+    #[inline]
+    pub fn try_set_field_by_index<T: 'static>(&mut self, val: T, index: usize) -> Result<(), DynamicFieldError<T>> {
+        self.type_layout.try_set_field_by_index(&mut self.data, index, val)
+    }
+
+    #[inline]
+    pub fn try_clone_field_by_index<T: 'static + Clone>(&self, index: usize) -> Result<T, DynamicFieldError<()>> {
+        self.type_layout.try_clone_field_by_index(&self.data, index)
+    }
+
+    #[inline]
+    pub fn try_get_field_ref_by_index<T: 'static>(&self, index: usize) -> Result<&T, DynamicFieldError<()>> {
+        self.type_layout.try_get_field_ref_by_index(&self.data, index)
+    }
+
+    #[inline]
+    pub fn try_get_field_mut_by_index<T: 'static>(&mut self, index: usize) -> Result<&mut T, DynamicFieldError<()>> {
+        self.type_layout.try_get_field_mut_by_index(&mut self.data, index)
+    }
+
+    /// Panics if `handle` was created from a different layout.
+    #[inline]
+    pub fn get<T: 'static>(&self, handle: &FieldHandle<T>) -> &T {
+        self.type_layout.check_handle(handle);
+        unsafe { &*self.data.as_ptr().add(handle.offset).cast::<T>() }
+    }
+
+    /// Panics if `handle` was created from a different layout.
+    #[inline]
+    pub fn get_mut<T: 'static>(&mut self, handle: &FieldHandle<T>) -> &mut T {
+        self.type_layout.check_handle(handle);
+        unsafe { &mut *self.data.as_mut_ptr().add(handle.offset).cast::<T>() }
+    }
+
+    /// Panics if `handle` was created from a different layout.
+    #[inline]
+    pub fn set<T: 'static>(&mut self, handle: &FieldHandle<T>, val: T) {
+        *self.get_mut(handle) = val;
+    }
+
+    #[inline]
+    pub fn try_get<T: 'static>(&self, handle: &FieldHandle<T>) -> Result<&T, DynamicFieldError<()>> {
+        self.type_layout.try_check_handle(handle)?;
+        Ok(unsafe { &*self.data.as_ptr().add(handle.offset).cast::<T>() })
+    }
+
+    #[inline]
+    pub fn try_get_mut<T: 'static>(&mut self, handle: &FieldHandle<T>) -> Result<&mut T, DynamicFieldError<()>> {
+        self.type_layout.try_check_handle(handle)?;
+        Ok(unsafe { &mut *self.data.as_mut_ptr().add(handle.offset).cast::<T>() })
+    }
+
+    #[inline]
+    pub fn get_struct_ref(&self, name: &str) -> DynamicStructRef<'_> {
+        self.type_layout.get_struct_ref(&self.data, name)
+    }
+
+    #[inline]
+    pub fn get_struct_ref_by_index(&self, index: usize) -> DynamicStructRef<'_> {
+        self.type_layout.get_struct_ref_by_index(&self.data, index)
+    }
+
+    #[inline]
+    pub fn try_get_struct_ref(&self, name: &str) -> Result<DynamicStructRef<'_>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_ref(&self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_struct_ref_by_index(&self, index: usize) -> Result<DynamicStructRef<'_>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_ref_by_index(&self.data, index)
+    }
+
+    #[inline]
+    pub fn get_struct_mut(&mut self, name: &str) -> DynamicStructMut<'_> {
+        self.type_layout.get_struct_mut(&mut self.data, name)
+    }
+
+    #[inline]
+    pub fn get_struct_mut_by_index(&mut self, index: usize) -> DynamicStructMut<'_> {
+        self.type_layout.get_struct_mut_by_index(&mut self.data, index)
+    }
+
+    #[inline]
+    pub fn try_get_struct_mut(&mut self, name: &str) -> Result<DynamicStructMut<'_>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_mut(&mut self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_struct_mut_by_index(&mut self, index: usize) -> Result<DynamicStructMut<'_>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_mut_by_index(&mut self.data, index)
+    }
+
+    #[inline]
+    pub fn get_array_ref<T: 'static>(&self, name: &str) -> &[T] {
+        self.type_layout.get_array_ref(&self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_array_ref<T: 'static>(&self, name: &str) -> Result<&[T], DynamicFieldError<()>> {
+        self.type_layout.try_get_array_ref(&self.data, name)
+    }
+
+    #[inline]
+    pub fn get_array_mut<T: 'static>(&mut self, name: &str) -> &mut [T] {
+        self.type_layout.get_array_mut(&mut self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_array_mut<T: 'static>(&mut self, name: &str) -> Result<&mut [T], DynamicFieldError<()>> {
+        self.type_layout.try_get_array_mut(&mut self.data, name)
+    }
+
+    #[inline]
+    pub fn get_struct_array_ref(&self, name: &str) -> DynamicStructArrayRef<'_> {
+        self.type_layout.get_struct_array_ref(&self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_struct_array_ref(&self, name: &str) -> Result<DynamicStructArrayRef<'_>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_array_ref(&self.data, name)
+    }
+
+    #[inline]
+    pub fn get_struct_array_mut(&mut self, name: &str) -> DynamicStructArrayMut<'_> {
+        self.type_layout.get_struct_array_mut(&mut self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_struct_array_mut(&mut self, name: &str) -> Result<DynamicStructArrayMut<'_>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_array_mut(&mut self.data, name)
+    }
+}
+
+/// A field resolved ahead of time by `DynamicTypeLayout::field_handle`.
+///
+/// Holds the field's offset, so access is a pointer add after comparing layout ids, with no name
+/// lookup or `TypeId` check.
+pub struct FieldHandle<T> {
+    layout_id: u64,
+    index: usize,
+    offset: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for FieldHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for FieldHandle<T> {}
+
+impl<T> fmt::Debug for FieldHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldHandle")
+            .field("layout_id", &self.layout_id)
+            .field("index", &self.index)
+            .field("offset", &self.offset)
+            .field("type", &std::any::type_name::<T>())
+            .finish()
+    }
+}
+
+impl<T> FieldHandle<T> {
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    pub fn layout_id(&self) -> u64 {
+        self.layout_id
+    }
+}
+
+/// A `DynamicStruct` whose every field can be compared and hashed, so it can be a `HashSet` or
+/// `HashMap` key. Equality is total since `StaticTypeLayout::with_hash` requires `Eq`.
+#[derive(Debug)]
+pub struct HashableStruct(DynamicStruct);
+
+impl HashableStruct {
+    /// Fails if any field's layout was created without `StaticTypeLayout::with_eq` or `with_hash`.
+    pub fn new(value: DynamicStruct) -> Result<Self, DynamicFieldError<()>> {
+        let layout = value.layout();
+        if let Some((name, type_name)) = layout.find_field_without(|layout| layout.eq_fn.is_some()) {
+            return Err(DynamicFieldError::FieldNotComparable { name, type_name: type_name.into() });
+        }
+        if let Some((name, type_name)) = layout.find_field_without(|layout| layout.hash_fn.is_some()) {
+            return Err(DynamicFieldError::FieldNotHashable { name, type_name: type_name.into() });
+        }
+        Ok(Self(value))
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> DynamicStruct {
+        self.0
+    }
+}
+
+impl Deref for HashableStruct {
+    type Target = DynamicStruct;
+
+    #[inline]
+    fn deref(&self) -> &DynamicStruct {
+        &self.0
+    }
+}
+
+impl PartialEq for HashableStruct {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, b) = (&self.0.type_layout, &other.0.type_layout);
+        a.is_structurally_equal(b) && unsafe { a.eq_data(self.0.data.as_ptr(), b, other.0.data.as_ptr()) }
+    }
+}
+
+impl Eq for HashableStruct {}
+
+impl Hash for HashableStruct {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        unsafe { self.0.type_layout.hash_data(self.0.data.as_ptr(), state) };
+    }
+}
+
+/// A borrowed view of a dynamic struct, either a whole `DynamicStruct` or one nested inside another.
+#[derive(Clone, Copy)]
+pub struct DynamicStructRef<'a> {
+    type_layout: &'a Arc<DynamicTypeLayout>,
+    /// Always initialised by `type_layout`, as the layout's field accessors require.
+    data: &'a [u8],
+}
+
+impl<'a> DynamicStructRef<'a> {
+    /// Views the struct as its base `name`, whose fields are a prefix of this struct's storage.
+    /// Viewing a struct as its own layout is allowed too.
+    pub fn try_as_base(&self, name: &str) -> Result<DynamicStructRef<'a>, DynamicFieldError<()>> {
+        if self.type_layout.name == name {
+            return Ok(*self);
+        }
+        match self.type_layout.base_layout(name) {
+            Some(base) => Ok(DynamicStructRef { type_layout: base, data: &self.data[..base.total_size] }),
+            None => Err(DynamicFieldError::NotDerivedFrom {
+                layout: self.type_layout.name.clone(),
+                base: name.into(),
+            }),
+        }
+    }
+
+    pub fn as_base(&self, name: &str) -> DynamicStructRef<'a> {
+        self.try_as_base(name).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Walks the struct and every field, see `DynamicVisitor` for the order of the callbacks.
+    /// Fields whose layout lacks `StaticTypeLayout::with_visit` are reported as opaque.
+    pub fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        visitor.enter_struct(*self);
+        let layout = self.type_layout;
+        for (index, kind) in layout.field_kinds.iter().enumerate() {
+            let offset = layout.field_offsets[index];
+            visitor.enter_field(&layout.field_names[index], index);
+            unsafe { kind.visit_data(&self.data[offset..offset + kind.size()], visitor) };
+            visitor.exit_field();
+        }
+        visitor.exit_struct();
+    }
+
+    /// Iterates every field in declaration order, base fields first.
+    pub fn fields(&self) -> impl ExactSizeIterator<Item = DynamicField<'a>> {
+        let layout: &'a DynamicTypeLayout = self.type_layout;
+        let data = self.data;
+        (0..layout.field_kinds.len()).map(move |index| {
+            let kind = &layout.field_kinds[index];
+            let offset = layout.field_offsets[index];
+            DynamicField {
+                name: &layout.field_names[index],
+                index,
+                type_name: layout.field_type_names[index],
+                type_id: layout.field_types[index],
+                value: kind.value_ref(&data[offset..offset + kind.size()]),
+            }
+        })
+    }
+}
+
+impl fmt::Debug for DynamicStructRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        unsafe { self.type_layout.fmt_data(self.data.as_ptr(), f) }
+    }
+}
+
+impl<'a> DynamicStructRef<'a> {
+    #[inline]
+    pub fn layout(&self) -> &'a Arc<DynamicTypeLayout> {
+        self.type_layout
+    }
+
+    /// Copies the viewed struct into a new `DynamicStruct`, see `DynamicStruct::try_clone`.
+    pub fn try_clone(&self) -> Result<DynamicStruct, DynamicFieldError<()>> {
+        self.clone_cloneable()
+            .map_err(|(name, type_name)| DynamicFieldError::FieldNotCloneable { name, type_name: type_name.into() })
+    }
+
+    /// Clones the struct, or names the first field without a clone function and its type.
+    fn clone_cloneable(&self) -> Result<DynamicStruct, (String, &'static str)> {
+        let layout = self.type_layout;
+        if let Some(missing) = layout.find_field_without(|layout| layout.clone_fn.is_some()) {
+            return Err(missing);
+        }
+
+        let mut data = AlignedBytes::zeroed(layout.total_size, layout.align);
+        unsafe { layout.clone_data(self.data.as_ptr(), data.as_mut_ptr()) };
+
+        Ok(DynamicStruct { data, type_layout: layout.clone() })
+    }
+
+    /// See `DynamicStruct::try_eq`.
+    pub fn try_eq(&self, other: &DynamicStructRef<'_>) -> Result<bool, DynamicFieldError<()>> {
+        let layout = self.type_layout;
+        if let Some((name, type_name)) = layout.find_field_without(|layout| layout.eq_fn.is_some()) {
+            return Err(DynamicFieldError::FieldNotComparable { name, type_name: type_name.into() });
+        }
+        if !layout.is_structurally_equal(other.type_layout) {
+            return Ok(false);
+        }
+
+        Ok(unsafe { layout.eq_data(self.data.as_ptr(), other.type_layout, other.data.as_ptr()) })
+    }
+
+    /// See `DynamicStruct::try_hash`.
+    pub fn try_hash<H: Hasher>(&self, state: &mut H) -> Result<(), DynamicFieldError<()>> {
+        let layout = self.type_layout;
+        if let Some((name, type_name)) = layout.find_field_without(|layout| layout.hash_fn.is_some()) {
+            return Err(DynamicFieldError::FieldNotHashable { name, type_name: type_name.into() });
+        }
+
+        unsafe { layout.hash_data(self.data.as_ptr(), state) };
+        Ok(())
+    }
+
+    #[inline]
+    pub fn clone_field<T: 'static + Clone>(&self, name: &str) -> T {
+        self.type_layout.clone_field(self.data, name)
+    }
+
+    /// Panics if `handle` was created from a different layout.
+    #[inline]
+    pub fn get<T: 'static>(&self, handle: &FieldHandle<T>) -> &'a T {
+        self.type_layout.check_handle(handle);
+        unsafe { &*self.data.as_ptr().add(handle.offset).cast::<T>() }
+    }
+
+    #[inline]
+    pub fn get_field_ref<T: 'static>(&self, name: &str) -> &'a T {
+        self.type_layout.get_field_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn get_field_ref_by_index<T: 'static>(&self, index: usize) -> &'a T {
+        self.type_layout.get_field_ref_by_index(self.data, index)
+    }
+
+    #[inline]
+    pub fn try_get_field_ref<T: 'static>(&self, name: &str) -> Result<&'a T, DynamicFieldError<()>> {
+        self.type_layout.try_get_field_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_field_ref_by_index<T: 'static>(&self, index: usize) -> Result<&'a T, DynamicFieldError<()>> {
+        self.type_layout.try_get_field_ref_by_index(self.data, index)
+    }
+
+    #[inline]
+    pub fn get_struct_ref(&self, name: &str) -> DynamicStructRef<'a> {
+        self.type_layout.get_struct_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn get_struct_ref_by_index(&self, index: usize) -> DynamicStructRef<'a> {
+        self.type_layout.get_struct_ref_by_index(self.data, index)
+    }
+
+    #[inline]
+    pub fn try_get_struct_ref(&self, name: &str) -> Result<DynamicStructRef<'a>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_struct_ref_by_index(&self, index: usize) -> Result<DynamicStructRef<'a>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_ref_by_index(self.data, index)
+    }
+
+    #[inline]
+    pub fn get_array_ref<T: 'static>(&self, name: &str) -> &'a [T] {
+        self.type_layout.get_array_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_array_ref<T: 'static>(&self, name: &str) -> Result<&'a [T], DynamicFieldError<()>> {
+        self.type_layout.try_get_array_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn get_struct_array_ref(&self, name: &str) -> DynamicStructArrayRef<'a> {
+        self.type_layout.get_struct_array_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_struct_array_ref(&self, name: &str) -> Result<DynamicStructArrayRef<'a>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_array_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_field_any_by_index(&self, index: usize) -> Result<&'a dyn Any, DynamicFieldError<()>> {
+        self.type_layout.try_get_field_any_by_index(self.data, index)
+    }
+}
+
+/// A mutable borrowed view of a dynamic struct, see `DynamicStructRef`.
+pub struct DynamicStructMut<'a> {
+    type_layout: &'a Arc<DynamicTypeLayout>,
+    /// Always initialised by `type_layout`, as the layout's field accessors require.
+    data: &'a mut [u8],
+}
+
+impl<'a> DynamicStructMut<'a> {
+    /// Mutable version of `DynamicStructRef::try_as_base`, consuming the view.
+    pub fn try_into_base(self, name: &str) -> Result<DynamicStructMut<'a>, DynamicFieldError<()>> {
+        if self.type_layout.name == name {
+            return Ok(self);
+        }
+        match self.type_layout.base_layout(name) {
+            Some(base) => Ok(DynamicStructMut { type_layout: base, data: &mut self.data[..base.total_size] }),
+            None => Err(DynamicFieldError::NotDerivedFrom {
+                layout: self.type_layout.name.clone(),
+                base: name.into(),
+            }),
+        }
+    }
+
+    #[inline]
+    pub fn try_as_base_mut(&mut self, name: &str) -> Result<DynamicStructMut<'_>, DynamicFieldError<()>> {
+        self.reborrow().try_into_base(name)
+    }
+
+    #[inline]
+    pub fn as_base_mut(&mut self, name: &str) -> DynamicStructMut<'_> {
+        self.try_as_base_mut(name).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl fmt::Debug for DynamicStructMut<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_ref(), f)
+    }
+}
+
+impl<'a> DynamicStructMut<'a> {
+    #[inline]
+    pub fn layout(&self) -> &'a Arc<DynamicTypeLayout> {
+        self.type_layout
+    }
+
+    #[inline]
+    pub fn as_ref(&self) -> DynamicStructRef<'_> {
+        DynamicStructRef { type_layout: self.type_layout, data: self.data }
+    }
+
+    /// Panics if `handle` was created from a different layout.
+    #[inline]
+    pub fn get<T: 'static>(&self, handle: &FieldHandle<T>) -> &T {
+        self.type_layout.check_handle(handle);
+        unsafe { &*self.data.as_ptr().add(handle.offset).cast::<T>() }
+    }
+
+    /// Panics if `handle` was created from a different layout.
+    #[inline]
+    pub fn get_mut<T: 'static>(&mut self, handle: &FieldHandle<T>) -> &mut T {
+        self.type_layout.check_handle(handle);
+        unsafe { &mut *self.data.as_mut_ptr().add(handle.offset).cast::<T>() }
+    }
+
+    /// Panics if `handle` was created from a different layout.
+    #[inline]
+    pub fn set<T: 'static>(&mut self, handle: &FieldHandle<T>, val: T) {
+        *self.get_mut(handle) = val;
+    }
+
+    #[inline]
+    pub fn set_field<T: 'static>(&mut self, name: &str, val: T) {
+        self.type_layout.set_field(self.data, name, val);
+    }
+
+    #[inline]
+    pub fn set_field_by_index<T: 'static>(&mut self, val: T, index: usize) {
+        self.type_layout.set_field_by_index(self.data, index, val);
+    }
+
+    #[inline]
+    pub fn try_set_field<T: 'static>(&mut self, name: &str, val: T) -> Result<(), DynamicFieldError<T>> {
+        self.type_layout.try_set_field(self.data, name, val)
+    }
+
+    #[inline]
+    pub fn try_set_field_by_index<T: 'static>(&mut self, val: T, index: usize) -> Result<(), DynamicFieldError<T>> {
+        self.type_layout.try_set_field_by_index(self.data, index, val)
+    }
+
+    #[inline]
+    pub fn clone_field<T: 'static + Clone>(&self, name: &str) -> T {
+        self.type_layout.clone_field(self.data, name)
+    }
+
+    #[inline]
+    pub fn get_field_ref<T: 'static>(&self, name: &str) -> &T {
+        self.type_layout.get_field_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn get_field_ref_by_index<T: 'static>(&self, index: usize) -> &T {
+        self.type_layout.get_field_ref_by_index(self.data, index)
+    }
+
+    #[inline]
+    pub fn get_field_mut<T: 'static>(&mut self, name: &str) -> &mut T {
+        self.type_layout.get_field_mut(self.data, name)
+    }
+
+    #[inline]
+    pub fn get_field_mut_by_index<T: 'static>(&mut self, index: usize) -> &mut T {
+        self.type_layout.get_field_mut_by_index(self.data, index)
+    }
+
+    #[inline]
+    pub fn try_get_field_ref<T: 'static>(&self, name: &str) -> Result<&T, DynamicFieldError<()>> {
+        self.type_layout.try_get_field_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_field_mut<T: 'static>(&mut self, name: &str) -> Result<&mut T, DynamicFieldError<()>> {
+        self.type_layout.try_get_field_mut(self.data, name)
+    }
+
+    #[inline]
+    pub fn get_struct_ref(&self, name: &str) -> DynamicStructRef<'_> {
+        self.type_layout.get_struct_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn get_struct_mut(&mut self, name: &str) -> DynamicStructMut<'_> {
+        self.type_layout.get_struct_mut(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_struct_ref(&self, name: &str) -> Result<DynamicStructRef<'_>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_struct_mut(&mut self, name: &str) -> Result<DynamicStructMut<'_>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_mut(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_struct_mut_by_index(&mut self, index: usize) -> Result<DynamicStructMut<'_>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_mut_by_index(self.data, index)
+    }
+
+    #[inline]
+    pub fn get_array_ref<T: 'static>(&self, name: &str) -> &[T] {
+        self.type_layout.get_array_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_array_ref<T: 'static>(&self, name: &str) -> Result<&[T], DynamicFieldError<()>> {
+        self.type_layout.try_get_array_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn get_array_mut<T: 'static>(&mut self, name: &str) -> &mut [T] {
+        self.type_layout.get_array_mut(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_array_mut<T: 'static>(&mut self, name: &str) -> Result<&mut [T], DynamicFieldError<()>> {
+        self.type_layout.try_get_array_mut(self.data, name)
+    }
+
+    #[inline]
+    pub fn get_struct_array_ref(&self, name: &str) -> DynamicStructArrayRef<'_> {
+        self.type_layout.get_struct_array_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_struct_array_ref(&self, name: &str) -> Result<DynamicStructArrayRef<'_>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_array_ref(self.data, name)
+    }
+
+    #[inline]
+    pub fn get_struct_array_mut(&mut self, name: &str) -> DynamicStructArrayMut<'_> {
+        self.type_layout.get_struct_array_mut(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_struct_array_mut(&mut self, name: &str) -> Result<DynamicStructArrayMut<'_>, DynamicFieldError<()>> {
+        self.type_layout.try_get_struct_array_mut(self.data, name)
+    }
+
+    #[inline]
+    pub fn try_get_field_any_mut_by_index(&mut self, index: usize) -> Result<&mut dyn Any, DynamicFieldError<()>> {
+        self.type_layout.try_get_field_any_mut_by_index(self.data, index)
+    }
+
+    /// Reborrows the view for a shorter lifetime, so it can be passed on without being consumed.
+    #[inline]
+    pub fn reborrow(&mut self) -> DynamicStructMut<'_> {
+        DynamicStructMut { type_layout: self.type_layout, data: self.data }
+    }
+
+    /// Mutable version of `DynamicStructRef::fields`.
+    pub fn fields_mut(&mut self) -> impl ExactSizeIterator<Item = DynamicFieldMut<'_>> {
+        self.reborrow().into_fields_mut()
+    }
+
+    fn into_fields_mut(self) -> impl ExactSizeIterator<Item = DynamicFieldMut<'a>> {
+        let layout: &'a DynamicTypeLayout = self.type_layout;
+        let data = self.data.as_mut_ptr();
+        (0..layout.field_kinds.len()).map(move |index| {
+            let kind = &layout.field_kinds[index];
+            // Fields never overlap, so each gets its own disjoint slice of the borrowed storage.
+            let bytes = unsafe {
+                std::slice::from_raw_parts_mut(data.add(layout.field_offsets[index]), kind.size())
+            };
+            DynamicFieldMut {
+                name: &layout.field_names[index],
+                index,
+                type_name: layout.field_type_names[index],
+                type_id: layout.field_types[index],
+                value: kind.value_mut(bytes),
+            }
+        })
+    }
+}
+
+/// An inline array of nested dynamic structs.
+#[derive(Clone, Copy)]
+pub struct DynamicStructArrayRef<'a> {
+    element: &'a Arc<DynamicTypeLayout>,
+    data: &'a [u8],
+    len: usize,
+}
+
+impl fmt::Debug for DynamicStructArrayRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> DynamicStructArrayRef<'a> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn layout(&self) -> &'a Arc<DynamicTypeLayout> {
+        self.element
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<DynamicStructRef<'a>> {
+        if index >= self.len {
+            return None;
+        }
+        let offset = index * self.element.total_size;
+        Some(DynamicStructRef {
+            type_layout: self.element,
+            data: &self.data[offset..offset + self.element.total_size],
+        })
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = DynamicStructRef<'a>> + '_ {
+        (0..self.len).map(|index| self.get(index).unwrap())
+    }
+}
+
+pub struct DynamicStructArrayMut<'a> {
+    element: &'a Arc<DynamicTypeLayout>,
+    data: &'a mut [u8],
+    len: usize,
+}
+
+impl fmt::Debug for DynamicStructArrayMut<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_ref(), f)
+    }
+}
+
+impl DynamicStructArrayMut<'_> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn as_ref(&self) -> DynamicStructArrayRef<'_> {
+        DynamicStructArrayRef { element: self.element, data: self.data, len: self.len }
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<DynamicStructRef<'_>> {
+        self.as_ref().get(index)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> Option<DynamicStructMut<'_>> {
+        if index >= self.len {
+            return None;
+        }
+        let offset = index * self.element.total_size;
+        Some(DynamicStructMut {
+            type_layout: self.element,
+            data: &mut self.data[offset..offset + self.element.total_size],
+        })
+    }
+}
+
+/// One field of a dynamic struct, as yielded by `DynamicStruct::fields`.
+#[derive(Clone, Copy)]
+pub struct DynamicField<'a> {
+    pub name: &'a str,
+    pub index: usize,
+    pub type_name: &'static str,
+    pub type_id: TypeId,
+    pub value: FieldValue<'a>,
+}
+
+pub struct DynamicFieldMut<'a> {
+    pub name: &'a str,
+    pub index: usize,
+    pub type_name: &'static str,
+    pub type_id: TypeId,
+    pub value: FieldValueMut<'a>,
+}
+
+/// The value of a field, static fields are `Any` while nested structs and arrays get views.
+#[derive(Clone, Copy)]
+pub enum FieldValue<'a> {
+    Value(&'a dyn Any),
+    Struct(DynamicStructRef<'a>),
+    Array(DynamicArrayRef<'a>),
+}
+
+impl<'a> FieldValue<'a> {
+    #[inline]
+    pub fn as_any(&self) -> Option<&'a dyn Any> {
+        match self {
+            FieldValue::Value(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn downcast_ref<T: Any>(&self) -> Option<&'a T> {
+        self.as_any().and_then(|value| value.downcast_ref())
+    }
+}
+
+pub enum FieldValueMut<'a> {
+    Value(&'a mut dyn Any),
+    Struct(DynamicStructMut<'a>),
+    Array(DynamicArrayMut<'a>),
+}
+
+impl FieldValueMut<'_> {
+    #[inline]
+    pub fn as_ref(&self) -> FieldValue<'_> {
+        match self {
+            FieldValueMut::Value(value) => FieldValue::Value(&**value),
+            FieldValueMut::Struct(value) => FieldValue::Struct(value.as_ref()),
+            FieldValueMut::Array(value) => FieldValue::Array(value.as_ref()),
+        }
+    }
+
+    #[inline]
+    pub fn downcast_mut<T: Any>(&mut self) -> Option<&mut T> {
+        match self {
+            FieldValueMut::Value(value) => value.downcast_mut(),
+            _ => None,
+        }
+    }
+}
+
+/// An inline array field of any element kind, see `DynamicStructArrayRef` for arrays known to
+/// hold dynamic structs.
+#[derive(Clone, Copy)]
+pub struct DynamicArrayRef<'a> {
+    element: &'a FieldKind,
+    data: &'a [u8],
+    len: usize,
+}
+
+impl<'a> DynamicArrayRef<'a> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn element_type_name(&self) -> &'static str {
+        self.element.type_name()
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<FieldValue<'a>> {
+        if index >= self.len {
+            return None;
+        }
+        let size = self.element.size();
+        Some(self.element.value_ref(&self.data[index * size..(index + 1) * size]))
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = FieldValue<'a>> + '_ {
+        (0..self.len).map(|index| self.get(index).unwrap())
+    }
+}
+
+pub struct DynamicArrayMut<'a> {
+    element: &'a FieldKind,
+    data: &'a mut [u8],
+    len: usize,
+}
+
+impl DynamicArrayMut<'_> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn as_ref(&self) -> DynamicArrayRef<'_> {
+        DynamicArrayRef { element: self.element, data: self.data, len: self.len }
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> Option<FieldValueMut<'_>> {
+        if index >= self.len {
+            return None;
+        }
+        let size = self.element.size();
+        Some(self.element.value_mut(&mut self.data[index * size..(index + 1) * size]))
+    }
+}
+
+pub struct DynamicEnumLayoutBuilder {
+    name: String,
+    /// Unit variants have no layout until `try_build` creates their empty one.
+    variants: Vec<(String, Option<Arc<DynamicTypeLayout>>)>,
+}
+
+impl DynamicEnumLayoutBuilder {
+    /// Adds a variant holding the fields of `layout`.
+    pub fn variant(mut self, name: &str, layout: &Arc<DynamicTypeLayout>) -> Self {
+        self.variants.push((name.into(), Some(layout.clone())));
+        self
+    }
+
+    /// Adds a variant without fields. Its layout is the only zero sized one, builders reject them.
+    pub fn unit_variant(mut self, name: &str) -> Self {
+        self.variants.push((name.into(), None));
+        self
+    }
+
+    /// Panics on duplicate variant names, no variants or if the layout is too large, see `try_build`.
+    pub fn build(self) -> DynamicEnumLayout {
+        self.try_build().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_build(self) -> Result<DynamicEnumLayout, TypeRegistryError> {
+        let Self { name, variants } = self;
+        if variants.is_empty() {
+            return Err(TypeRegistryError::NoVariants { layout: name });
+        }
+
+        let mut variant_names = Vec::with_capacity(variants.len());
+        let mut variant_layouts = Vec::with_capacity(variants.len());
+        let mut name_to_variant = AHashMap::with_capacity(variants.len());
+        let mut align = std::mem::align_of::<u32>();
+        let mut payload_size = 0;
+
+        for (index, (variant_name, layout)) in variants.into_iter().enumerate() {
+            if name_to_variant.contains_key(variant_name.as_str()) {
+                return Err(TypeRegistryError::DuplicateVariantName {
+                    layout: name,
+                    variant: variant_name,
+                });
+            }
+            let layout = match layout {
+                Some(layout) => layout,
+                None => Arc::new(DynamicTypeLayout::builder(variant_name.clone()).build_sized()?),
+            };
+            align = align.max(layout.align);
+            payload_size = payload_size.max(layout.total_size);
+            name_to_variant.insert(variant_name.as_str().into(), index);
+            variant_names.push(variant_name);
+            variant_layouts.push(layout);
+        }
+
+        // Same as a `#[repr(C, u32)]` enum, the tag first then every payload at one shared offset.
+        let overflow = || TypeRegistryError::SizeOverflow { layout: name.clone() };
+        let payload_offset = std::mem::size_of::<u32>().next_multiple_of(align);
+        let total_size = payload_offset
+            .checked_add(payload_size)
+            .and_then(|size| size.checked_next_multiple_of(align))
+            .ok_or_else(overflow)?;
+        if Layout::from_size_align(total_size, align).is_err() {
+            return Err(overflow());
+        }
+
+        Ok(DynamicEnumLayout {
+            name,
+            variant_names,
+            variant_layouts,
+            name_to_variant,
+            payload_offset,
+            total_size,
+            align,
+        })
+    }
+}
+
+/// A tagged union of dynamic struct layouts, laid out like a `#[repr(C, u32)]` enum: a `u32`
+/// discriminant at offset 0 followed by the active variant's fields at `payload_offset`.
+pub struct DynamicEnumLayout {
+    pub name: String,
+    pub variant_names: Vec<String>,
+    pub variant_layouts: Vec<Arc<DynamicTypeLayout>>,
+    pub name_to_variant: AHashMap<std::string::String, usize>,
+    pub payload_offset: usize,
+    pub total_size: usize,
+    pub align: usize,
+}
+
+impl DynamicEnumLayout {
+    pub fn builder(name: String) -> DynamicEnumLayoutBuilder {
+        DynamicEnumLayoutBuilder { name, variants: Vec::new() }
+    }
+
+    #[inline]
+    pub fn variant_index(&self, name: &str) -> Option<usize> {
+        self.name_to_variant.get(name).copied()
+    }
+}
+
+/// A value of a `DynamicEnumLayout`, always holding exactly one initialised variant.
+pub struct DynamicEnum {
+    type_layout: Arc<DynamicEnumLayout>,
+    data: AlignedBytes,
+}
+
+impl Drop for DynamicEnum {
+    fn drop(&mut self) {
+        let active = self.active_variant();
+        unsafe { self.type_layout.variant_layouts[active].drop_data(self.payload_ptr()) };
+    }
+}
+
+impl fmt::Debug for DynamicEnum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let active = self.active_variant();
+        let name = format!("{}::{}", self.type_layout.name, self.type_layout.variant_names[active]);
+        unsafe { self.type_layout.variant_layouts[active].fmt_data_as(&name, self.payload_ptr(), f) }
+    }
+}
+
+impl DynamicEnum {
+    /// Creates the enum holding its first variant with default fields.
+    pub fn new(type_layout: Arc<DynamicEnumLayout>) -> Self {
+        let mut data = AlignedBytes::zeroed(type_layout.total_size, type_layout.align);
+        unsafe { type_layout.variant_layouts[0].init_data(data.as_mut_ptr().add(type_layout.payload_offset)) };
+
+        Self { type_layout, data }
+    }
+
+    pub fn with_variant(type_layout: Arc<DynamicEnumLayout>, name: &str) -> Self {
+        Self::try_with_variant(type_layout, name).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_with_variant(type_layout: Arc<DynamicEnumLayout>, name: &str) -> Result<Self, DynamicFieldError<()>> {
+        let index = type_layout
+            .variant_index(name)
+            .ok_or_else(|| DynamicFieldError::VariantNameNotFound { name: name.into() })?;
+        let mut value = Self::new(type_layout);
+        value.set_variant_by_index(index);
+        Ok(value)
+    }
+
+    #[inline]
+    pub fn layout(&self) -> &Arc<DynamicEnumLayout> {
+        &self.type_layout
+    }
+
+    #[inline]
+    pub fn active_variant(&self) -> usize {
+        unsafe { self.data.as_ptr().cast::<u32>().read() as usize }
+    }
+
+    #[inline]
+    pub fn active_variant_name(&self) -> &str {
+        &self.type_layout.variant_names[self.active_variant()]
+    }
+
+    #[inline]
+    pub fn is_variant(&self, name: &str) -> bool {
+        self.type_layout.variant_index(name) == Some(self.active_variant())
+    }
+
+    /// Switches to the variant `name` with default fields, dropping the fields of the active one.
+    pub fn set_variant(&mut self, name: &str) {
+        if let Err(err) = self.try_set_variant(name) {
+            panic!("{}", err);
+        }
+    }
+
+    pub fn try_set_variant(&mut self, name: &str) -> Result<(), DynamicFieldError<()>> {
+        let index = self
+            .type_layout
+            .variant_index(name)
+            .ok_or_else(|| DynamicFieldError::VariantNameNotFound { name: name.into() })?;
+        self.set_variant_by_index(index);
+        Ok(())
+    }
+
+    fn set_variant_by_index(&mut self, index: usize) {
+        let layout = &self.type_layout.variant_layouts[index];
+        // Initialise first so a panicking default can't leave the active variant dropped.
+        let mut payload = AlignedBytes::zeroed(layout.total_size, layout.align);
+        unsafe {
+            layout.init_data(payload.as_mut_ptr());
+            self.type_layout.variant_layouts[self.active_variant()].drop_data(self.payload_ptr());
+            let dst = self.data.as_mut_ptr();
+            dst.add(self.type_layout.payload_offset)
+                .copy_from_nonoverlapping(payload.as_ptr(), payload.len());
+            dst.cast::<u32>().write(index as u32);
+        }
+    }
+
+    /// The fields of the active variant.
+    #[inline]
+    pub fn variant(&self) -> DynamicStructRef<'_> {
+        let layout = &self.type_layout.variant_layouts[self.active_variant()];
+        let offset = self.type_layout.payload_offset;
+        DynamicStructRef { type_layout: layout, data: &self.data[offset..offset + layout.total_size] }
+    }
+
+    #[inline]
+    pub fn variant_mut(&mut self) -> DynamicStructMut<'_> {
+        let layout = &self.type_layout.variant_layouts[self.active_variant()];
+        let offset = self.type_layout.payload_offset;
+        DynamicStructMut { type_layout: layout, data: &mut self.data[offset..offset + layout.total_size] }
+    }
+
+    /// The fields of variant `name`, failing unless it is the active one.
+    pub fn try_variant(&self, name: &str) -> Result<DynamicStructRef<'_>, DynamicFieldError<()>> {
+        self.check_active(name)?;
+        Ok(self.variant())
+    }
+
+    pub fn try_variant_mut(&mut self, name: &str) -> Result<DynamicStructMut<'_>, DynamicFieldError<()>> {
+        self.check_active(name)?;
+        Ok(self.variant_mut())
+    }
+
+    fn check_active(&self, name: &str) -> Result<(), DynamicFieldError<()>> {
+        match self.type_layout.variant_index(name) {
+            Some(index) if index == self.active_variant() => Ok(()),
+            Some(_) => Err(DynamicFieldError::VariantNotActive {
+                requested: name.into(),
+                active: self.active_variant_name().into(),
+            }),
+            None => Err(DynamicFieldError::VariantNameNotFound { name: name.into() }),
+        }
+    }
+
+    #[inline]
+    pub fn get_field_ref<T: 'static>(&self, name: &str) -> &T {
+        self.variant().get_field_ref(name)
+    }
+
+    #[inline]
+    pub fn get_field_mut<T: 'static>(&mut self, name: &str) -> &mut T {
+        let layout = &self.type_layout.variant_layouts[self.active_variant()];
+        let offset = self.type_layout.payload_offset;
+        layout.get_field_mut(&mut self.data[offset..offset + layout.total_size], name)
+    }
+
+    #[inline]
+    pub fn set_field<T: 'static>(&mut self, name: &str, val: T) {
+        self.variant_mut().set_field(name, val);
+    }
+
+    #[inline]
+    pub fn try_get_field_ref<T: 'static>(&self, name: &str) -> Result<&T, DynamicFieldError<()>> {
+        self.variant().try_get_field_ref(name)
+    }
+
+    #[inline]
+    pub fn try_get_field_mut<T: 'static>(&mut self, name: &str) -> Result<&mut T, DynamicFieldError<()>> {
+        let layout = &self.type_layout.variant_layouts[self.active_variant()];
+        let offset = self.type_layout.payload_offset;
+        layout.try_get_field_mut(&mut self.data[offset..offset + layout.total_size], name)
+    }
+
+    #[inline]
+    pub fn try_set_field<T: 'static>(&mut self, name: &str, val: T) -> Result<(), DynamicFieldError<T>> {
+        self.variant_mut().try_set_field(name, val)
+    }
+
+    #[inline]
+    fn payload_ptr(&self) -> *const u8 {
+        unsafe { self.data.as_ptr().add(self.type_layout.payload_offset) }
+    }
+}
+
+/// Zero initialised heap storage aligned to the owning layout's alignment, so every field offset
+/// computed by `DynamicTypeLayout` lands on an address suitable for the field's type.
+struct AlignedBytes {
+    ptr: NonNull<u8>,
+    len: usize,
+    align: usize,
+}
+
+impl AlignedBytes {
+    fn zeroed(len: usize, align: usize) -> Self {
+        let ptr = if len == 0 {
+            Self::dangling(align)
+        } else {
+            let layout = Layout::from_size_align(len, align)
+                .unwrap_or_else(|_| panic!("Invalid layout, size: {}, align: {}", len, align));
+            let ptr = unsafe { alloc::alloc_zeroed(layout) };
+            NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout))
+        };
+
+        Self { ptr, len, align }
+    }
+
+    #[inline]
+    fn dangling(align: usize) -> NonNull<u8> {
+        // An aligned, non-null address is all a zero length slice needs.
+        unsafe { NonNull::new_unchecked(align as *mut u8) }
+    }
+
+    /// Frees the storage without running any field destructors, leaving an empty buffer behind.
+    fn clear(&mut self) {
+        if self.len != 0 {
+            unsafe {
+                alloc::dealloc(
+                    self.ptr.as_ptr(),
+                    Layout::from_size_align_unchecked(self.len, self.align),
+                );
+            }
+            self.ptr = Self::dangling(self.align);
+            self.len = 0;
+        }
+    }
+}
+
+impl Deref for AlignedBytes {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBytes {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBytes {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+// The buffer is plain bytes, ownership semantics match `Vec<u8>`.
+unsafe impl Send for AlignedBytes {}
+unsafe impl Sync for AlignedBytes {}
+
+/// Clones the value at the first pointer into the uninitialised memory at the second.
+pub type CloneFn = unsafe fn(*const u8, *mut u8);
+/// Formats the value at the pointer with its `Debug` implementation.
+pub type DebugFn = unsafe fn(*const u8, &mut fmt::Formatter<'_>) -> fmt::Result;
+/// Compares the values at both pointers with their `PartialEq` implementation.
+pub type EqFn = unsafe fn(*const u8, *const u8) -> bool;
+/// Feeds the value at the pointer into the hasher with its `Hash` implementation.
+pub type HashFn = unsafe fn(*const u8, &mut dyn Hasher);
+/// Walks the value at the pointer with its `Visitable` implementation.
+pub type VisitFn = unsafe fn(*const u8, &mut dyn DynamicVisitor);
+/// Converts the value at the pointer to JSON with its `Serialize` implementation.
+#[cfg(feature = "serde")]
+pub type SerializeFn = unsafe fn(*const u8) -> Result<serde_json::Value, serde_json::Error>;
+/// Replaces the initialised value at the pointer with one read from JSON with its `Deserialize`
+/// implementation.
+#[cfg(feature = "serde")]
+pub type DeserializeFn = unsafe fn(serde_json::Value, *mut u8) -> Result<(), serde_json::Error>;
+/// Attaches the `Any` vtable of the type to the pointer.
+pub type AsAnyFn = unsafe fn(*const u8) -> *const dyn Any;
+/// Attaches the `Any` vtable of the type to the pointer.
+pub type AsAnyMutFn = unsafe fn(*mut u8) -> *mut dyn Any;
+/// Writes a field's overridden default into the uninitialised memory at the pointer. Kept private
+/// to the layout, the pointer must be valid and aligned for the field's type.
+type DefaultFn = Arc<dyn Fn(*mut u8) + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub struct StaticTypeLayout {
+    type_id: TypeId,
+    size: usize,
+    align: usize,
+    default: unsafe fn() -> Vec<u8>,
+    drop_fn: Option<fn(*const u8)>,
+    clone_fn: Option<CloneFn>,
+    debug_fn: Option<DebugFn>,
+    eq_fn: Option<EqFn>,
+    hash_fn: Option<HashFn>,
+    visit_fn: Option<VisitFn>,
+    #[cfg(feature = "serde")]
+    serialize_fn: Option<SerializeFn>,
+    #[cfg(feature = "serde")]
+    deserialize_fn: Option<DeserializeFn>,
+    as_any_fn: AsAnyFn,
+    as_any_mut_fn: AsAnyMutFn,
+    name: &'static str,
+}
+
+impl StaticTypeLayout {
+    pub fn of<T: Any + Default + DefaultBytes>() -> Self {
+        StaticTypeLayout {
+            type_id: TypeId::of::<T>(),
+            size: std::mem::size_of::<T>(),
+            align: std::mem::align_of::<T>(),
+            default: { T::default_bytes },
+            name: std::any::type_name::<T>(),
+            drop_fn: {
+                if std::mem::needs_drop::<T>() {
+                    let func = unsafe {
+                        std::mem::transmute::<unsafe fn(*mut T), fn(*const u8)>(drop_in_place::<T>)
+                    };
+                    Some(func)
+                } else {
+                    None
+                }
+            },
+            clone_fn: None,
+            debug_fn: None,
+            eq_fn: None,
+            hash_fn: None,
+            visit_fn: None,
+            #[cfg(feature = "serde")]
+            serialize_fn: None,
+            #[cfg(feature = "serde")]
+            deserialize_fn: None,
+            as_any_fn: as_any::<T>,
+            as_any_mut_fn: as_any_mut::<T>,
+        }
+    }
+
+    /// Captures `T::clone` so dynamic structs holding this type can be cloned.
+    pub fn with_clone<T: Any + Clone>(mut self) -> Self {
+        self.check_type::<T>();
+        self.clone_fn = Some(clone_into::<T>);
+        self
+    }
+
+    /// Captures `T`'s `Debug` implementation so dynamic structs can print this field.
+    pub fn with_debug<T: Any + fmt::Debug>(mut self) -> Self {
+        self.check_type::<T>();
+        self.debug_fn = Some(debug_fmt::<T>);
+        self
+    }
+
+    /// Captures `T`'s `PartialEq` implementation so dynamic structs holding this type can be compared.
+    pub fn with_eq<T: Any + PartialEq>(mut self) -> Self {
+        self.check_type::<T>();
+        self.eq_fn = Some(eq_fields::<T>);
+        self
+    }
+
+    /// Captures `T`'s `Hash` implementation so dynamic structs holding this type can be hashed.
+    /// Requires `Eq` so that `HashableStruct` can rely on equality being total.
+    pub fn with_hash<T: Any + Eq + Hash>(mut self) -> Self {
+        self.check_type::<T>();
+        self.hash_fn = Some(hash_field::<T>);
+        self
+    }
+
+    /// Captures `T`'s `Visitable` implementation so `DynamicStruct::visit` can look inside this field
+    /// instead of reporting it as opaque.
+    pub fn with_visit<T: Any + Visitable>(mut self) -> Self {
+        self.check_type::<T>();
+        self.visit_fn = Some(visit_field::<T>);
+        self
+    }
+
+    #[inline]
+    pub fn type_name(&self) -> &'static str {
+        self.name
+    }
+
+    #[inline]
+    fn check_type<T: Any>(&self) {
+        if self.type_id != TypeId::of::<T>() {
+            panic!(
+                "Invalid type, expected: {:?}, but found {:?}",
+                self.name,
+                std::any::type_name::<T>()
+            );
+        }
+    }
+}
+
+/// # Safety
+/// `src` must point to a valid `T` and `dst` to memory valid for writing a `T`.
+unsafe fn clone_into<T: Clone>(src: *const u8, dst: *mut u8) {
+    dst.cast::<T>().write((*src.cast::<T>()).clone());
+}
+
+/// # Safety
+/// `src` must point to a valid `T`.
+unsafe fn visit_field<T: Visitable>(src: *const u8, visitor: &mut dyn DynamicVisitor) {
+    (*src.cast::<T>()).visit(visitor)
+}
+
+/// # Safety
+/// `src` must point to a valid `T`.
+unsafe fn debug_fmt<T: fmt::Debug>(src: *const u8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Debug::fmt(&*src.cast::<T>(), f)
+}
+
+/// # Safety
+/// `a` and `b` must both point to a valid `T`.
+unsafe fn eq_fields<T: PartialEq>(a: *const u8, b: *const u8) -> bool {
+    *a.cast::<T>() == *b.cast::<T>()
+}
+
+fn as_any<T: Any>(src: *const u8) -> *const dyn Any {
+    src.cast::<T>() as *const dyn Any
+}
+
+fn as_any_mut<T: Any>(src: *mut u8) -> *mut dyn Any {
+    src.cast::<T>() as *mut dyn Any
+}
+
+/// # Safety
+/// `src` must point to a valid `T`.
+unsafe fn hash_field<T: Hash>(src: *const u8, mut state: &mut dyn Hasher) {
+    (*src.cast::<T>()).hash(&mut state);
+}
+
+pub trait DefaultBytes: Default {
+    /// # Safety
+    /// If this type is not `Copy` then remember to cast it back to its original type and drop it when you're done with it.
+    unsafe fn default_bytes() -> Vec<u8> {
+        let new = Self::default();
+        let new_bytes = unsafe {
+            std::slice::from_raw_parts(
+                &new as *const Self as *const u8,
+                std::mem::size_of::<Self>(),
+            )
+        };
+        let mut output = Vec::with_capacity(std::mem::size_of::<Self>());
+        for b in new_bytes {
+            output.push(*b);
+        }
+        if std::mem::needs_drop::<Self>() {
+            std::mem::forget(new);
+        }
+        output
+    }
+}
+
+impl<T: Default> DefaultBytes for T {}
+
+/// # Safety
+/// Only a valid trait for sequential collections of bytes.
+pub unsafe trait VecToType {
+    /// # Safety
+    /// Only cast the vec if it was created with `DefaultBytes` trait or created manually the same way and with the same generic type.
     unsafe fn cast<T>(self) -> T;
+
+    /// # Safety
+    /// Only call this if it was created with `DefaultBytes` trait or created manually the same way and with the same generic type.
     unsafe fn drop_as<T>(self);
 }
 
 unsafe impl VecToType for Vec<u8> {
-    /// # Safety
-    /// Only cast the vec if it was created with `DefaultBytes` trait or created manually the same way and with the same generic type.
     unsafe fn cast<T>(self) -> T {
-        let bytes = self.as_ptr().cast::<T>();
+        let bytes = self.as_ptr();
         let mut output = MaybeUninit::<T>::zeroed();
-        bytes.copy_to_nonoverlapping(output.as_mut_ptr(), 1);
+        // A `Vec<u8>` is only guaranteed to be byte aligned, so copy bytes rather than values.
+        bytes.copy_to_nonoverlapping(output.as_mut_ptr().cast::<u8>(), std::mem::size_of::<T>());
         output.assume_init()
     }
 
-    /// # Safety
-    /// Only call this if it was created with `DefaultBytes` trait or created manually the same way and with the same generic type.
     unsafe fn drop_as<T>(self) {
-        let bytes = self.as_ptr().cast_mut().cast::<T>();
-        drop_in_place(bytes);
+        drop(self.cast::<T>());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, Clone, Copy, PartialEq)]
+    #[repr(align(64))]
+    struct OverAligned(u8);
+
+    fn is_aligned<T>(value: &T) -> bool {
+        (value as *const T as usize).is_multiple_of(std::mem::align_of::<T>())
+    }
+
+    #[test]
+    fn layout_align_is_max_field_align() {
+        let layout = DynamicTypeLayout::new(
+            "Aligned".into(),
+            &[
+                ("a", &StaticTypeLayout::of::<u8>()),
+                ("b", &StaticTypeLayout::of::<f64>()),
+                ("c", &StaticTypeLayout::of::<u16>()),
+            ],
+        );
+        assert_eq!(layout.align, std::mem::align_of::<f64>());
+
+        let layout = DynamicTypeLayout::new(
+            "OverAligned".into(),
+            &[
+                ("a", &StaticTypeLayout::of::<u8>()),
+                ("b", &StaticTypeLayout::of::<OverAligned>()),
+            ],
+        );
+        assert_eq!(layout.align, 64);
+    }
+
+    #[test]
+    fn field_pointers_are_aligned() {
+        let layout = Arc::new(DynamicTypeLayout::new(
+            "Mixed".into(),
+            &[
+                ("a", &StaticTypeLayout::of::<u8>()),
+                ("b", &StaticTypeLayout::of::<f64>()),
+                ("c", &StaticTypeLayout::of::<u8>()),
+                ("d", &StaticTypeLayout::of::<Arc<String>>()),
+                ("e", &StaticTypeLayout::of::<u16>()),
+                ("f", &StaticTypeLayout::of::<Vec<u64>>()),
+                ("g", &StaticTypeLayout::of::<u128>()),
+                ("h", &StaticTypeLayout::of::<OverAligned>()),
+                ("i", &StaticTypeLayout::of::<String>()),
+            ],
+        ));
+
+        // Several instances so a lucky allocator alignment can't hide a problem.
+        let instances: Vec<_> = (0..32).map(|_| DynamicStruct::new(layout.clone())).collect();
+        for instance in &instances {
+            assert!((instance.data.as_ptr() as usize).is_multiple_of(layout.align));
+            assert!(is_aligned(instance.get_field_ref_by_index::<u8>(0)));
+            assert!(is_aligned(instance.get_field_ref_by_index::<f64>(1)));
+            assert!(is_aligned(instance.get_field_ref_by_index::<u8>(2)));
+            assert!(is_aligned(instance.get_field_ref_by_index::<Arc<String>>(3)));
+            assert!(is_aligned(instance.get_field_ref_by_index::<u16>(4)));
+            assert!(is_aligned(instance.get_field_ref_by_index::<Vec<u64>>(5)));
+            assert!(is_aligned(instance.get_field_ref_by_index::<u128>(6)));
+            assert!(is_aligned(instance.get_field_ref_by_index::<OverAligned>(7)));
+            assert!(is_aligned(instance.get_field_ref_by_index::<String>(8)));
+        }
+    }
+
+    #[test]
+    fn aligned_fields_round_trip() {
+        let layout = Arc::new(DynamicTypeLayout::new(
+            "RoundTrip".into(),
+            &[
+                ("flag", &StaticTypeLayout::of::<u8>()),
+                ("value", &StaticTypeLayout::of::<f64>()),
+                ("shared", &StaticTypeLayout::of::<Arc<Vec<i32>>>()),
+                ("wide", &StaticTypeLayout::of::<OverAligned>()),
+            ],
+        ));
+
+        let mut instance = DynamicStruct::new(layout);
+        instance.set_field("flag", 1u8);
+        instance.set_field("value", 12.5f64);
+        instance.set_field("shared", Arc::new(vec![1, 2, 3]));
+        instance.set_field("wide", OverAligned(7));
+
+        assert_eq!(*instance.get_field_ref::<u8>("flag"), 1);
+        assert_eq!(*instance.get_field_ref::<f64>("value"), 12.5);
+        assert_eq!(**instance.get_field_ref::<Arc<Vec<i32>>>("shared"), vec![1, 2, 3]);
+        assert_eq!(*instance.get_field_ref::<OverAligned>("wide"), OverAligned(7));
+    }
+
+    /// Declares a `#[repr(C)]` struct and checks a `DynamicTypeLayout` built from the same fields
+    /// agrees with the compiler on every offset, the alignment and the total size.
+    macro_rules! assert_repr_c {
+        ($name:ident { $($field:ident: $ty:ty),* $(,)? }) => {{
+            #[allow(dead_code)]
+            #[repr(C)]
+            struct $name {
+                $($field: $ty),*
+            }
+
+            let layout = DynamicTypeLayout::new(
+                stringify!($name).into(),
+                &[$((stringify!($field), &StaticTypeLayout::of::<$ty>())),*],
+            );
+
+            $(
+                assert_eq!(
+                    layout.field_offsets[layout.name_to_index[stringify!($field)]],
+                    std::mem::offset_of!($name, $field),
+                    "offset of {}.{}",
+                    stringify!($name),
+                    stringify!($field),
+                );
+            )*
+            assert_eq!(layout.align, std::mem::align_of::<$name>(), "align of {}", stringify!($name));
+            assert_eq!(layout.total_size, std::mem::size_of::<$name>(), "size of {}", stringify!($name));
+        }};
+    }
+
+    #[test]
+    fn empty_layout_has_no_size() {
+        // Only unit enum variants get here, `try_build` rejects empty layouts.
+        let layout = DynamicTypeLayout::builder("Empty".into()).build_sized().unwrap();
+        assert_eq!(layout.total_size, 0);
+        assert_eq!(layout.align, 1);
+    }
+
+    #[test]
+    fn layout_matches_repr_c_primitives() {
+        assert_repr_c!(Single { a: u8 });
+        assert_repr_c!(Pair { a: u8, b: u8 });
+        assert_repr_c!(SmallThenLarge { a: u8, b: u64 });
+        assert_repr_c!(LargeThenSmall { a: u64, b: u8 });
+        assert_repr_c!(TrailingPadding { a: u32, b: u16 });
+        assert_repr_c!(Bytes { o: u8, k: u8, a: i32, b: f32 });
+        assert_repr_c!(Interleaved { a: u8, b: u16, c: u8, d: u32, e: u8, f: u64, g: u8 });
+        assert_repr_c!(Floats { a: f32, b: f64, c: f32 });
+        assert_repr_c!(Wide { a: u8, b: u128, c: u8 });
+        assert_repr_c!(Signed { a: i8, b: i16, c: i32, d: i64, e: i128 });
+        assert_repr_c!(Flags { a: bool, b: char, c: bool });
+        assert_repr_c!(Pointers { a: u8, b: usize, c: isize, d: u8 });
+    }
+
+    #[test]
+    fn layout_matches_repr_c_heap_types() {
+        assert_repr_c!(Strings { a: u8, b: String, c: std::string::String, d: u16 });
+        assert_repr_c!(Vecs { a: Vec<u8>, b: u8, c: Vec<f64> });
+        assert_repr_c!(Shared { a: u8, b: Arc<u64>, c: Option<Arc<String>>, d: u32 });
+        assert_repr_c!(Boxes { a: Option<Box<u8>>, b: u16, c: Box<[u8; 3]> });
+        assert_repr_c!(Locks { a: u8, b: Arc<RwLock<Vec<i32>>>, c: Mutex<u64>, d: RwLock<u8> });
+        assert_repr_c!(Options { a: Option<u8>, b: Option<u32>, c: Option<f64>, d: Option<u8> });
+    }
+
+    #[test]
+    fn layout_matches_repr_c_aggregates() {
+        assert_repr_c!(Arrays { a: u8, b: [u16; 3], c: [u8; 5], d: [u64; 2] });
+        assert_repr_c!(Tuples { a: (u8, u32), b: u8, c: (u64, u8) });
+        assert_repr_c!(OverAlignedFields { a: u8, b: OverAligned, c: u8 });
+        assert_repr_c!(ZeroSized { a: u8, b: (), c: u32, d: () });
+        assert_repr_c!(MainTest { o: u8, k: u8, a: i32, b: f32, c: String, d: Vec<i32>, e: Arc<u8> });
+    }
+
+    #[test]
+    fn layout_matches_repr_c_permutations() {
+        assert_repr_c!(P1 { a: u8, b: u16, c: u32, d: u64 });
+        assert_repr_c!(P2 { a: u64, b: u32, c: u16, d: u8 });
+        assert_repr_c!(P3 { a: u16, b: u8, c: u64, d: u32 });
+        assert_repr_c!(P4 { a: u32, b: u64, c: u8, d: u16 });
+        assert_repr_c!(P5 { a: u8, b: u64, c: u16, d: u32 });
+        assert_repr_c!(P6 { a: u16, b: u64, c: u32, d: u8 });
+    }
+
+    #[test]
+    fn cast_to_repr_c_mirror() {
+        #[repr(C)]
+        struct Mirror {
+            o: u8,
+            a: i32,
+            k: u8,
+            c: String,
+            d: Vec<i32>,
+            e: u16,
+        }
+
+        let layout = Arc::new(DynamicTypeLayout::new(
+            "Mirror".into(),
+            &[
+                ("o", &StaticTypeLayout::of::<u8>()),
+                ("a", &StaticTypeLayout::of::<i32>()),
+                ("k", &StaticTypeLayout::of::<u8>()),
+                ("c", &StaticTypeLayout::of::<String>()),
+                ("d", &StaticTypeLayout::of::<Vec<i32>>()),
+                ("e", &StaticTypeLayout::of::<u16>()),
+            ],
+        ));
+
+        let mut instance = DynamicStruct::new(layout);
+        instance.set_field("o", 3u8);
+        instance.set_field("a", -7i32);
+        instance.set_field("k", 9u8);
+        instance.set_field("c", String::from("mirror"));
+        instance.set_field("d", vec![4, 5]);
+        instance.set_field("e", 512u16);
+
+        let mirror = unsafe { instance.cast::<Mirror>() };
+        assert_eq!(mirror.o, 3);
+        assert_eq!(mirror.a, -7);
+        assert_eq!(mirror.k, 9);
+        assert_eq!(mirror.c, "mirror");
+        assert_eq!(mirror.d, vec![4, 5]);
+        assert_eq!(mirror.e, 512);
+    }
+
+    #[test]
+    fn optimized_layout_removes_padding() {
+        let fields: &[(&str, &StaticTypeLayout)] = &[
+            ("a", &StaticTypeLayout::of::<u8>()),
+            ("b", &StaticTypeLayout::of::<f64>()),
+            ("c", &StaticTypeLayout::of::<u8>()),
+            ("d", &StaticTypeLayout::of::<String>()),
+            ("e", &StaticTypeLayout::of::<u16>()),
+            ("f", &StaticTypeLayout::of::<u8>()),
+        ];
+        let repr_c = DynamicTypeLayout::new("Padded".into(), fields);
+        let optimized = DynamicTypeLayout::with_strategy("Packed".into(), fields, LayoutStrategy::Optimized);
+
+        assert_eq!(repr_c.total_size, 56);
+        assert_eq!(optimized.total_size, 40);
+        assert_eq!(optimized.align, repr_c.align);
+        assert_eq!(optimized.strategy, LayoutStrategy::Optimized);
+        for (index, (name, _)) in fields.iter().enumerate() {
+            assert_eq!(optimized.name_to_index[*name], index);
+            assert_eq!(optimized.field_types[index], repr_c.field_types[index]);
+        }
+
+        let mut instance = DynamicStruct::new(Arc::new(optimized));
+        instance.set_field_by_index(1u8, 0);
+        instance.set_field("b", 2.5f64);
+        instance.set_field("d", String::from("packed"));
+        instance.set_field_by_index(4u16, 4);
+        instance.set_field("f", 6u8);
+        assert_eq!(*instance.get_field_ref::<u8>("a"), 1);
+        assert_eq!(*instance.get_field_ref_by_index::<f64>(1), 2.5);
+        assert_eq!(*instance.get_field_ref::<u8>("c"), 0);
+        assert_eq!(instance.get_field_ref::<String>("d"), "packed");
+        assert_eq!(*instance.get_field_ref::<u16>("e"), 4);
+        assert_eq!(*instance.get_field_ref_by_index::<u8>(5), 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "reordered")]
+    fn cast_refuses_optimized_layout() {
+        #[repr(C)]
+        struct Mirror {
+            a: u8,
+            b: u64,
+        }
+
+        let layout = DynamicTypeLayout::with_strategy(
+            "Packed".into(),
+            &[("a", &StaticTypeLayout::of::<u8>()), ("b", &StaticTypeLayout::of::<u64>())],
+            LayoutStrategy::Optimized,
+        );
+        let instance = DynamicStruct::new(Arc::new(layout));
+        let _ = unsafe { instance.cast::<Mirror>() };
+    }
+
+    #[test]
+    #[should_panic(expected = "reordered")]
+    fn cast_refuses_repr_c_layout_over_optimized_base() {
+        #[repr(C)]
+        struct Mirror {
+            a: u8,
+            b: u64,
+            c: u8,
+        }
+
+        let base = Arc::new(DynamicTypeLayout::with_strategy(
+            "Packed".into(),
+            &[("a", &StaticTypeLayout::of::<u8>()), ("b", &StaticTypeLayout::of::<u64>())],
+            LayoutStrategy::Optimized,
+        ));
+        let layout = DynamicTypeLayout::builder("Derived".into())
+            .base(&base)
+            .field("c", &StaticTypeLayout::of::<u8>())
+            .build();
+        assert_eq!(layout.strategy, LayoutStrategy::ReprC);
+        assert!(!layout.is_repr_c());
+        let instance = DynamicStruct::new(Arc::new(layout));
+        let _ = unsafe { instance.cast::<Mirror>() };
+    }
+
+    #[test]
+    fn try_clone_copies_every_field() {
+        let layout = Arc::new(DynamicTypeLayout::new(
+            "Template".into(),
+            &[
+                ("id", &StaticTypeLayout::of::<u32>().with_clone::<u32>()),
+                ("name", &StaticTypeLayout::of::<String>().with_clone::<String>()),
+                ("items", &StaticTypeLayout::of::<Vec<i32>>().with_clone::<Vec<i32>>()),
+                ("shared", &StaticTypeLayout::of::<Arc<u8>>().with_clone::<Arc<u8>>()),
+            ],
+        ));
+        assert!(layout.is_cloneable());
+
+        let mut template = DynamicStruct::new(layout);
+        template.set_field("id", 42u32);
+        template.set_field("name", String::from("template"));
+        template.set_field("items", vec![1, 2, 3]);
+        template.set_field("shared", Arc::new(5u8));
+
+        let mut copy = template.clone();
+        copy.get_field_mut::<Vec<i32>>("items").push(4);
+        copy.set_field("name", String::from("copy"));
+
+        assert_eq!(*copy.get_field_ref::<u32>("id"), 42);
+        assert_eq!(copy.get_field_ref::<String>("name"), "copy");
+        assert_eq!(copy.get_field_ref::<Vec<i32>>("items"), &vec![1, 2, 3, 4]);
+        assert_eq!(template.get_field_ref::<String>("name"), "template");
+        assert_eq!(template.get_field_ref::<Vec<i32>>("items"), &vec![1, 2, 3]);
+        assert_eq!(Arc::strong_count(template.get_field_ref::<Arc<u8>>("shared")), 2);
+    }
+
+    #[test]
+    fn try_clone_fails_without_clone_fn() {
+        let layout = Arc::new(DynamicTypeLayout::new(
+            "Partial".into(),
+            &[
+                ("id", &StaticTypeLayout::of::<u32>().with_clone::<u32>()),
+                ("lock", &StaticTypeLayout::of::<Mutex<u32>>()),
+            ],
+        ));
+        assert!(!layout.is_cloneable());
+
+        let instance = DynamicStruct::new(layout);
+        match instance.try_clone() {
+            Err(DynamicFieldError::FieldNotCloneable { name, .. }) => assert_eq!(name, "lock"),
+            _ => panic!("expected clone to fail"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid type")]
+    fn with_clone_rejects_other_type() {
+        let _ = StaticTypeLayout::of::<u32>().with_clone::<u64>();
+    }
+
+    #[test]
+    fn debug_prints_fields_and_fallback() {
+        let layout = Arc::new(DynamicTypeLayout::new(
+            "Player".into(),
+            &[
+                ("health", &StaticTypeLayout::of::<i32>().with_debug::<i32>()),
+                ("name", &StaticTypeLayout::of::<String>().with_debug::<String>()),
+                ("lock", &StaticTypeLayout::of::<Mutex<u32>>()),
+            ],
+        ));
+
+        let mut instance = DynamicStruct::new(layout);
+        instance.set_field("health", 100i32);
+        instance.set_field("name", String::from("Ash"));
+
+        assert_eq!(
+            format!("{:?}", instance),
+            format!(
+                "Player {{ health: 100, name: \"Ash\", lock: <{}, {} bytes> }}",
+                std::any::type_name::<Mutex<u32>>(),
+                std::mem::size_of::<Mutex<u32>>(),
+            ),
+        );
+    }
+
+    fn hashable<T: Any + Default + Eq + Hash>() -> StaticTypeLayout {
+        StaticTypeLayout::of::<T>().with_eq::<T>().with_hash::<T>()
+    }
+
+    #[test]
+    fn equality_and_hashing_dedupe() {
+        let fields: &[(&str, &StaticTypeLayout)] = &[
+            ("id", &hashable::<u32>()),
+            ("name", &hashable::<String>()),
+            ("tags", &hashable::<Vec<u8>>()),
+        ];
+        let layout = Arc::new(DynamicTypeLayout::new("Item".into(), fields));
+        // Same name and field types in a separate allocation, placed differently.
+        let mirror = Arc::new(DynamicTypeLayout::with_strategy("Item".into(), fields, LayoutStrategy::Optimized));
+        assert!(layout.is_comparable() && layout.is_hashable());
+
+        let create = |layout: &Arc<DynamicTypeLayout>, id: u32, name: &str| {
+            let mut instance = DynamicStruct::new(layout.clone());
+            instance.set_field("id", id);
+            instance.set_field("name", String::from(name));
+            instance.set_field("tags", vec![1u8, 2]);
+            instance
+        };
+
+        let a = create(&layout, 1, "sword");
+        let b = create(&mirror, 1, "sword");
+        let c = create(&layout, 2, "shield");
+        assert_eq!(a.try_eq(&b).ok(), Some(true));
+        assert_eq!(a.try_eq(&c).ok(), Some(false));
+
+        let set: std::collections::HashSet<HashableStruct> =
+            [a, b, c].into_iter().map(|value| HashableStruct::new(value).unwrap()).collect();
+        assert_eq!(set.len(), 2);
+
+        let other = Arc::new(DynamicTypeLayout::new("Other".into(), fields));
+        assert_eq!(create(&layout, 1, "sword").try_eq(&create(&other, 1, "sword")).ok(), Some(false));
+    }
+
+    #[test]
+    fn equality_reports_uncomparable_field() {
+        let layout = Arc::new(DynamicTypeLayout::new(
+            "Locked".into(),
+            &[("id", &hashable::<u32>()), ("lock", &StaticTypeLayout::of::<Mutex<u32>>())],
+        ));
+        assert!(!layout.is_comparable());
+        assert!(!layout.is_hashable());
+
+        let a = DynamicStruct::new(layout.clone());
+        let b = DynamicStruct::new(layout);
+        match a.try_eq(&b) {
+            Err(DynamicFieldError::FieldNotComparable { name, .. }) => assert_eq!(name, "lock"),
+            _ => panic!("expected comparison to fail"),
+        }
+        let mut hasher = ahash::AHasher::default();
+        match a.try_hash(&mut hasher) {
+            Err(DynamicFieldError::FieldNotHashable { name, .. }) => assert_eq!(name, "lock"),
+            _ => panic!("expected hashing to fail"),
+        }
+        match HashableStruct::new(a) {
+            Err(DynamicFieldError::FieldNotComparable { name, .. }) => assert_eq!(name, "lock"),
+            _ => panic!("expected the key to be rejected"),
+        }
+    }
+
+    fn common<T: Any + Default + Clone + fmt::Debug + Eq + Hash>() -> StaticTypeLayout {
+        StaticTypeLayout::of::<T>()
+            .with_clone::<T>()
+            .with_debug::<T>()
+            .with_eq::<T>()
+            .with_hash::<T>()
+    }
+
+    fn inventory_layout() -> Arc<DynamicTypeLayout> {
+        Arc::new(
+            DynamicTypeLayout::builder("Inventory".into())
+                .field("gold", &common::<u32>())
+                .field("items", &common::<Vec<String>>())
+                .build(),
+        )
+    }
+
+    #[test]
+    fn nested_struct_is_stored_inline() {
+        let inventory = inventory_layout();
+        let player = Arc::new(
+            DynamicTypeLayout::builder("Player".into())
+                .field("level", &common::<u8>())
+                .nested("inventory", &inventory)
+                .field("name", &common::<String>())
+                .build(),
+        );
+
+        #[allow(dead_code)]
+        #[repr(C)]
+        struct InventoryMirror {
+            gold: u32,
+            items: Vec<String>,
+        }
+        #[allow(dead_code)]
+        #[repr(C)]
+        struct PlayerMirror {
+            level: u8,
+            inventory: InventoryMirror,
+            name: String,
+        }
+        assert_eq!(player.total_size, std::mem::size_of::<PlayerMirror>());
+        assert_eq!(player.align, std::mem::align_of::<PlayerMirror>());
+        assert_eq!(player.field_offsets[1], std::mem::offset_of!(PlayerMirror, inventory));
+
+        let mut instance = DynamicStruct::new(player);
+        {
+            let mut nested = instance.get_struct_mut("inventory");
+            nested.set_field("gold", 250u32);
+            nested.get_field_mut::<Vec<String>>("items").push(String::from("sword"));
+        }
+        instance.set_field("name", String::from("Ash"));
+
+        let nested = instance.get_struct_ref("inventory");
+        assert!(Arc::ptr_eq(nested.layout(), &inventory));
+        assert_eq!(*nested.get_field_ref::<u32>("gold"), 250);
+        assert_eq!(nested.get_field_ref::<Vec<String>>("items"), &vec![String::from("sword")]);
+        assert!(instance.try_get_field_ref::<u32>("inventory").is_err());
+        assert!(instance.try_get_struct_ref("name").is_err());
+
+        assert_eq!(
+            format!("{:?}", instance),
+            "Player { level: 0, inventory: Inventory { gold: 250, items: [\"sword\"] }, name: \"Ash\" }",
+        );
+
+        let copy = instance.clone();
+        assert_eq!(copy.try_eq(&instance).ok(), Some(true));
+        assert_eq!(copy.get_struct_ref("inventory").clone_field::<Vec<String>>("items").len(), 1);
+
+        let mirror = unsafe { instance.cast::<PlayerMirror>() };
+        assert_eq!(mirror.inventory.gold, 250);
+        assert_eq!(mirror.inventory.items, vec![String::from("sword")]);
+        assert_eq!(mirror.name, "Ash");
+    }
+
+    #[test]
+    fn nested_struct_fields_are_dropped() {
+        let inner = Arc::new(
+            DynamicTypeLayout::builder("Inner".into())
+                .field("shared", &StaticTypeLayout::of::<Arc<u8>>())
+                .build(),
+        );
+        let outer = Arc::new(
+            DynamicTypeLayout::builder("Outer".into())
+                .nested("a", &inner)
+                .nested("b", &inner)
+                .build(),
+        );
+
+        let shared = Arc::new(1u8);
+        let mut instance = DynamicStruct::new(outer);
+        instance.get_struct_mut("a").set_field("shared", shared.clone());
+        instance.get_struct_mut("b").set_field("shared", shared.clone());
+        assert_eq!(Arc::strong_count(&shared), 3);
+
+        match instance.try_clone() {
+            Err(DynamicFieldError::FieldNotCloneable { name, .. }) => assert_eq!(name, "a.shared"),
+            _ => panic!("expected clone to fail"),
+        }
+
+        drop(instance);
+        assert_eq!(Arc::strong_count(&shared), 1);
+    }
+
+    #[test]
+    fn field_handle_reads_and_writes() {
+        let layout = inventory_layout();
+        let gold = layout.field_handle::<u32>("gold").unwrap();
+        let items = layout.field_handle::<Vec<String>>("items").unwrap();
+        assert_eq!(gold.offset(), layout.field_offsets[0]);
+
+        let mut instance = DynamicStruct::new(layout.clone());
+        instance.set(&gold, 50);
+        instance.get_mut(&items).push("sword".into());
+        assert_eq!(*instance.get(&gold), 50);
+        assert_eq!(instance.get_field_ref::<u32>("gold"), &50);
+        assert_eq!(instance.as_ref().get(&items), &["sword".to_owned()]);
+
+        assert!(matches!(
+            layout.field_handle::<i32>("gold"),
+            Err(DynamicFieldError::GetInvalidTypeOfField { .. })
+        ));
+        assert!(matches!(
+            layout.field_handle::<u32>("silver"),
+            Err(DynamicFieldError::GetFieldNameNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn field_handle_rejects_other_layout() {
+        // Identical fields, but a separately built layout.
+        let other = inventory_layout();
+        let gold = inventory_layout().field_handle::<u32>("gold").unwrap();
+        let mut instance = DynamicStruct::new(other);
+
+        assert!(matches!(
+            instance.try_get(&gold),
+            Err(DynamicFieldError::HandleLayoutMismatch { .. })
+        ));
+        assert!(instance.try_get_mut(&gold).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "different layout")]
+    fn field_handle_panics_on_other_layout() {
+        let gold = inventory_layout().field_handle::<u32>("gold").unwrap();
+        let instance = DynamicStruct::new(inventory_layout());
+        instance.get(&gold);
+    }
+
+    #[test]
+    fn try_new_reports_bad_definitions() {
+        let byte = StaticTypeLayout::of::<u8>();
+        assert!(matches!(
+            DynamicTypeLayout::try_new("Twice".into(), &[("a", &byte), ("a", &byte)]),
+            Err(TypeRegistryError::DuplicateFieldName { field, .. }) if field == "a"
+        ));
+        assert!(matches!(
+            DynamicTypeLayout::try_new("Empty".into(), &[]),
+            Err(TypeRegistryError::ZeroSizedLayout { .. })
+        ));
+        assert!(matches!(
+            DynamicTypeLayout::builder("Empty".into()).try_build(),
+            Err(TypeRegistryError::ZeroSizedLayout { .. })
+        ));
+        // Never instantiated, only the size is needed.
+        let mut huge = StaticTypeLayout::of::<u8>();
+        huge.size = usize::MAX / 2;
+        assert!(matches!(
+            DynamicTypeLayout::try_new("Huge".into(), &[("a", &huge), ("b", &byte)]),
+            Err(TypeRegistryError::SizeOverflow { .. })
+        ));
+        assert!(matches!(
+            DynamicTypeLayout::try_new("Huger".into(), &[("a", &huge), ("b", &huge), ("c", &huge)]),
+            Err(TypeRegistryError::SizeOverflow { .. })
+        ));
+        assert!(DynamicTypeLayout::try_new("Fine".into(), &[("a", &byte)]).is_ok());
+    }
+
+    #[test]
+    fn registry_lookups_are_fallible() {
+        let registry = TypeRegistry::default();
+        assert!(matches!(
+            registry.try_create_dynamic("Missing"),
+            Err(TypeRegistryError::UnknownDynamicType { .. })
+        ));
+
+        let byte = StaticTypeLayout::of::<u8>();
+        registry.add_dyn(DynamicTypeLayout::new("Thing".into(), &[("a", &byte)])).unwrap();
+        // Nothing holds the old layout yet, so it can be replaced.
+        registry.add_dyn(DynamicTypeLayout::new("Thing".into(), &[("b", &byte)])).unwrap();
+
+        let instance = registry.try_create_dynamic("Thing").unwrap();
+        assert!(matches!(
+            registry.add_dyn(DynamicTypeLayout::new("Thing".into(), &[("c", &byte)])),
+            Err(TypeRegistryError::LayoutInUse { instances: 1, .. })
+        ));
+        assert!(instance.layout().name_to_index.contains_key("b"));
+
+        drop(instance);
+        registry.add_dyn(DynamicTypeLayout::new("Thing".into(), &[("c", &byte)])).unwrap();
+    }
+
+    /// Small xorshift generator, enough to throw arbitrary input at the `try_*` accessors.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn index(&mut self) -> usize {
+            // Mostly near the field count, sometimes anywhere.
+            match self.next() % 4 {
+                0 => self.next() as usize,
+                1 => usize::MAX - (self.next() % 4) as usize,
+                _ => (self.next() % 8) as usize,
+            }
+        }
+
+        fn name(&mut self) -> std::string::String {
+            const NAMES: &[&str] = &["level", "inventory", "name", "gold", "", "level ", "Level", "inventory.gold", "\u{0}"];
+            let name = NAMES[(self.next() % NAMES.len() as u64) as usize];
+            if self.next().is_multiple_of(4) {
+                format!("{}{}", name, self.next())
+            } else {
+                name.to_owned()
+            }
+        }
+    }
+
+    /// Runs every typed `try_*` accessor for one `T` and checks the result against the layout.
+    fn hammer_typed<T: 'static + Default + Clone>(instance: &mut DynamicStruct, index: usize, name: &str) {
+        let layout = instance.layout().clone();
+        let expected = index < layout.field_types.len() && layout.field_types[index] == TypeId::of::<T>();
+        assert_eq!(instance.try_get_field_ref_by_index::<T>(index).is_ok(), expected);
+        assert_eq!(instance.try_get_field_mut_by_index::<T>(index).is_ok(), expected);
+        assert_eq!(instance.try_clone_field_by_index::<T>(index).is_ok(), expected);
+        assert_eq!(instance.try_set_field_by_index(T::default(), index).is_ok(), expected);
+        assert_eq!(layout.field_handle_by_index::<T>(index).is_ok(), expected);
+        assert_eq!(instance.as_ref().try_get_field_ref_by_index::<T>(index).is_ok(), expected);
+
+        let expected = layout
+            .name_to_index
+            .get(name)
+            .is_some_and(|index| layout.field_types[*index] == TypeId::of::<T>());
+        assert_eq!(instance.try_get_field_ref::<T>(name).is_ok(), expected);
+        assert_eq!(instance.try_get_field_mut::<T>(name).is_ok(), expected);
+        assert_eq!(instance.try_clone_field::<T>(name).is_ok(), expected);
+        assert_eq!(instance.try_set_field(name, T::default()).is_ok(), expected);
+        assert_eq!(layout.field_handle::<T>(name).is_ok(), expected);
+    }
+
+    #[test]
+    fn try_accessors_never_panic() {
+        let inventory = inventory_layout();
+        let player = Arc::new(
+            DynamicTypeLayout::builder("Player".into())
+                .field("level", &common::<u8>())
+                .nested("inventory", &inventory)
+                .field("name", &common::<String>())
+                .build(),
+        );
+        let mut instance = DynamicStruct::new(player.clone());
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+
+        for _ in 0..2000 {
+            let index = rng.index();
+            let name = rng.name();
+            hammer_typed::<u8>(&mut instance, index, &name);
+            hammer_typed::<u32>(&mut instance, index, &name);
+            hammer_typed::<String>(&mut instance, index, &name);
+            hammer_typed::<Vec<String>>(&mut instance, index, &name);
+            hammer_typed::<f32>(&mut instance, index, &name);
+
+            let is_nested = matches!(player.field_kinds.get(index), Some(FieldKind::Dynamic(_)));
+            let is_static = matches!(player.field_kinds.get(index), Some(FieldKind::Static(_)));
+            assert_eq!(instance.try_get_struct_ref_by_index(index).is_ok(), is_nested);
+            assert_eq!(instance.try_get_struct_mut_by_index(index).is_ok(), is_nested);
+            assert_eq!(instance.as_ref().try_get_field_any_by_index(index).is_ok(), is_static);
+            assert_eq!(instance.as_mut().try_get_field_any_mut_by_index(index).is_ok(), is_static);
+
+            let is_nested = matches!(
+                player.name_to_index.get(name.as_str()).map(|index| &player.field_kinds[*index]),
+                Some(FieldKind::Dynamic(_))
+            );
+            assert_eq!(instance.try_get_struct_ref(&name).is_ok(), is_nested);
+            assert_eq!(instance.try_get_struct_mut(&name).is_ok(), is_nested);
+        }
+
+        // The struct is still intact after all of that.
+        assert!(instance.try_clone().is_ok());
+        assert_eq!(instance.try_eq(&DynamicStruct::new(player)).ok(), Some(true));
+    }
+
+    #[test]
+    fn layout_buffers_only_open_with_their_layout() {
+        let layout = inventory_layout();
+        let other = inventory_layout();
+        let mut buffer = LayoutBuffer::new(layout.clone());
+        assert_eq!(buffer.layout_id(), layout.id());
+
+        layout.buffer_mut(&mut buffer).set_field("gold", 3u32);
+        assert_eq!(layout.buffer_ref(&buffer).get_field_ref::<u32>("gold"), &3);
+
+        // A structurally identical layout is still a different layout.
+        assert!(matches!(
+            other.try_buffer_ref(&buffer),
+            Err(DynamicFieldError::BufferLayoutMismatch { .. })
+        ));
+        assert!(other.try_buffer_mut(&mut buffer).is_err());
+
+        buffer.reset();
+        assert_eq!(layout.buffer_ref(&buffer).get_field_ref::<u32>("gold"), &0);
+    }
+
+    #[test]
+    #[should_panic(expected = "initialised by")]
+    fn layout_buffer_panics_with_another_layout() {
+        let buffer = LayoutBuffer::new(inventory_layout());
+        inventory_layout().buffer_ref(&buffer);
+    }
+
+    fn message_layout() -> DynamicEnumLayout {
+        let chat = Arc::new(
+            DynamicTypeLayout::builder("Chat".into())
+                .field("channel", &common::<u8>())
+                .field("text", &common::<String>())
+                .build(),
+        );
+        let moved = Arc::new(
+            DynamicTypeLayout::builder("Move".into())
+                .field("x", &StaticTypeLayout::of::<f32>())
+                .field("y", &StaticTypeLayout::of::<f32>())
+                .field("shared", &StaticTypeLayout::of::<Arc<u8>>())
+                .build(),
+        );
+        DynamicEnumLayout::builder("Message".into())
+            .variant("Chat", &chat)
+            .variant("Move", &moved)
+            .unit_variant("Ping")
+            .build()
+    }
+
+    #[test]
+    fn enum_layout_matches_repr_c_enum() {
+        #[allow(dead_code)]
+        #[repr(C, u32)]
+        enum Mirror {
+            Chat { channel: u8, text: String },
+            Move { x: f32, y: f32, shared: Arc<u8> },
+            Ping,
+        }
+
+        let layout = message_layout();
+        assert_eq!(layout.total_size, std::mem::size_of::<Mirror>());
+        assert_eq!(layout.align, std::mem::align_of::<Mirror>());
+
+        assert!(matches!(
+            DynamicEnumLayout::builder("Empty".into()).try_build(),
+            Err(TypeRegistryError::NoVariants { .. })
+        ));
+        assert!(matches!(
+            DynamicEnumLayout::builder("Twice".into()).unit_variant("A").unit_variant("A").try_build(),
+            Err(TypeRegistryError::DuplicateVariantName { variant, .. }) if variant == "A"
+        ));
+    }
+
+    #[test]
+    fn enum_switches_and_drops_active_variant() {
+        let registry = TypeRegistry::default();
+        registry.add_dyn_enum(message_layout()).unwrap();
+        let mut message = registry.create_dynamic_enum("Message");
+
+        assert_eq!(message.active_variant_name(), "Chat");
+        message.set_field("text", String::from("hello"));
+        assert_eq!(message.get_field_ref::<String>("text"), "hello");
+        assert_eq!(format!("{:?}", message), r#"Message::Chat { channel: 0, text: "hello" }"#);
+
+        let shared = Arc::new(0u8);
+        message.set_variant("Move");
+        assert!(message.is_variant("Move"));
+        assert!(message.try_get_field_ref::<String>("text").is_err());
+        message.set_field("shared", shared.clone());
+        *message.get_field_mut::<f32>("x") = 2.0;
+        assert_eq!(message.try_variant("Move").unwrap().get_field_ref::<f32>("x"), &2.0);
+        assert!(matches!(
+            message.try_variant("Chat"),
+            Err(DynamicFieldError::VariantNotActive { .. })
+        ));
+        assert_eq!(Arc::strong_count(&shared), 2);
+
+        message.set_variant("Ping");
+        assert_eq!(Arc::strong_count(&shared), 1);
+        assert!(matches!(
+            message.try_set_variant("Pong"),
+            Err(DynamicFieldError::VariantNameNotFound { .. })
+        ));
+
+        message.set_variant("Move");
+        message.set_field("shared", shared.clone());
+        assert!(registry.add_dyn_enum(message_layout()).is_err());
+        drop(message);
+        assert_eq!(Arc::strong_count(&shared), 1);
+        assert!(registry.try_create_dynamic_enum("Missing").is_err());
+    }
+
+    #[test]
+    fn structs_and_enums_share_one_namespace() {
+        let registry = TypeRegistry::default();
+        registry.add_dyn_enum(message_layout()).unwrap();
+        let layout = DynamicTypeLayout::builder("Message".into())
+            .field("id", &common::<u8>())
+            .build();
+        assert!(matches!(
+            registry.add_dyn(layout),
+            Err(TypeRegistryError::DuplicateTypeName { name }) if name == "Message"
+        ));
+        assert!(registry.get_dynamic_layout("Message").is_none());
+
+        let registry = TypeRegistry::default();
+        registry
+            .add_dyn(DynamicTypeLayout::builder("Message".into()).field("id", &common::<u8>()).build())
+            .unwrap();
+        assert!(matches!(
+            registry.add_dyn_enum(message_layout()),
+            Err(TypeRegistryError::DuplicateTypeName { .. })
+        ));
+        assert!(registry.get_dynamic_enum_layout("Message").is_none());
+    }
+
+    #[test]
+    fn array_fields_are_stored_inline() {
+        let inventory = inventory_layout();
+        let layout = Arc::new(
+            DynamicTypeLayout::builder("Shop".into())
+                .field("id", &common::<u8>())
+                .array("prices", &common::<i32>(), 16)
+                .nested_array("stock", &inventory, 2)
+                .array("tags", &common::<String>(), 3)
+                .build(),
+        );
+
+        #[allow(dead_code)]
+        #[repr(C)]
+        struct InventoryMirror {
+            gold: u32,
+            items: Vec<String>,
+        }
+        #[allow(dead_code)]
+        #[repr(C)]
+        struct ShopMirror {
+            id: u8,
+            prices: [i32; 16],
+            stock: [InventoryMirror; 2],
+            tags: [String; 3],
+        }
+        assert_eq!(layout.total_size, std::mem::size_of::<ShopMirror>());
+        assert_eq!(layout.align, std::mem::align_of::<ShopMirror>());
+        assert_eq!(layout.field_offsets[1], std::mem::offset_of!(ShopMirror, prices));
+        assert_eq!(layout.field_offsets[2], std::mem::offset_of!(ShopMirror, stock));
+        assert_eq!(layout.field_offsets[3], std::mem::offset_of!(ShopMirror, tags));
+
+        let mut shop = DynamicStruct::new(layout.clone());
+        assert_eq!(shop.get_array_ref::<i32>("prices"), &[0; 16]);
+        shop.get_array_mut::<i32>("prices")[3] = 15;
+        shop.get_array_mut::<String>("tags")[2].push_str("sale");
+        {
+            let mut stock = shop.get_struct_array_mut("stock");
+            assert_eq!(stock.len(), 2);
+            stock.get_mut(1).unwrap().set_field("gold", 7u32);
+            assert!(stock.get_mut(2).is_none());
+        }
+        let stock = shop.get_struct_array_ref("stock");
+        assert_eq!(stock.iter().map(|item| *item.get_field_ref::<u32>("gold")).collect::<Vec<_>>(), [0, 7]);
+
+        assert!(matches!(
+            shop.try_get_array_ref::<u32>("prices"),
+            Err(DynamicFieldError::GetInvalidTypeOfField { .. })
+        ));
+        assert!(shop.try_get_array_ref::<u8>("id").is_err());
+        assert!(shop.try_get_struct_array_ref("prices").is_err());
+        assert!(shop.try_get_field_ref::<i32>("prices").is_err());
+
+        let copy = shop.clone();
+        assert_eq!(copy.try_eq(&shop).ok(), Some(true));
+        assert_eq!(copy.get_array_ref::<i32>("prices")[3], 15);
+        assert!(format!("{:?}", copy).contains(r#"tags: ["", "", "sale"]"#));
+    }
+
+    #[test]
+    fn array_elements_are_dropped() {
+        let layout = Arc::new(
+            DynamicTypeLayout::builder("Holder".into())
+                .array("shared", &StaticTypeLayout::of::<Arc<u8>>(), 4)
+                .build(),
+        );
+        let shared = Arc::new(0u8);
+        let mut instance = DynamicStruct::new(layout.clone());
+        for slot in instance.get_array_mut::<Arc<u8>>("shared") {
+            *slot = shared.clone();
+        }
+        assert_eq!(Arc::strong_count(&shared), 5);
+        match instance.try_clone() {
+            Err(DynamicFieldError::FieldNotCloneable { name, .. }) => assert_eq!(name, "shared[0]"),
+            _ => panic!("expected clone to fail"),
+        }
+        drop(instance);
+        assert_eq!(Arc::strong_count(&shared), 1);
+    }
+
+    #[test]
+    fn field_defaults_override_type_defaults() {
+        let class = Arc::new(
+            DynamicTypeLayout::builder("Warrior".into())
+                .field_with_default("health", &common::<i32>(), 100)
+                .field_with_default_fn("name", &common::<String>(), || String::from("Conan"))
+                .field("level", &common::<u8>())
+                .build(),
+        );
+        let party = Arc::new(
+            DynamicTypeLayout::builder("Party".into())
+                .nested("leader", &class)
+                .nested_array("members", &class, 2)
+                .build(),
+        );
+
+        let warrior = DynamicStruct::new(class.clone());
+        assert_eq!(warrior.get_field_ref::<i32>("health"), &100);
+        assert_eq!(warrior.get_field_ref::<String>("name"), "Conan");
+        assert_eq!(warrior.get_field_ref::<u8>("level"), &0);
+
+        let party = DynamicStruct::new(party);
+        assert_eq!(party.get_struct_ref("leader").get_field_ref::<i32>("health"), &100);
+        let members = party.get_struct_array_ref("members");
+        assert!(members.iter().all(|member| member.get_field_ref::<String>("name") == "Conan"));
+        assert_eq!(party.try_clone().unwrap().try_eq(&party).ok(), Some(true));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid type")]
+    fn field_default_must_match_layout() {
+        DynamicTypeLayout::builder("Wrong".into()).field_with_default("health", &common::<i32>(), 100u8);
+    }
+
+    #[test]
+    fn prototypes_are_cloned_on_create() {
+        let registry = TypeRegistry::default();
+        registry.add_dyn(DynamicTypeLayout::new(
+            "Goblin".into(),
+            &[("health", &common::<i32>()), ("loot", &common::<Vec<String>>())],
+        ))
+        .unwrap();
+
+        let mut prototype = registry.create_dynamic("Goblin");
+        prototype.set_field("health", 30);
+        prototype.get_field_mut::<Vec<String>>("loot").push("dagger".into());
+        assert!(matches!(
+            registry.try_create_from_prototype("Goblin"),
+            Err(TypeRegistryError::NoPrototype { .. })
+        ));
+        registry.add_prototype(prototype).unwrap();
+
+        let mut goblin = registry.create_dynamic("Goblin");
+        assert_eq!(goblin.get_field_ref::<i32>("health"), &30);
+        goblin.get_field_mut::<Vec<String>>("loot").clear();
+        let other = registry.create_from_prototype("Goblin");
+        assert_eq!(other.get_field_ref::<Vec<String>>("loot"), &["dagger".to_owned()]);
+
+        // The prototype holds the layout, so it has to go before the layout can be replaced.
+        drop((goblin, other));
+        let replacement = || DynamicTypeLayout::new("Goblin".into(), &[("health", &common::<i32>())]);
+        assert!(registry.add_dyn(replacement()).is_err());
+        assert!(registry.remove_prototype("Goblin").is_some());
+        registry.add_dyn(replacement()).unwrap();
+    }
+
+    #[test]
+    fn prototypes_are_validated() {
+        let registry = TypeRegistry::default();
+        let plain = Arc::new(DynamicTypeLayout::new("Plain".into(), &[("id", &StaticTypeLayout::of::<u32>())]));
+        assert!(matches!(
+            registry.add_prototype(DynamicStruct::new(plain.clone())),
+            Err(TypeRegistryError::UnknownDynamicType { .. })
+        ));
+
+        registry.add_dyn(DynamicTypeLayout::new("Plain".into(), &[("id", &StaticTypeLayout::of::<u32>())])).unwrap();
+        assert!(matches!(
+            registry.add_prototype(DynamicStruct::new(plain)),
+            Err(TypeRegistryError::PrototypeLayoutMismatch { .. })
+        ));
+        match registry.add_prototype(registry.create_dynamic("Plain")) {
+            Err(TypeRegistryError::PrototypeNotCloneable { field, .. }) => assert_eq!(field, "id"),
+            _ => panic!("expected an uncloneable prototype"),
+        }
+    }
+
+    #[test]
+    fn derived_layouts_start_with_base_fields() {
+        let object = Arc::new(
+            DynamicTypeLayout::builder("CoreObject".into())
+                .field("id", &common::<u64>())
+                .field("flags", &common::<u8>())
+                .build(),
+        );
+        let actor = Arc::new(
+            DynamicTypeLayout::builder("Actor".into())
+                .base(&object)
+                .field_with_default("health", &common::<i32>(), 100)
+                .build(),
+        );
+        let player = Arc::new(
+            DynamicTypeLayout::builder("Player".into())
+                .base(&actor)
+                .field("name", &common::<String>())
+                .build(),
+        );
+
+        #[allow(dead_code)]
+        #[repr(C)]
+        struct ObjectMirror {
+            id: u64,
+            flags: u8,
+        }
+        #[allow(dead_code)]
+        #[repr(C)]
+        struct ActorMirror {
+            base: ObjectMirror,
+            health: i32,
+        }
+        assert_eq!(actor.field_offsets[2], std::mem::offset_of!(ActorMirror, health));
+        assert_eq!(actor.total_size, std::mem::size_of::<ActorMirror>());
+        assert_eq!(player.field_names, ["id", "flags", "health", "name"]);
+
+        let registry = TypeRegistry::default();
+        registry
+            .add_dyn(DynamicTypeLayout::builder("Player".into()).base(&actor).build())
+            .unwrap();
+        assert!(registry.is_a("Player", "CoreObject"));
+        assert!(registry.is_a("Player", "Player"));
+        assert!(!registry.is_a("Player", "Item"));
+
+        let mut instance = DynamicStruct::new(player.clone());
+        instance.set_field("id", 42u64);
+        assert_eq!(instance.get_field_ref::<i32>("health"), &100);
+        instance.as_base_mut("Actor").set_field("health", 5);
+        assert_eq!(instance.get_field_ref::<i32>("health"), &5);
+
+        let object_view = instance.as_base("CoreObject");
+        assert_eq!(object_view.layout().name, "CoreObject");
+        assert_eq!(object_view.get_field_ref::<u64>("id"), &42);
+        assert!(object_view.try_get_field_ref::<i32>("health").is_err());
+        assert!(matches!(
+            instance.try_as_base("Item"),
+            Err(DynamicFieldError::NotDerivedFrom { .. })
+        ));
+
+        assert!(matches!(
+            DynamicTypeLayout::builder("Bad".into()).base(&actor).field("id", &common::<u64>()).try_build(),
+            Err(TypeRegistryError::ShadowedField { field, base, .. }) if field == "id" && base == "CoreObject"
+        ));
+        assert!(matches!(
+            DynamicTypeLayout::builder("Bad".into()).base(&player).field("health", &common::<i32>()).try_build(),
+            Err(TypeRegistryError::ShadowedField { field, base, .. }) if field == "health" && base == "Actor"
+        ));
+    }
+
+    #[test]
+    fn fields_enumerate_every_kind() {
+        let inventory = inventory_layout();
+        let layout = Arc::new(
+            DynamicTypeLayout::builder("Player".into())
+                .field("level", &common::<u8>())
+                .nested("inventory", &inventory)
+                .array("scores", &common::<i32>(), 3)
+                .field("name", &common::<String>())
+                .strategy(LayoutStrategy::Optimized)
+                .build(),
+        );
+        let mut player = DynamicStruct::new(layout);
+
+        for mut field in player.fields_mut() {
+            if let Some(level) = field.value.downcast_mut::<u8>() {
+                *level = 9;
+            }
+            match field.value {
+                FieldValueMut::Struct(mut inventory) => inventory.set_field("gold", 12u32),
+                FieldValueMut::Array(mut scores) => {
+                    for index in 0..scores.len() {
+                        *scores.get_mut(index).unwrap().downcast_mut::<i32>().unwrap() = index as i32;
+                    }
+                }
+                FieldValueMut::Value(value) => {
+                    if let Some(name) = value.downcast_mut::<String>() {
+                        name.push_str("Ash");
+                    }
+                }
+            }
+        }
+
+        let fields: Vec<_> = player.fields().collect();
+        assert_eq!(fields.iter().map(|field| field.name).collect::<Vec<_>>(), ["level", "inventory", "scores", "name"]);
+        assert_eq!(fields[0].type_id, TypeId::of::<u8>());
+        assert_eq!(fields[0].type_name, std::any::type_name::<u8>());
+        assert_eq!(fields[0].value.downcast_ref::<u8>(), Some(&9));
+        assert_eq!(fields[3].value.downcast_ref::<String>().map(|name| name.as_str()), Some("Ash"));
+        assert!(fields[3].value.downcast_ref::<u8>().is_none());
+        match fields[1].value {
+            FieldValue::Struct(inventory) => assert_eq!(inventory.get_field_ref::<u32>("gold"), &12),
+            _ => panic!("expected a nested struct"),
+        }
+        match fields[2].value {
+            FieldValue::Array(scores) => {
+                let scores: Vec<_> = scores.iter().map(|score| *score.downcast_ref::<i32>().unwrap()).collect();
+                assert_eq!(scores, [0, 1, 2]);
+            }
+            _ => panic!("expected an array"),
+        }
+        assert_eq!(player.get_array_ref::<i32>("scores"), &[0, 1, 2]);
     }
 }
@@ -0,0 +1,511 @@
+//! A compact binary encoding of dynamic structs for network sync.
+//!
+//! The encoding carries no field names or type information, both sides must share the layout. Fields
+//! are written in declaration order, nested structs inline and arrays element by element. Primitives
+//! are fixed-width little-endian, `usize`/`isize` are widened to 64 bits, strings and `Vec`s are
+//! prefixed with their length as a LEB128 varint and `Option`s with a presence byte.
+
+use std::{any::{Any, TypeId}, sync::Arc};
+
+use ahash::AHashMap;
+use smartstring::alias::String;
+use thiserror::Error;
+
+use super::{DynamicStruct, DynamicTypeLayout, FieldKind, TypeRegistry};
+
+#[derive(Debug, Error)]
+pub enum CodecError {
+    #[error("No codec registered for field {field} of type {type_name}.")]
+    NoCodec {
+        field: String,
+        type_name: String
+    },
+    #[error("Input ended early, {needed} bytes needed but only {remaining} remain.")]
+    UnexpectedEof {
+        needed: usize,
+        remaining: usize
+    },
+    #[error("Input is not a valid encoding of {type_name}.")]
+    Corrupt {
+        type_name: &'static str
+    },
+    #[error("{remaining} bytes left over after decoding {layout}.")]
+    TrailingBytes {
+        layout: String,
+        remaining: usize
+    }
+}
+
+/// Types with a binary encoding, captured for dynamic fields with `TypeRegistry::add_codec`.
+///
+/// Every encoding must take at least one byte, decoders rely on it to reject lengths longer than the
+/// remaining input before allocating.
+pub trait BinaryCodec: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// Decodes a value from the front of `input` and advances it past the bytes read.
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError>;
+}
+
+/// Appends the value at the pointer to the buffer with its `BinaryCodec` implementation.
+pub type EncodeFn = unsafe fn(*const u8, &mut Vec<u8>);
+/// Replaces the initialised value at the pointer with one decoded from the front of the input.
+pub type DecodeFn = unsafe fn(&mut &[u8], *mut u8) -> Result<(), CodecError>;
+
+pub(super) struct Codec {
+    encode: EncodeFn,
+    decode: DecodeFn,
+}
+
+/// # Safety
+/// `src` must point to a valid `T`.
+unsafe fn encode_field<T: BinaryCodec>(src: *const u8, out: &mut Vec<u8>) {
+    (*src.cast::<T>()).encode(out)
+}
+
+/// # Safety
+/// `dst` must point to a valid `T`, which is dropped and replaced.
+unsafe fn decode_field<T: BinaryCodec>(input: &mut &[u8], dst: *mut u8) -> Result<(), CodecError> {
+    *dst.cast::<T>() = T::decode(input)?;
+    Ok(())
+}
+
+impl TypeRegistry {
+    /// Registers `T`'s `BinaryCodec` implementation, used for every field of type `T`.
+    pub fn add_codec<T: Any + BinaryCodec>(&self) {
+        self.codecs.write().insert(
+            TypeId::of::<T>(),
+            Codec { encode: encode_field::<T>, decode: decode_field::<T> },
+        );
+    }
+
+    /// Registers the codecs of every primitive and both string types. `Vec`s, `Option`s and other
+    /// containers have to be registered per element type with `TypeRegistry::add_codec`.
+    pub fn add_default_codecs(&self) {
+        macro_rules! add_codecs {
+            ($($ty:ty),* $(,)?) => {
+                $(self.add_codec::<$ty>();)*
+            };
+        }
+
+        add_codecs!(
+            bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64,
+            std::string::String, smartstring::alias::String,
+        );
+    }
+
+    /// Appends the encoding of `value` to `out`. Fails without writing anything if any field's type
+    /// has no registered codec.
+    pub fn encode(&self, value: &DynamicStruct, out: &mut Vec<u8>) -> Result<(), CodecError> {
+        let start = out.len();
+        let result = encode_struct(&self.codecs.read(), &value.type_layout, &value.data, out);
+        if result.is_err() {
+            out.truncate(start);
+        }
+        result
+    }
+
+    /// Decodes a struct of `layout` from all of `input`, failing if the input is truncated, corrupt
+    /// or longer than the encoding.
+    pub fn decode(&self, layout: &Arc<DynamicTypeLayout>, mut input: &[u8]) -> Result<DynamicStruct, CodecError> {
+        let mut value = DynamicStruct::new(layout.clone());
+        decode_struct(&self.codecs.read(), layout, &mut value.data, &mut input)?;
+        if !input.is_empty() {
+            return Err(CodecError::TrailingBytes { layout: layout.name.clone(), remaining: input.len() });
+        }
+        Ok(value)
+    }
+}
+
+fn encode_struct(
+    codecs: &AHashMap<TypeId, Codec>,
+    layout: &DynamicTypeLayout,
+    data: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<(), CodecError> {
+    for (index, kind) in layout.field_kinds.iter().enumerate() {
+        let offset = layout.field_offsets[index];
+        encode_kind(codecs, &layout.field_names[index], kind, &data[offset..offset + kind.size()], out)?;
+    }
+    Ok(())
+}
+
+fn encode_kind(
+    codecs: &AHashMap<TypeId, Codec>,
+    name: &str,
+    kind: &FieldKind,
+    data: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<(), CodecError> {
+    match kind {
+        FieldKind::Static(layout) => {
+            let codec = codecs.get(&layout.type_id).ok_or_else(|| CodecError::NoCodec {
+                field: name.into(),
+                type_name: layout.name.into(),
+            })?;
+            unsafe { (codec.encode)(data.as_ptr(), out) };
+            Ok(())
+        }
+        FieldKind::Dynamic(layout) => encode_struct(codecs, layout, data, out),
+        FieldKind::Array { element, len } => {
+            let size = element.size();
+            for index in 0..*len {
+                encode_kind(codecs, name, element, &data[index * size..(index + 1) * size], out)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn decode_struct(
+    codecs: &AHashMap<TypeId, Codec>,
+    layout: &DynamicTypeLayout,
+    data: &mut [u8],
+    input: &mut &[u8],
+) -> Result<(), CodecError> {
+    for (index, kind) in layout.field_kinds.iter().enumerate() {
+        let offset = layout.field_offsets[index];
+        decode_kind(codecs, &layout.field_names[index], kind, &mut data[offset..offset + kind.size()], input)?;
+    }
+    Ok(())
+}
+
+fn decode_kind(
+    codecs: &AHashMap<TypeId, Codec>,
+    name: &str,
+    kind: &FieldKind,
+    data: &mut [u8],
+    input: &mut &[u8],
+) -> Result<(), CodecError> {
+    match kind {
+        FieldKind::Static(layout) => {
+            let codec = codecs.get(&layout.type_id).ok_or_else(|| CodecError::NoCodec {
+                field: name.into(),
+                type_name: layout.name.into(),
+            })?;
+            unsafe { (codec.decode)(input, data.as_mut_ptr()) }
+        }
+        FieldKind::Dynamic(layout) => decode_struct(codecs, layout, data, input),
+        FieldKind::Array { element, len } => {
+            let size = element.size();
+            for index in 0..*len {
+                decode_kind(codecs, name, element, &mut data[index * size..(index + 1) * size], input)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Splits `len` bytes off the front of `input`.
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], CodecError> {
+    if input.len() < len {
+        return Err(CodecError::UnexpectedEof { needed: len, remaining: input.len() });
+    }
+    let (head, tail) = input.split_at(len);
+    *input = tail;
+    Ok(head)
+}
+
+fn take_array<const N: usize>(input: &mut &[u8]) -> Result<[u8; N], CodecError> {
+    let mut bytes = [0; N];
+    bytes.copy_from_slice(take(input, N)?);
+    Ok(bytes)
+}
+
+fn encode_len(len: usize, out: &mut Vec<u8>) {
+    let mut value = len as u64;
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Decodes a length and checks the input has at least that many bytes left, as every element takes
+/// at least one.
+fn decode_len(input: &mut &[u8]) -> Result<usize, CodecError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let [byte] = take_array(input)?;
+        if shift == 63 && byte > 1 {
+            break;
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            let len = usize::try_from(value).map_err(|_| CodecError::Corrupt { type_name: "length" })?;
+            if len > input.len() {
+                return Err(CodecError::UnexpectedEof { needed: len, remaining: input.len() });
+            }
+            return Ok(len);
+        }
+    }
+    Err(CodecError::Corrupt { type_name: "length" })
+}
+
+macro_rules! le_codec {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl BinaryCodec for $ty {
+                #[inline]
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                #[inline]
+                fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+                    Ok(<$ty>::from_le_bytes(take_array(input)?))
+                }
+            }
+        )*
+    };
+}
+
+le_codec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl BinaryCodec for usize {
+    #[inline]
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        usize::try_from(u64::decode(input)?).map_err(|_| CodecError::Corrupt { type_name: "usize" })
+    }
+}
+
+impl BinaryCodec for isize {
+    #[inline]
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        isize::try_from(i64::decode(input)?).map_err(|_| CodecError::Corrupt { type_name: "isize" })
+    }
+}
+
+impl BinaryCodec for bool {
+    #[inline]
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        match take_array(input)? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(CodecError::Corrupt { type_name: "bool" }),
+        }
+    }
+}
+
+impl BinaryCodec for char {
+    #[inline]
+    fn encode(&self, out: &mut Vec<u8>) {
+        u32::from(*self).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        char::from_u32(u32::decode(input)?).ok_or(CodecError::Corrupt { type_name: "char" })
+    }
+}
+
+impl BinaryCodec for std::string::String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        let len = decode_len(input)?;
+        let bytes = take(input, len)?;
+        std::str::from_utf8(bytes)
+            .map(Into::into)
+            .map_err(|_| CodecError::Corrupt { type_name: "String" })
+    }
+}
+
+impl BinaryCodec for smartstring::alias::String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        let len = decode_len(input)?;
+        let bytes = take(input, len)?;
+        std::str::from_utf8(bytes)
+            .map(Into::into)
+            .map_err(|_| CodecError::Corrupt { type_name: "String" })
+    }
+}
+
+impl<T: BinaryCodec> BinaryCodec for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        for element in self {
+            element.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        let len = decode_len(input)?;
+        (0..len).map(|_| T::decode(input)).collect()
+    }
+}
+
+impl<T: BinaryCodec> BinaryCodec for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                out.push(1);
+                value.encode(out);
+            }
+            None => out.push(0),
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        match take_array(input)? {
+            [0] => Ok(None),
+            [1] => T::decode(input).map(Some),
+            _ => Err(CodecError::Corrupt { type_name: "Option" }),
+        }
+    }
+}
+
+/// Boxes own their value, so they are encoded as the value itself.
+impl<T: BinaryCodec> BinaryCodec for Box<T> {
+    #[inline]
+    fn encode(&self, out: &mut Vec<u8>) {
+        (**self).encode(out);
+    }
+
+    #[inline]
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        T::decode(input).map(Box::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamic_types::StaticTypeLayout;
+
+    fn registry() -> TypeRegistry {
+        let registry = TypeRegistry::default();
+        registry.add_default_codecs();
+        registry.add_codec::<Vec<std::string::String>>();
+        registry.add_codec::<Option<u16>>();
+        registry
+    }
+
+    /// `Packet { sender, tags, header: Header { seq, ack }, slots: [Option<u16>; 2] }`, codecs
+    /// live in the registry so the fields need no more than their plain layouts.
+    fn packet_layout() -> Arc<DynamicTypeLayout> {
+        let header = Arc::new(
+            DynamicTypeLayout::builder("Header".into())
+                .field("seq", &StaticTypeLayout::of::<u16>())
+                .field("ack", &StaticTypeLayout::of::<bool>())
+                .build(),
+        );
+        Arc::new(
+            DynamicTypeLayout::builder("Packet".into())
+                .field("sender", &StaticTypeLayout::of::<smartstring::alias::String>())
+                .field("tags", &StaticTypeLayout::of::<Vec<std::string::String>>())
+                .nested("header", &header)
+                .array("slots", &StaticTypeLayout::of::<Option<u16>>(), 2)
+                .build(),
+        )
+    }
+
+    fn packet(layout: &Arc<DynamicTypeLayout>) -> DynamicStruct {
+        let mut packet = DynamicStruct::new(layout.clone());
+        packet.set_field("sender", smartstring::alias::String::from("Bob"));
+        packet.set_field("tags", vec!["hi".to_string()]);
+        packet.get_struct_mut("header").set_field("seq", 0x0102u16);
+        packet.get_struct_mut("header").set_field("ack", true);
+        packet.get_array_mut::<Option<u16>>("slots")[1] = Some(7);
+        packet
+    }
+
+    /// The encoding of `packet`, with the header's `ack` at 10 and the first slot's tag at 11.
+    #[rustfmt::skip]
+    const PACKET: [u8; 15] = [
+        3, b'B', b'o', b'b',
+        1, 2, b'h', b'i',
+        0x02, 0x01, 1,
+        0, 1, 7, 0,
+    ];
+
+    #[test]
+    fn encodes_fields_in_declaration_order() {
+        let layout = packet_layout();
+        let mut out = Vec::new();
+        registry().encode(&packet(&layout), &mut out).unwrap();
+        assert_eq!(out, PACKET);
+
+        let decoded = registry().decode(&layout, &out).unwrap();
+        assert_eq!(decoded.get_field_ref::<smartstring::alias::String>("sender"), "Bob");
+        assert_eq!(decoded.get_field_ref::<Vec<std::string::String>>("tags"), &["hi"]);
+        assert_eq!(*decoded.get_struct_ref("header").get_field_ref::<u16>("seq"), 0x0102);
+        assert!(*decoded.get_struct_ref("header").get_field_ref::<bool>("ack"));
+        assert_eq!(decoded.get_array_ref::<Option<u16>>("slots"), &[None, Some(7)]);
+    }
+
+    #[test]
+    fn lengths_are_varints() {
+        for (len, bytes) in [(0, &[0][..]), (127, &[0x7f]), (128, &[0x80, 0x01]), (300, &[0xac, 0x02])] {
+            let mut out = Vec::new();
+            encode_len(len, &mut out);
+            assert_eq!(out, bytes);
+
+            let input = [bytes, &vec![0; len][..]].concat();
+            let mut input = &input[..];
+            assert_eq!(decode_len(&mut input).unwrap(), len);
+        }
+
+        let mut input = &[0xff; 11][..];
+        assert!(matches!(decode_len(&mut input), Err(CodecError::Corrupt { .. })));
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let layout = packet_layout();
+        for len in 0..PACKET.len() {
+            let err = registry().decode(&layout, &PACKET[..len]).unwrap_err();
+            assert!(matches!(err, CodecError::UnexpectedEof { .. }), "{} bytes: {}", len, err);
+        }
+
+        let err = registry().decode(&layout, &[&PACKET[..], &[0]].concat()).unwrap_err();
+        assert!(matches!(err, CodecError::TrailingBytes { remaining: 1, .. }), "{}", err);
+    }
+
+    #[test]
+    fn corrupt_input_is_an_error() {
+        let layout = packet_layout();
+        let corrupt = |at: usize, byte: u8| {
+            let mut input = PACKET;
+            input[at] = byte;
+            registry().decode(&layout, &input).unwrap_err()
+        };
+        assert!(matches!(corrupt(1, 0xff), CodecError::Corrupt { type_name: "String" }));
+        assert!(matches!(corrupt(10, 2), CodecError::Corrupt { type_name: "bool" }));
+        assert!(matches!(corrupt(11, 2), CodecError::Corrupt { type_name: "Option" }));
+    }
+
+    #[test]
+    fn missing_codecs_are_reported() {
+        let layout = packet_layout();
+        let registry = TypeRegistry::default();
+        registry.add_default_codecs();
+
+        let mut out = vec![42];
+        let err = registry.encode(&packet(&layout), &mut out).unwrap_err();
+        assert!(matches!(&err, CodecError::NoCodec { field, .. } if field == "tags"), "{}", err);
+        assert_eq!(out, [42]);
+
+        let err = registry.decode(&layout, &[0]).unwrap_err();
+        assert!(matches!(&err, CodecError::NoCodec { field, .. } if field == "tags"), "{}", err);
+    }
+}
@@ -0,0 +1,852 @@
+use std::{any::{Any, type_name}, fmt, str::FromStr, sync::Arc};
+
+use parking_lot::{Mutex, RwLock};
+use smartstring::alias::String;
+
+use super::{
+    DynamicArrayMut, DynamicArrayRef, DynamicFieldError, DynamicStruct, DynamicStructMut, DynamicStructRef, DynamicTypeLayout,
+    FieldKind, FieldValue, FieldValueMut,
+};
+
+type PathResult = Result<(), DynamicFieldError<()>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    Field(String),
+    Index(usize),
+}
+
+/// A parsed field path such as `stats.health.max` or `items[3].count`.
+///
+/// Parse once and reuse with `DynamicStruct::with_path` and friends to skip the parsing, or resolve
+/// it against a layout with `DynamicTypeLayout::path_handle` to skip the field lookups as well.
+/// Walking a path looks through nested dynamic structs, `Option`, `Box`, `Arc`, `Arc<RwLock<_>>`
+/// and `Arc<Mutex<_>>`, and index segments into inline array fields and into `Vec`s of dynamic
+/// structs or of the requested type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldPath {
+    path: String,
+    segments: Vec<PathSegment>,
+    /// Byte offset in `path` where each segment ends, used to name the failing segment in errors.
+    ends: Vec<usize>,
+}
+
+impl FieldPath {
+    pub fn parse(path: &str) -> Result<Self, DynamicFieldError<()>> {
+        let invalid = |position| DynamicFieldError::InvalidPath { path: path.into(), position };
+        let bytes = path.as_bytes();
+        let mut segments = Vec::new();
+        let mut ends = Vec::new();
+        let mut expect_field = true;
+        let mut pos = 0;
+
+        while pos < bytes.len() {
+            if expect_field {
+                let start = pos;
+                while pos < bytes.len() && !matches!(bytes[pos], b'.' | b'[' | b']') {
+                    pos += 1;
+                }
+                if start == pos {
+                    return Err(invalid(pos));
+                }
+                segments.push(PathSegment::Field(path[start..pos].into()));
+                ends.push(pos);
+                expect_field = false;
+                continue;
+            }
+
+            match bytes[pos] {
+                b'.' => {
+                    pos += 1;
+                    expect_field = true;
+                }
+                b'[' => {
+                    let start = pos + 1;
+                    let mut end = start;
+                    while end < bytes.len() && bytes[end].is_ascii_digit() {
+                        end += 1;
+                    }
+                    if end == start || bytes.get(end) != Some(&b']') {
+                        return Err(invalid(end));
+                    }
+                    let index = path[start..end].parse().map_err(|_| invalid(start))?;
+                    pos = end + 1;
+                    segments.push(PathSegment::Index(index));
+                    ends.push(pos);
+                }
+                _ => return Err(invalid(pos)),
+            }
+        }
+
+        // Empty paths and trailing dots.
+        if expect_field {
+            return Err(invalid(pos));
+        }
+
+        Ok(Self { path: path.into(), segments, ends })
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    #[inline]
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// The path up to and including the segment at `depth`, e.g. `items[3]`.
+    #[inline]
+    fn segment_text(&self, depth: usize) -> String {
+        self.path[..self.ends[depth]].into()
+    }
+}
+
+impl FromStr for FieldPath {
+    type Err = DynamicFieldError<()>;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        Self::parse(path)
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+/// A `FieldPath` resolved against one layout by `DynamicTypeLayout::path_handle`.
+///
+/// The leading segments that stay inside inline nested structs and arrays are resolved to a single
+/// offset when the handle is built, so walking them is a pointer add after comparing layout ids. Segments past
+/// a container such as `Vec<DynamicStruct>` are still looked up on every walk, since the structs
+/// inside can have any layout.
+#[derive(Clone)]
+pub struct PathHandle {
+    path: FieldPath,
+    layout_id: u64,
+    /// Number of leading segments resolved into `offset` and `target`.
+    resolved: usize,
+    offset: usize,
+    /// The kind of the value the resolved segments lead to.
+    target: FieldKind,
+}
+
+impl fmt::Debug for PathHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PathHandle")
+            .field("path", &self.path.as_str())
+            .field("layout_id", &self.layout_id)
+            .field("resolved", &self.resolved)
+            .field("offset", &self.offset)
+            .field("type", &match &self.target {
+                FieldKind::Dynamic(layout) => layout.name.as_str(),
+                kind => kind.type_name(),
+            })
+            .finish()
+    }
+}
+
+impl PathHandle {
+    #[inline]
+    pub fn path(&self) -> &FieldPath {
+        &self.path
+    }
+
+    #[inline]
+    pub fn layout_id(&self) -> u64 {
+        self.layout_id
+    }
+
+    /// Byte offset from the start of the struct to the last resolved field.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn check_layout(&self, layout: &DynamicTypeLayout) -> PathResult {
+        if self.layout_id != layout.id() {
+            return Err(DynamicFieldError::HandleLayoutMismatch { layout: layout.name.clone() });
+        }
+        Ok(())
+    }
+
+    /// Where walking continues, `data` must be a struct of the layout this handle was built from.
+    fn cursor<'a>(&'a self, data: &'a [u8]) -> Cursor<'a> {
+        let data = &data[self.offset..self.offset + self.target.size()];
+        Cursor::new(&self.target, self.target.value_ref(data))
+    }
+
+    fn cursor_mut<'a>(&'a self, data: &'a mut [u8]) -> CursorMut<'a> {
+        let data = &mut data[self.offset..self.offset + self.target.size()];
+        CursorMut::new(&self.target, self.target.value_mut(data))
+    }
+}
+
+impl DynamicTypeLayout {
+    /// Parses `path` and resolves it against this layout, see `PathHandle`.
+    #[inline]
+    pub fn path_handle(&self, path: &str) -> Result<PathHandle, DynamicFieldError<()>> {
+        self.path_handle_with(&FieldPath::parse(path)?)
+    }
+
+    /// Resolves an already parsed path, failing on the first field this layout doesn't have or the
+    /// first index past the end of an inline array.
+    pub fn path_handle_with(&self, path: &FieldPath) -> Result<PathHandle, DynamicFieldError<()>> {
+        let mut offset = 0;
+        let mut resolved = 0;
+        // `None` while still at this layout, every path starts with one of its fields.
+        let mut kind: Option<&FieldKind> = None;
+        while let Some(segment) = path.segments.get(resolved) {
+            let not_traversable = |type_name: &str| DynamicFieldError::PathNotTraversable {
+                segment: path.segment_text(resolved),
+                type_name: type_name.into(),
+            };
+            match (segment, kind) {
+                // Static values such as `Vec<DynamicStruct>` are walked on every access.
+                (_, Some(FieldKind::Static(_))) => break,
+                (PathSegment::Index(index), Some(FieldKind::Array { element, len })) => {
+                    if index >= len {
+                        return Err(DynamicFieldError::PathIndexOutOfBounds { segment: path.segment_text(resolved), len: *len });
+                    }
+                    offset += index * element.size();
+                    kind = Some(element);
+                }
+                (PathSegment::Field(_), Some(array @ FieldKind::Array { .. })) => return Err(not_traversable(array.type_name())),
+                (segment, None | Some(FieldKind::Dynamic(_))) => {
+                    let layout = match kind {
+                        Some(FieldKind::Dynamic(layout)) => layout,
+                        _ => self,
+                    };
+                    let PathSegment::Field(name) = segment else {
+                        return Err(not_traversable(&layout.name));
+                    };
+                    let Some(&index) = layout.name_to_index.get(name.as_str()) else {
+                        return Err(DynamicFieldError::PathFieldNotFound { segment: path.segment_text(resolved) });
+                    };
+                    offset += layout.field_offsets[index];
+                    kind = Some(&layout.field_kinds[index]);
+                }
+            }
+            resolved += 1;
+        }
+        let target = kind.expect("Field paths have at least one segment.").clone();
+        Ok(PathHandle { path: path.clone(), layout_id: self.id(), resolved, offset, target })
+    }
+}
+
+enum Cursor<'a> {
+    Struct(DynamicStructRef<'a>),
+    Array(DynamicArrayRef<'a>),
+    Value(&'a dyn Any, &'static str),
+}
+
+impl<'a> Cursor<'a> {
+    /// Where walking continues at `value`, a value of `kind`.
+    fn new(kind: &FieldKind, value: FieldValue<'a>) -> Self {
+        match value {
+            FieldValue::Struct(view) => Cursor::Struct(view),
+            FieldValue::Array(array) => Cursor::Array(array),
+            FieldValue::Value(value) => Cursor::Value(value, kind.type_name()),
+        }
+    }
+
+    /// Where walking continues at the field `index` of `view`.
+    fn field(view: DynamicStructRef<'a>, index: usize) -> Self {
+        let kind = &view.type_layout.field_kinds[index];
+        let offset = view.type_layout.field_offsets[index];
+        Cursor::new(kind, kind.value_ref(&view.data[offset..offset + kind.size()]))
+    }
+}
+
+enum CursorMut<'a> {
+    Struct(DynamicStructMut<'a>),
+    Array(DynamicArrayMut<'a>),
+    Value(&'a mut dyn Any, &'static str),
+}
+
+impl<'a> CursorMut<'a> {
+    fn new(kind: &FieldKind, value: FieldValueMut<'a>) -> Self {
+        match value {
+            FieldValueMut::Struct(view) => CursorMut::Struct(view),
+            FieldValueMut::Array(array) => CursorMut::Array(array),
+            FieldValueMut::Value(value) => CursorMut::Value(value, kind.type_name()),
+        }
+    }
+
+    fn field(view: DynamicStructMut<'a>, index: usize) -> Self {
+        let DynamicStructMut { type_layout, data } = view;
+        let kind = &type_layout.field_kinds[index];
+        let offset = type_layout.field_offsets[index];
+        CursorMut::new(kind, kind.value_mut(&mut data[offset..offset + kind.size()]))
+    }
+}
+
+/// Names an inline array in errors, e.g. `[u16; 4]`.
+fn array_type_name(array: DynamicArrayRef<'_>) -> String {
+    format!("[{}; {}]", array.element_type_name(), array.len()).as_str().into()
+}
+
+fn index_out_of_bounds(path: &FieldPath, depth: usize, len: usize) -> PathResult {
+    Err(DynamicFieldError::PathIndexOutOfBounds { segment: path.segment_text(depth), len })
+}
+
+fn missing(segment: String) -> PathResult {
+    Err(DynamicFieldError::PathValueMissing { segment })
+}
+
+/// Calls `f` with the `X` stored in `value`, directly or behind one of the supported wrappers.
+/// Returns `None` if `value` holds something else.
+fn read_through<X: Any>(value: &dyn Any, segment: &str, f: &mut dyn FnMut(&X) -> PathResult) -> Option<PathResult> {
+    if let Some(value) = value.downcast_ref::<X>() {
+        return Some(f(value));
+    }
+    if let Some(value) = value.downcast_ref::<Box<X>>() {
+        return Some(f(value));
+    }
+    if let Some(value) = value.downcast_ref::<Arc<X>>() {
+        return Some(f(value));
+    }
+    if let Some(value) = value.downcast_ref::<Arc<RwLock<X>>>() {
+        return Some(f(&value.read()));
+    }
+    if let Some(value) = value.downcast_ref::<Arc<Mutex<X>>>() {
+        return Some(f(&value.lock()));
+    }
+    if let Some(value) = value.downcast_ref::<Option<X>>() {
+        return Some(value.as_ref().map_or_else(|| missing(segment.into()), f));
+    }
+    if let Some(value) = value.downcast_ref::<Option<Box<X>>>() {
+        return Some(value.as_ref().map_or_else(|| missing(segment.into()), |value| f(value)));
+    }
+    if let Some(value) = value.downcast_ref::<Option<Arc<X>>>() {
+        return Some(value.as_ref().map_or_else(|| missing(segment.into()), |value| f(value)));
+    }
+    if let Some(value) = value.downcast_ref::<Option<Arc<RwLock<X>>>>() {
+        return Some(value.as_ref().map_or_else(|| missing(segment.into()), |value| f(&value.read())));
+    }
+    if let Some(value) = value.downcast_ref::<Option<Arc<Mutex<X>>>>() {
+        return Some(value.as_ref().map_or_else(|| missing(segment.into()), |value| f(&value.lock())));
+    }
+    None
+}
+
+/// Mutable counterpart of `read_through`, a shared `Arc<X>` can only be written when it is unique.
+fn write_through<X: Any>(value: &mut dyn Any, segment: &str, f: &mut dyn FnMut(&mut X) -> PathResult) -> Option<PathResult> {
+    let not_mutable = || Err(DynamicFieldError::PathNotMutable { segment: segment.into() });
+    if value.is::<X>() {
+        return value.downcast_mut::<X>().map(f);
+    }
+    if let Some(value) = value.downcast_mut::<Box<X>>() {
+        return Some(f(value));
+    }
+    if let Some(value) = value.downcast_mut::<Arc<X>>() {
+        return Some(Arc::get_mut(value).map_or_else(not_mutable, f));
+    }
+    if let Some(value) = value.downcast_mut::<Arc<RwLock<X>>>() {
+        return Some(f(&mut value.write()));
+    }
+    if let Some(value) = value.downcast_mut::<Arc<Mutex<X>>>() {
+        return Some(f(&mut value.lock()));
+    }
+    if let Some(value) = value.downcast_mut::<Option<X>>() {
+        return Some(value.as_mut().map_or_else(|| missing(segment.into()), f));
+    }
+    if let Some(value) = value.downcast_mut::<Option<Box<X>>>() {
+        return Some(value.as_mut().map_or_else(|| missing(segment.into()), |value| f(value)));
+    }
+    if let Some(value) = value.downcast_mut::<Option<Arc<X>>>() {
+        return Some(match value.as_mut() {
+            Some(value) => Arc::get_mut(value).map_or_else(not_mutable, f),
+            None => missing(segment.into()),
+        });
+    }
+    if let Some(value) = value.downcast_mut::<Option<Arc<RwLock<X>>>>() {
+        return Some(value.as_ref().map_or_else(|| missing(segment.into()), |value| f(&mut value.write())));
+    }
+    if let Some(value) = value.downcast_mut::<Option<Arc<Mutex<X>>>>() {
+        return Some(value.as_ref().map_or_else(|| missing(segment.into()), |value| f(&mut value.lock())));
+    }
+    None
+}
+
+fn read_index<Z: Any, T: Any>(
+    path: &FieldPath,
+    depth: usize,
+    value: &dyn Any,
+    index: usize,
+    f: &mut dyn FnMut(&T),
+) -> Option<PathResult> {
+    let segment = path.segment_text(depth);
+    read_through::<Vec<Z>>(value, &segment, &mut |items| match items.get(index) {
+        Some(item) => walk(path, depth + 1, Cursor::Value(item, type_name::<Z>()), f),
+        None => Err(DynamicFieldError::PathIndexOutOfBounds { segment: segment.clone(), len: items.len() }),
+    })
+}
+
+fn write_index<Z: Any, T: Any>(
+    path: &FieldPath,
+    depth: usize,
+    value: &mut dyn Any,
+    index: usize,
+    f: &mut dyn FnMut(&mut T),
+) -> Option<PathResult> {
+    let segment = path.segment_text(depth);
+    write_through::<Vec<Z>>(value, &segment, &mut |items| {
+        let len = items.len();
+        match items.get_mut(index) {
+            Some(item) => walk_mut(path, depth + 1, CursorMut::Value(item, type_name::<Z>()), f),
+            None => Err(DynamicFieldError::PathIndexOutOfBounds { segment: segment.clone(), len }),
+        }
+    })
+}
+
+fn walk<T: Any>(path: &FieldPath, depth: usize, cursor: Cursor<'_>, f: &mut dyn FnMut(&T)) -> PathResult {
+    // `depth` only reaches zero segments for the root, which `FieldPath::parse` never produces.
+    let last = depth.max(1) - 1;
+    let Some(segment) = path.segments.get(depth) else {
+        let invalid_type = |actual_type: &str| DynamicFieldError::PathInvalidType {
+            segment: path.segment_text(last),
+            type_requested: type_name::<T>().into(),
+            actual_type: actual_type.into(),
+        };
+        return match cursor {
+            Cursor::Value(value, actual_type) => {
+                read_through::<T>(value, &path.segment_text(last), &mut |value| {
+                    f(value);
+                    Ok(())
+                })
+                .unwrap_or_else(|| Err(invalid_type(actual_type)))
+            }
+            Cursor::Struct(view) => Err(invalid_type(&view.layout().name)),
+            Cursor::Array(array) => Err(invalid_type(&array_type_name(array))),
+        };
+    };
+
+    match (segment, cursor) {
+        (PathSegment::Field(name), Cursor::Struct(view)) => {
+            let Some(index) = view.layout().name_to_index.get(name.as_str()) else {
+                return Err(DynamicFieldError::PathFieldNotFound { segment: path.segment_text(depth) });
+            };
+            walk(path, depth + 1, Cursor::field(view, *index), f)
+        }
+        (PathSegment::Field(_), Cursor::Value(value, type_name)) => {
+            // The value wraps a dynamic struct, resolve it and retry the same segment on it.
+            read_through::<DynamicStruct>(value, &path.segment_text(last), &mut |nested| {
+                walk(path, depth, Cursor::Struct(nested.as_ref()), f)
+            })
+            .unwrap_or_else(|| {
+                Err(DynamicFieldError::PathNotTraversable { segment: path.segment_text(depth), type_name: type_name.into() })
+            })
+        }
+        (PathSegment::Index(_), Cursor::Struct(view)) => Err(DynamicFieldError::PathNotTraversable {
+            segment: path.segment_text(depth),
+            type_name: view.layout().name.clone(),
+        }),
+        (PathSegment::Index(index), Cursor::Array(array)) => match array.get(*index) {
+            Some(value) => walk(path, depth + 1, Cursor::new(array.element, value), f),
+            None => index_out_of_bounds(path, depth, array.len()),
+        },
+        (PathSegment::Field(_), Cursor::Array(array)) => Err(DynamicFieldError::PathNotTraversable {
+            segment: path.segment_text(depth),
+            type_name: array_type_name(array),
+        }),
+        (PathSegment::Index(index), Cursor::Value(value, type_name)) => {
+            let index = *index;
+            read_index::<DynamicStruct, T>(path, depth, value, index, f)
+                .or_else(|| read_index::<T, T>(path, depth, value, index, f))
+                .or_else(|| read_index::<Option<DynamicStruct>, T>(path, depth, value, index, f))
+                .or_else(|| read_index::<Box<DynamicStruct>, T>(path, depth, value, index, f))
+                .or_else(|| read_index::<Arc<RwLock<DynamicStruct>>, T>(path, depth, value, index, f))
+                .or_else(|| read_index::<Option<Arc<RwLock<DynamicStruct>>>, T>(path, depth, value, index, f))
+                .or_else(|| read_index::<Option<T>, T>(path, depth, value, index, f))
+                .unwrap_or_else(|| {
+                    Err(DynamicFieldError::PathNotTraversable { segment: path.segment_text(depth), type_name: type_name.into() })
+                })
+        }
+    }
+}
+
+fn walk_mut<T: Any>(path: &FieldPath, depth: usize, cursor: CursorMut<'_>, f: &mut dyn FnMut(&mut T)) -> PathResult {
+    let last = depth.max(1) - 1;
+    let Some(segment) = path.segments.get(depth) else {
+        let invalid_type = |actual_type: &str| DynamicFieldError::PathInvalidType {
+            segment: path.segment_text(last),
+            type_requested: type_name::<T>().into(),
+            actual_type: actual_type.into(),
+        };
+        return match cursor {
+            CursorMut::Value(value, actual_type) => {
+                write_through::<T>(value, &path.segment_text(last), &mut |value| {
+                    f(value);
+                    Ok(())
+                })
+                .unwrap_or_else(|| Err(invalid_type(actual_type)))
+            }
+            CursorMut::Struct(view) => Err(invalid_type(&view.layout().name)),
+            CursorMut::Array(array) => Err(invalid_type(&array_type_name(array.as_ref()))),
+        };
+    };
+
+    match (segment, cursor) {
+        (PathSegment::Field(name), CursorMut::Struct(view)) => {
+            let Some(&index) = view.layout().name_to_index.get(name.as_str()) else {
+                return Err(DynamicFieldError::PathFieldNotFound { segment: path.segment_text(depth) });
+            };
+            walk_mut(path, depth + 1, CursorMut::field(view, index), f)
+        }
+        (PathSegment::Field(_), CursorMut::Value(value, type_name)) => {
+            write_through::<DynamicStruct>(value, &path.segment_text(last), &mut |nested| {
+                walk_mut(path, depth, CursorMut::Struct(nested.as_mut()), f)
+            })
+            .unwrap_or_else(|| {
+                Err(DynamicFieldError::PathNotTraversable { segment: path.segment_text(depth), type_name: type_name.into() })
+            })
+        }
+        (PathSegment::Index(_), CursorMut::Struct(view)) => Err(DynamicFieldError::PathNotTraversable {
+            segment: path.segment_text(depth),
+            type_name: view.layout().name.clone(),
+        }),
+        (PathSegment::Index(index), CursorMut::Array(mut array)) => {
+            let (element, len) = (array.element, array.len());
+            match array.get_mut(*index) {
+                Some(value) => walk_mut(path, depth + 1, CursorMut::new(element, value), f),
+                None => index_out_of_bounds(path, depth, len),
+            }
+        }
+        (PathSegment::Field(_), CursorMut::Array(array)) => Err(DynamicFieldError::PathNotTraversable {
+            segment: path.segment_text(depth),
+            type_name: array_type_name(array.as_ref()),
+        }),
+        (PathSegment::Index(index), CursorMut::Value(value, type_name)) => {
+            let index = *index;
+            write_index::<DynamicStruct, T>(path, depth, value, index, f)
+                .or_else(|| write_index::<T, T>(path, depth, value, index, f))
+                .or_else(|| write_index::<Option<DynamicStruct>, T>(path, depth, value, index, f))
+                .or_else(|| write_index::<Box<DynamicStruct>, T>(path, depth, value, index, f))
+                .or_else(|| write_index::<Arc<RwLock<DynamicStruct>>, T>(path, depth, value, index, f))
+                .or_else(|| write_index::<Option<Arc<RwLock<DynamicStruct>>>, T>(path, depth, value, index, f))
+                .or_else(|| write_index::<Option<T>, T>(path, depth, value, index, f))
+                .unwrap_or_else(|| {
+                    Err(DynamicFieldError::PathNotTraversable { segment: path.segment_text(depth), type_name: type_name.into() })
+                })
+        }
+    }
+}
+
+impl<'a> DynamicStructRef<'a> {
+    /// Calls `f` with the value at `path`, see `FieldPath` for what can be walked through.
+    pub fn with_path<T: Any, R>(&self, path: &FieldPath, f: impl FnOnce(&T) -> R) -> Result<R, DynamicFieldError<()>> {
+        let mut f = Some(f);
+        let mut output = None;
+        walk(path, 0, Cursor::Struct(*self), &mut |value| {
+            output = f.take().map(|f| f(value));
+        })?;
+        Ok(output.expect("Path walk succeeded without visiting a value."))
+    }
+
+    /// Like `with_path`, starting from the field `handle` resolved. Fails if `handle` was built
+    /// from a different layout.
+    pub fn with_path_handle<T: Any, R>(&self, handle: &PathHandle, f: impl FnOnce(&T) -> R) -> Result<R, DynamicFieldError<()>> {
+        handle.check_layout(self.type_layout)?;
+        let mut f = Some(f);
+        let mut output = None;
+        walk(&handle.path, handle.resolved, handle.cursor(self.data), &mut |value| {
+            output = f.take().map(|f| f(value));
+        })?;
+        Ok(output.expect("Path walk succeeded without visiting a value."))
+    }
+}
+
+impl<'a> DynamicStructMut<'a> {
+    /// Calls `f` with the value at `path` mutably, see `FieldPath` for what can be walked through.
+    pub fn with_path_mut<T: Any, R>(&mut self, path: &FieldPath, f: impl FnOnce(&mut T) -> R) -> Result<R, DynamicFieldError<()>> {
+        let mut f = Some(f);
+        let mut output = None;
+        walk_mut(path, 0, CursorMut::Struct(self.reborrow()), &mut |value| {
+            output = f.take().map(|f| f(value));
+        })?;
+        Ok(output.expect("Path walk succeeded without visiting a value."))
+    }
+
+    /// Mutable counterpart of `DynamicStructRef::with_path_handle`.
+    pub fn with_path_handle_mut<T: Any, R>(&mut self, handle: &PathHandle, f: impl FnOnce(&mut T) -> R) -> Result<R, DynamicFieldError<()>> {
+        handle.check_layout(self.type_layout)?;
+        let mut f = Some(f);
+        let mut output = None;
+        walk_mut(&handle.path, handle.resolved, handle.cursor_mut(self.data), &mut |value| {
+            output = f.take().map(|f| f(value));
+        })?;
+        Ok(output.expect("Path walk succeeded without visiting a value."))
+    }
+}
+
+impl DynamicStruct {
+    #[inline]
+    pub fn get_path<T: Any + Clone>(&self, path: &str) -> Result<T, DynamicFieldError<()>> {
+        self.get_path_with(&FieldPath::parse(path)?)
+    }
+
+    #[inline]
+    pub fn get_path_with<T: Any + Clone>(&self, path: &FieldPath) -> Result<T, DynamicFieldError<()>> {
+        self.with_path(path, T::clone)
+    }
+
+    #[inline]
+    pub fn set_path<T: Any>(&mut self, path: &str, val: T) -> Result<(), DynamicFieldError<()>> {
+        self.set_path_with(&FieldPath::parse(path)?, val)
+    }
+
+    #[inline]
+    pub fn set_path_with<T: Any>(&mut self, path: &FieldPath, val: T) -> Result<(), DynamicFieldError<()>> {
+        self.with_path_mut(path, |field: &mut T| *field = val)
+    }
+
+    #[inline]
+    pub fn with_path<T: Any, R>(&self, path: &FieldPath, f: impl FnOnce(&T) -> R) -> Result<R, DynamicFieldError<()>> {
+        self.as_ref().with_path(path, f)
+    }
+
+    #[inline]
+    pub fn with_path_mut<T: Any, R>(&mut self, path: &FieldPath, f: impl FnOnce(&mut T) -> R) -> Result<R, DynamicFieldError<()>> {
+        self.as_mut().with_path_mut(path, f)
+    }
+
+    #[inline]
+    pub fn get_path_handle<T: Any + Clone>(&self, handle: &PathHandle) -> Result<T, DynamicFieldError<()>> {
+        self.with_path_handle(handle, T::clone)
+    }
+
+    #[inline]
+    pub fn set_path_handle<T: Any>(&mut self, handle: &PathHandle, val: T) -> Result<(), DynamicFieldError<()>> {
+        self.with_path_handle_mut(handle, |field: &mut T| *field = val)
+    }
+
+    #[inline]
+    pub fn with_path_handle<T: Any, R>(&self, handle: &PathHandle, f: impl FnOnce(&T) -> R) -> Result<R, DynamicFieldError<()>> {
+        self.as_ref().with_path_handle(handle, f)
+    }
+
+    #[inline]
+    pub fn with_path_handle_mut<T: Any, R>(&mut self, handle: &PathHandle, f: impl FnOnce(&mut T) -> R) -> Result<R, DynamicFieldError<()>> {
+        self.as_mut().with_path_handle_mut(handle, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamic_types::StaticTypeLayout;
+
+    fn layouts() -> (Arc<DynamicTypeLayout>, Arc<DynamicTypeLayout>) {
+        let health = Arc::new(
+            DynamicTypeLayout::builder("Health".into())
+                .field("current", &StaticTypeLayout::of::<i32>())
+                .field("max", &StaticTypeLayout::of::<i32>())
+                .build(),
+        );
+        let stats = Arc::new(
+            DynamicTypeLayout::builder("Stats".into())
+                .nested("health", &health)
+                .field("scores", &StaticTypeLayout::of::<Vec<u32>>())
+                .build(),
+        );
+        let item = Arc::new(
+            DynamicTypeLayout::builder("Item".into())
+                .field("count", &StaticTypeLayout::of::<u16>())
+                .build(),
+        );
+        let player = Arc::new(
+            DynamicTypeLayout::builder("Player".into())
+                .nested("stats", &stats)
+                .field("items", &StaticTypeLayout::of::<Vec<DynamicStruct>>())
+                .field("pet", &StaticTypeLayout::of::<Option<DynamicStruct>>())
+                .field("shared", &StaticTypeLayout::of::<Option<Arc<RwLock<DynamicStruct>>>>())
+                .build(),
+        );
+        (player, item)
+    }
+
+    #[test]
+    fn parse_paths() {
+        let path = FieldPath::parse("items[3].count").unwrap();
+        assert_eq!(
+            path.segments(),
+            &[PathSegment::Field("items".into()), PathSegment::Index(3), PathSegment::Field("count".into())]
+        );
+        assert_eq!(FieldPath::parse("a[1][2]").unwrap().segments().len(), 3);
+
+        for (path, position) in [("", 0), ("a.", 2), (".a", 0), ("a[", 2), ("a[x]", 2), ("a[1", 3), ("a]", 1), ("a..b", 2)] {
+            match FieldPath::parse(path) {
+                Err(DynamicFieldError::InvalidPath { position: found, .. }) => assert_eq!(found, position, "{}", path),
+                _ => panic!("{} should not parse", path),
+            }
+        }
+    }
+
+    #[test]
+    fn get_and_set_through_nested_containers() {
+        let (player_layout, item_layout) = layouts();
+        let mut player = DynamicStruct::new(player_layout);
+
+        player.set_path("stats.health.max", 100i32).unwrap();
+        assert_eq!(player.get_path::<i32>("stats.health.max").unwrap(), 100);
+
+        player.set_path("stats.scores", vec![1u32, 2, 3]).unwrap();
+        player.set_path("stats.scores[1]", 20u32).unwrap();
+        assert_eq!(player.get_path::<u32>("stats.scores[1]").unwrap(), 20);
+
+        let items = (0..4).map(|count| {
+            let mut item = DynamicStruct::new(item_layout.clone());
+            item.set_field("count", count as u16);
+            item
+        });
+        player.set_field("items", items.collect::<Vec<_>>());
+        player.set_path("items[3].count", 30u16).unwrap();
+        assert_eq!(player.get_path::<u16>("items[3].count").unwrap(), 30);
+        assert_eq!(player.get_path::<u16>("items[2].count").unwrap(), 2);
+
+        player.set_field("pet", Some(DynamicStruct::new(item_layout.clone())));
+        player.set_path("pet.count", 5u16).unwrap();
+        assert_eq!(player.get_path::<u16>("pet.count").unwrap(), 5);
+
+        let shared = Arc::new(RwLock::new(DynamicStruct::new(item_layout)));
+        player.set_field("shared", Some(shared.clone()));
+        player.set_path("shared.count", 9u16).unwrap();
+        assert_eq!(*shared.read().get_field_ref::<u16>("count"), 9);
+
+        let path = FieldPath::parse("stats.health.max").unwrap();
+        for _ in 0..3 {
+            player.with_path_mut(&path, |max: &mut i32| *max += 1).unwrap();
+        }
+        assert_eq!(player.get_path_with::<i32>(&path).unwrap(), 103);
+    }
+
+    #[test]
+    fn path_handles_resolve_inline_fields_up_front() {
+        let (player_layout, item_layout) = layouts();
+        let mut player = DynamicStruct::new(player_layout.clone());
+
+        let max = player_layout.path_handle("stats.health.max").unwrap();
+        let stats = &player_layout.field_kinds[player_layout.name_to_index["stats"]];
+        let FieldKind::Dynamic(stats) = stats else { panic!("stats should be nested") };
+        let health = &stats.field_kinds[stats.name_to_index["health"]];
+        let FieldKind::Dynamic(health) = health else { panic!("health should be nested") };
+        assert_eq!(
+            max.offset(),
+            player_layout.field_offsets[player_layout.name_to_index["stats"]]
+                + stats.field_offsets[stats.name_to_index["health"]]
+                + health.field_offsets[health.name_to_index["max"]]
+        );
+        for _ in 0..3 {
+            player.with_path_handle_mut(&max, |max: &mut i32| *max += 1).unwrap();
+        }
+        assert_eq!(player.get_path_handle::<i32>(&max).unwrap(), 3);
+
+        // Segments past a container are walked at access time.
+        let count = player_layout.path_handle("items[1].count").unwrap();
+        player.set_field("items", (0..2).map(|_| DynamicStruct::new(item_layout.clone())).collect::<Vec<_>>());
+        player.set_path_handle(&count, 7u16).unwrap();
+        assert_eq!(player.get_path::<u16>("items[1].count").unwrap(), 7);
+
+        let nested = player_layout.path_handle("stats.health").unwrap();
+        let health = player.with_path_handle(&nested, |health: &DynamicStruct| health.layout().name.clone());
+        assert!(matches!(health, Err(DynamicFieldError::PathInvalidType { .. })));
+
+        match player_layout.path_handle("stats.mana") {
+            Err(DynamicFieldError::PathFieldNotFound { segment }) => assert_eq!(segment, "stats.mana"),
+            other => panic!("unexpected {:?}", other),
+        }
+        let item = DynamicStruct::new(item_layout);
+        match item.get_path_handle::<i32>(&max) {
+            Err(DynamicFieldError::HandleLayoutMismatch { layout }) => assert_eq!(layout, "Item"),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn index_segments_step_into_inline_arrays() {
+        let (_, item_layout) = layouts();
+        let team = Arc::new(
+            DynamicTypeLayout::builder("Team".into())
+                .array("colors", &StaticTypeLayout::of::<u32>(), 4)
+                .nested_array("members", &item_layout, 3)
+                .array("tags", &StaticTypeLayout::of::<Vec<u16>>(), 2)
+                .build(),
+        );
+        let mut instance = DynamicStruct::new(team.clone());
+
+        instance.set_path("colors[2]", 0xff00ffu32).unwrap();
+        assert_eq!(instance.get_path::<u32>("colors[2]").unwrap(), 0xff00ff);
+        assert_eq!(instance.get_array_ref::<u32>("colors"), &[0, 0, 0xff00ff, 0]);
+
+        instance.set_path("members[1].count", 4u16).unwrap();
+        assert_eq!(instance.get_path::<u16>("members[1].count").unwrap(), 4);
+        assert_eq!(instance.get_struct_array_ref("members").get(1).unwrap().get_field_ref::<u16>("count"), &4);
+
+        instance.set_path("tags[1]", vec![1u16, 2]).unwrap();
+        instance.set_path("tags[1][0]", 5u16).unwrap();
+        assert_eq!(instance.get_path::<u16>("tags[1][0]").unwrap(), 5);
+
+        // Handles resolve indices into inline arrays up front, bounds included.
+        let count = team.path_handle("members[2].count").unwrap();
+        assert_eq!(count.offset(), team.field_offsets[team.name_to_index["members"]] + 2 * item_layout.total_size);
+        instance.set_path_handle(&count, 6u16).unwrap();
+        assert_eq!(instance.get_path::<u16>("members[2].count").unwrap(), 6);
+        let tag = team.path_handle("tags[1][1]").unwrap();
+        assert_eq!(instance.get_path_handle::<u16>(&tag).unwrap(), 2);
+
+        match team.path_handle("colors[4]") {
+            Err(DynamicFieldError::PathIndexOutOfBounds { segment, len }) => {
+                assert_eq!(segment, "colors[4]");
+                assert_eq!(len, 4);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match instance.get_path::<u16>("members[3].count") {
+            Err(DynamicFieldError::PathIndexOutOfBounds { segment, .. }) => assert_eq!(segment, "members[3]"),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+        match instance.get_path::<u32>("colors.red") {
+            Err(DynamicFieldError::PathNotTraversable { segment, .. }) => assert_eq!(segment, "colors.red"),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+        assert!(matches!(instance.get_path::<u32>("colors"), Err(DynamicFieldError::PathInvalidType { .. })));
+    }
+
+    #[test]
+    fn errors_name_the_failing_segment() {
+        let (player_layout, _) = layouts();
+        let mut player = DynamicStruct::new(player_layout);
+
+        match player.get_path::<i32>("stats.mana") {
+            Err(DynamicFieldError::PathFieldNotFound { segment }) => assert_eq!(segment, "stats.mana"),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+        match player.get_path::<u16>("items[0].count") {
+            Err(DynamicFieldError::PathIndexOutOfBounds { segment, len }) => {
+                assert_eq!(segment, "items[0]");
+                assert_eq!(len, 0);
+            }
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+        match player.set_path("pet.count", 1u16) {
+            Err(DynamicFieldError::PathValueMissing { segment }) => assert_eq!(segment, "pet"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match player.get_path::<u8>("stats.health.max") {
+            Err(DynamicFieldError::PathInvalidType { segment, .. }) => assert_eq!(segment, "stats.health.max"),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+        match player.get_path::<i32>("stats.health.max.value") {
+            Err(DynamicFieldError::PathNotTraversable { segment, .. }) => assert_eq!(segment, "stats.health.max.value"),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+        match player.get_path::<i32>("stats[0]") {
+            Err(DynamicFieldError::PathNotTraversable { segment, .. }) => assert_eq!(segment, "stats[0]"),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,1062 @@
+//! KingsIsle's ObjectProperty wire format for dynamic structs of the client's classes.
+//!
+//! An object starts with the `string_id` hash of its class name. Deep objects, the default, follow
+//! it with their size in bits and then each property as its size in bits, its hash and its value.
+//! Sizes count the bits after the size field itself, so readers can skip properties they don't know.
+//! Shallow objects write the bare values in declaration order instead. Only properties whose flags
+//! contain `SerializerOptions::property_mask` are written, deprecated ones never are.
+//!
+//! Values are little-endian, strings are prefixed with a `u16` length in bytes for `std::string` and
+//! in UTF-16 code units for `std::wstring`, pointers with a presence flag and lists with a `u32`
+//! element count. Bools and presence flags take a byte each, or a single bit in bit-packed mode
+//! where nothing is aligned to bytes.
+//!
+//! Property types resolve through the registry's type names, see `TypeRegistry::resolve_type_name`,
+//! and are written with the `PropertyValue` registered for the type under their pointers and lists.
+//! Properties holding other classes of the dumps, which the client writes as nested objects, are out
+//! of scope and have no wire encoding.
+
+use std::{any::{type_name, Any, TypeId}, marker::PhantomData, sync::Arc};
+
+use smartstring::alias::String;
+use thiserror::Error;
+
+use super::{
+    type_names::TypeWrapper, DynamicStruct, DynamicTypeLayout, DynamicTypeLayoutBuilder, StaticTypeLayout,
+    TypeRegistry, TypeRegistryError,
+};
+use crate::{Color, Point, Vector3D, GID};
+
+/// Flags a property is declared with in the client's type dumps.
+pub mod flags {
+    pub const SAVE: u32 = 1 << 0;
+    pub const COPY: u32 = 1 << 1;
+    pub const PUBLIC: u32 = 1 << 2;
+    pub const TRANSMIT: u32 = 1 << 3;
+    pub const PRIVILEGED_TRANSMIT: u32 = 1 << 4;
+    pub const PERSIST: u32 = 1 << 5;
+    pub const DEPRECATED: u32 = 1 << 6;
+    pub const NOSCRIPT: u32 = 1 << 7;
+    pub const DELTA_ENCODE: u32 = 1 << 8;
+    pub const BLOB: u32 = 1 << 9;
+}
+
+#[derive(Debug, Error)]
+pub enum ObjectPropertyError {
+    #[error("Input ended early, {needed} bits needed but only {remaining} remain.")]
+    UnexpectedEof {
+        needed: usize,
+        remaining: usize
+    },
+    #[error("Expected an object with type hash {expected:#x} but found {found:#x}.")]
+    TypeHashMismatch {
+        expected: u32,
+        found: u32
+    },
+    #[error("Property with hash {hash:#x} declared {declared} bits but {read} were read.")]
+    PropertySizeMismatch {
+        hash: u32,
+        declared: usize,
+        read: usize
+    },
+    #[error("Object {layout} declared {declared} bits but {read} were read.")]
+    ObjectSizeMismatch {
+        layout: String,
+        declared: usize,
+        read: usize
+    },
+    #[error("String of length {len} is too long for its u16 length prefix.")]
+    StringTooLong {
+        len: usize
+    },
+    #[error("List of length {len} is too long for its u32 length prefix.")]
+    ListTooLong {
+        len: usize
+    },
+    #[error("Input holds an invalid string.")]
+    InvalidString,
+    #[error("Value was created from layout {found}, not {expected}.")]
+    LayoutMismatch {
+        expected: String,
+        found: String
+    },
+    #[error("Property {property} has type {ctype}, which has no wire encoding.")]
+    UnknownType {
+        property: String,
+        ctype: String
+    },
+    #[error("Property {property} is declared multiple times in {class}.")]
+    DuplicateProperty {
+        class: String,
+        property: String
+    },
+    #[error("Layout {class} has no field for property {property}.")]
+    UnknownProperty {
+        class: String,
+        property: String
+    },
+    #[error("Property {property} is written as {expected} but its field stores {found}.")]
+    StorageMismatch {
+        property: String,
+        expected: String,
+        found: String
+    },
+    #[error("Class {class} can't be laid out: {source}")]
+    Layout {
+        class: String,
+        source: TypeRegistryError
+    }
+}
+
+/// KingsIsle's hash of class names, used as the type hash of objects.
+pub fn string_id(name: &str) -> u32 {
+    let mut result = 0i32;
+    let mut shift = 0u32;
+    for &byte in name.as_bytes() {
+        let value = byte as i32 - 32;
+        result ^= value.wrapping_shl(shift);
+        if shift > 24 {
+            result ^= value >> (32 - shift);
+            if shift >= 27 {
+                shift -= 32 - 5;
+                continue;
+            }
+        }
+        shift += 5;
+    }
+    result.wrapping_abs() as u32
+}
+
+/// The djb2 hash of property names, masked to 31 bits.
+pub fn djb2(name: &str) -> u32 {
+    name.bytes().fold(5381u32, |hash, byte| hash.wrapping_mul(33).wrapping_add(byte as u32)) & 0x7fff_ffff
+}
+
+/// The hash identifying a property in deep objects, from its name and C++ type.
+pub fn property_hash(name: &str, ctype: &str) -> u32 {
+    string_id(ctype).wrapping_add(djb2(name))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SerializerOptions {
+    /// Pack bools and presence flags into single bits, without aligning anything to bytes.
+    pub bit_packed: bool,
+    /// Write bare values in declaration order, without sizes or property hashes.
+    pub shallow: bool,
+    /// Only properties with all of these flags set are written and, in shallow objects, read.
+    pub property_mask: u32,
+}
+
+/// Writes and reads the value of the field at an index, for one Rust type the field stores.
+#[derive(Clone, Copy)]
+struct Encoding {
+    type_id: TypeId,
+    type_name: &'static str,
+    write: fn(&mut BitWriter, &DynamicStruct, usize) -> Result<(), ObjectPropertyError>,
+    read: fn(&mut BitReader, &mut DynamicStruct, usize) -> Result<(), ObjectPropertyError>,
+}
+
+/// The encodings of a `PropertyValue` and every wrapper around it.
+pub(super) struct PropertyEncoder {
+    wrap: fn(&[TypeWrapper]) -> Option<Encoding>,
+}
+
+fn encoding<W: Wire>() -> Encoding {
+    Encoding {
+        type_id: TypeId::of::<W::Value>(),
+        type_name: type_name::<W::Value>(),
+        write: write_property::<W>,
+        read: read_property::<W>,
+    }
+}
+
+fn wrap_one<W: Wire>(wrapper: TypeWrapper) -> Encoding {
+    match wrapper {
+        TypeWrapper::Pointer => encoding::<PointerWire<W>>(),
+        TypeWrapper::SharedPointer => encoding::<SharedWire<W>>(),
+        TypeWrapper::List => encoding::<ListWire<W>>(),
+    }
+}
+
+/// Wraps `W` in `wrappers`, outermost first, up to the two levels `TypeNameResolver` stores.
+fn wrap<W: Wire>(wrappers: &[TypeWrapper]) -> Option<Encoding> {
+    match *wrappers {
+        [] => Some(encoding::<W>()),
+        [wrapper] => Some(wrap_one::<W>(wrapper)),
+        [outer, TypeWrapper::Pointer] => Some(wrap_one::<PointerWire<W>>(outer)),
+        [outer, TypeWrapper::SharedPointer] => Some(wrap_one::<SharedWire<W>>(outer)),
+        [outer, TypeWrapper::List] => Some(wrap_one::<ListWire<W>>(outer)),
+        _ => None,
+    }
+}
+
+fn write_property<W: Wire>(writer: &mut BitWriter, value: &DynamicStruct, index: usize) -> Result<(), ObjectPropertyError> {
+    W::encode(value.get_field_ref_by_index::<W::Value>(index), writer)
+}
+
+fn read_property<W: Wire>(reader: &mut BitReader, value: &mut DynamicStruct, index: usize) -> Result<(), ObjectPropertyError> {
+    *value.get_field_mut_by_index::<W::Value>(index) = W::decode(reader)?;
+    Ok(())
+}
+
+impl TypeRegistry {
+    /// Registers `T`'s `PropertyValue` implementation, used for every property whose type resolves
+    /// to `T`, on its own or behind pointers and lists.
+    pub fn add_property_value<T: PropertyValue>(&self) {
+        self.property_values
+            .write()
+            .insert(TypeId::of::<T>(), PropertyEncoder { wrap: wrap::<T> });
+    }
+
+    /// Registers the encodings of the types the default resolver and `add_kitypes` name.
+    pub fn add_default_property_values(&self) {
+        macro_rules! add_property_values {
+            ($($ty:ty),* $(,)?) => {
+                $(self.add_property_value::<$ty>();)*
+            };
+        }
+
+        add_property_values!(bool, u8, i8, i16, u16, i32, u32, i64, u64, f32, f64, String, GID, Vector3D, Color, Point);
+    }
+}
+
+/// The field layout and encoding of a property of C++ type `ctype`.
+fn resolve(registry: &TypeRegistry, property: &str, ctype: &str) -> Result<(StaticTypeLayout, Encoding), ObjectPropertyError> {
+    let unknown = || ObjectPropertyError::UnknownType { property: property.into(), ctype: ctype.into() };
+    let layout = registry.try_resolve_type_name(ctype).map_err(|_| unknown())?;
+    let wrapped = registry.try_resolve_wrapped_type_name(ctype).map_err(|_| unknown())?;
+    // `std::wstring` is stored as a `String` like `std::string`, only its encoding differs.
+    let encoding = if wrapped.name == "std::wstring" && wrapped.layout.type_id == TypeId::of::<String>() {
+        wrap::<Wide>(&wrapped.wrappers)
+    } else {
+        let encoders = registry.property_values.read();
+        encoders.get(&wrapped.layout.type_id).and_then(|encoder| (encoder.wrap)(&wrapped.wrappers))
+    };
+    encoding.map(|encoding| (layout, encoding)).ok_or_else(unknown)
+}
+
+#[derive(Clone)]
+struct Property {
+    name: String,
+    index: usize,
+    hash: u32,
+    flags: u32,
+    encoding: Encoding,
+}
+
+/// Where the fields of a class's instances come from.
+enum ClassLayout {
+    /// Every property adds a field.
+    Build(DynamicTypeLayoutBuilder),
+    /// Properties name fields of a layout built elsewhere.
+    Registered(Arc<DynamicTypeLayout>),
+}
+
+/// Collects the properties of an `ObjectPropertyClass` from a type dump.
+pub struct ObjectPropertyClassBuilder<'a> {
+    registry: &'a TypeRegistry,
+    name: String,
+    layout: ClassLayout,
+    properties: Vec<Property>,
+}
+
+impl ObjectPropertyClassBuilder<'_> {
+    /// Adds a property of C++ type `ctype`, panics if it has no wire encoding, see `try_property`.
+    pub fn property(self, name: &str, ctype: &str, flags: u32) -> Self {
+        self.try_property(name, ctype, flags).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Adds a property of C++ type `ctype`, resolved with `TypeRegistry::resolve_type_name` and
+    /// written with the `PropertyValue` registered for the type it wraps. Fails if the type has no
+    /// wire encoding or, for registered layouts, if the layout has no such field or stores it as
+    /// another type.
+    pub fn try_property(mut self, name: &str, ctype: &str, flags: u32) -> Result<Self, ObjectPropertyError> {
+        let (layout, encoding) = resolve(self.registry, name, ctype)?;
+        let (index, type_id, type_name) = match &self.layout {
+            ClassLayout::Build(_) => (self.properties.len(), layout.type_id, layout.type_name()),
+            ClassLayout::Registered(class) => {
+                let index = *class.name_to_index.get(name).ok_or_else(|| ObjectPropertyError::UnknownProperty {
+                    class: class.name.clone(),
+                    property: name.into(),
+                })?;
+                (index, class.field_types[index], class.field_type_names[index])
+            }
+        };
+        if type_id != encoding.type_id {
+            return Err(ObjectPropertyError::StorageMismatch {
+                property: name.into(),
+                expected: encoding.type_name.into(),
+                found: type_name.into(),
+            });
+        }
+
+        self.layout = match self.layout {
+            ClassLayout::Build(builder) => ClassLayout::Build(builder.field(name, &layout)),
+            registered => registered,
+        };
+        self.properties.push(Property {
+            name: name.into(),
+            index,
+            hash: property_hash(name, ctype),
+            flags,
+            encoding,
+        });
+        Ok(self)
+    }
+
+    /// Panics on duplicate property names, see `try_build`.
+    pub fn build(self) -> ObjectPropertyClass {
+        self.try_build().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Reports properties declared more than once instead of panicking. Classes without properties
+    /// are valid, their objects hold nothing but the type hash and, when deep, their size.
+    pub fn try_build(self) -> Result<ObjectPropertyClass, ObjectPropertyError> {
+        for (index, property) in self.properties.iter().enumerate() {
+            if self.properties[..index].iter().any(|other| other.name == property.name) {
+                return Err(ObjectPropertyError::DuplicateProperty {
+                    class: self.name.clone(),
+                    property: property.name.clone(),
+                });
+            }
+        }
+
+        let layout = match self.layout {
+            ClassLayout::Build(builder) => {
+                let layout = builder
+                    .build_sized()
+                    .map_err(|source| ObjectPropertyError::Layout { class: self.name.clone(), source })?;
+                Arc::new(layout)
+            }
+            ClassLayout::Registered(layout) => layout,
+        };
+        Ok(ObjectPropertyClass {
+            type_hash: string_id(&self.name),
+            layout,
+            properties: self.properties,
+        })
+    }
+}
+
+/// A class from the client's type dumps, pairing the `DynamicTypeLayout` of its instances with the
+/// hashes and flags the wire format needs.
+pub struct ObjectPropertyClass {
+    layout: Arc<DynamicTypeLayout>,
+    type_hash: u32,
+    properties: Vec<Property>,
+}
+
+impl ObjectPropertyClass {
+    /// `name` is the class name as the dumps spell it, e.g. `class WizItemTemplate`. Each property
+    /// adds a field to a new layout for the class.
+    pub fn builder<'a>(registry: &'a TypeRegistry, name: &str) -> ObjectPropertyClassBuilder<'a> {
+        ObjectPropertyClassBuilder {
+            registry,
+            name: name.into(),
+            layout: ClassLayout::Build(DynamicTypeLayout::builder(name.into())),
+            properties: Vec::new(),
+        }
+    }
+
+    /// Serializes instances of an existing layout, such as the ones `load_type_dump` registers. The
+    /// class is named after the layout and each property names one of its fields.
+    pub fn for_layout<'a>(registry: &'a TypeRegistry, layout: &Arc<DynamicTypeLayout>) -> ObjectPropertyClassBuilder<'a> {
+        ObjectPropertyClassBuilder {
+            registry,
+            name: layout.name.clone(),
+            layout: ClassLayout::Registered(layout.clone()),
+            properties: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn layout(&self) -> &Arc<DynamicTypeLayout> {
+        &self.layout
+    }
+
+    #[inline]
+    pub fn type_hash(&self) -> u32 {
+        self.type_hash
+    }
+
+    /// The properties written with `options`, in declaration order.
+    fn serialized(&self, options: SerializerOptions) -> impl Iterator<Item = &Property> {
+        self.properties.iter().filter(move |property| {
+            property.flags & options.property_mask == options.property_mask && property.flags & flags::DEPRECATED == 0
+        })
+    }
+
+    pub fn serialize(&self, value: &DynamicStruct, options: SerializerOptions) -> Result<Vec<u8>, ObjectPropertyError> {
+        if value.layout().id() != self.layout.id() {
+            return Err(ObjectPropertyError::LayoutMismatch {
+                expected: self.layout.name.clone(),
+                found: value.layout().name.clone(),
+            });
+        }
+
+        let mut writer = BitWriter { bytes: Vec::new(), len: 0, bit_packed: options.bit_packed };
+        writer.write_bytes(&self.type_hash.to_le_bytes());
+        if options.shallow {
+            for property in self.serialized(options) {
+                (property.encoding.write)(&mut writer, value, property.index)?;
+            }
+        } else {
+            let object_size = writer.reserve_size();
+            for property in self.serialized(options) {
+                let property_size = writer.reserve_size();
+                writer.write_bytes(&property.hash.to_le_bytes());
+                (property.encoding.write)(&mut writer, value, property.index)?;
+                writer.finish_size(property_size);
+            }
+            writer.finish_size(object_size);
+        }
+        Ok(writer.bytes)
+    }
+
+    /// Reads an object of this class, properties missing from deep objects keep their defaults and
+    /// unknown ones are skipped.
+    pub fn deserialize(&self, data: &[u8], options: SerializerOptions) -> Result<DynamicStruct, ObjectPropertyError> {
+        let mut reader = BitReader { data, pos: 0, bit_packed: options.bit_packed };
+        let found = u32::from_le_bytes(reader.read_array()?);
+        if found != self.type_hash {
+            return Err(ObjectPropertyError::TypeHashMismatch { expected: self.type_hash, found });
+        }
+
+        let mut value = DynamicStruct::new(self.layout.clone());
+        if options.shallow {
+            for property in self.serialized(options) {
+                (property.encoding.read)(&mut reader, &mut value, property.index)?;
+            }
+            return Ok(value);
+        }
+
+        let (object_start, object_end) = reader.read_size()?;
+        while reader.pos < object_end {
+            let (start, end) = reader.read_size()?;
+            let hash = u32::from_le_bytes(reader.read_array()?);
+            match self.properties.iter().find(|property| property.hash == hash) {
+                Some(property) => (property.encoding.read)(&mut reader, &mut value, property.index)?,
+                None => reader.pos = reader.pos.max(end),
+            }
+            if reader.pos != end {
+                return Err(ObjectPropertyError::PropertySizeMismatch {
+                    hash,
+                    declared: end - start,
+                    read: reader.pos - start,
+                });
+            }
+        }
+        if reader.pos != object_end {
+            return Err(ObjectPropertyError::ObjectSizeMismatch {
+                layout: self.layout.name.clone(),
+                declared: object_end - object_start,
+                read: reader.pos - object_start,
+            });
+        }
+        Ok(value)
+    }
+}
+
+/// The output of `PropertyValue::write`.
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    /// Length in bits, the last byte may be partially filled.
+    len: usize,
+    bit_packed: bool,
+}
+
+impl BitWriter {
+    /// Writes the low `bits` bits of `value`.
+    pub fn write_bits(&mut self, value: u64, bits: u32) {
+        for bit in 0..bits {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> bit) & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 1 << (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_bits(byte as u64, 8);
+        }
+    }
+
+    /// Writes a bool, a byte or a single bit in bit-packed mode.
+    pub fn write_flag(&mut self, value: bool) {
+        self.write_bits(value as u64, if self.bit_packed { 1 } else { 8 });
+    }
+
+    /// Writes a placeholder size, returning its position for `BitWriter::finish_size`.
+    fn reserve_size(&mut self) -> usize {
+        let at = self.len;
+        self.write_bits(0, 32);
+        at
+    }
+
+    /// Fills in the size reserved at `at` with the number of bits written since.
+    fn finish_size(&mut self, at: usize) {
+        let size = (self.len - at - 32) as u32;
+        for bit in 0..32 {
+            let pos = at + bit;
+            let mask = 1 << (pos % 8);
+            if (size >> bit) & 1 == 1 {
+                self.bytes[pos / 8] |= mask;
+            } else {
+                self.bytes[pos / 8] &= !mask;
+            }
+        }
+    }
+}
+
+/// The input of `PropertyValue::read`.
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    /// Position in bits.
+    pos: usize,
+    bit_packed: bool,
+}
+
+impl BitReader<'_> {
+    pub fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.pos
+    }
+
+    pub fn read_bits(&mut self, bits: u32) -> Result<u64, ObjectPropertyError> {
+        if self.remaining() < bits as usize {
+            return Err(ObjectPropertyError::UnexpectedEof { needed: bits as usize, remaining: self.remaining() });
+        }
+        let mut value = 0;
+        for bit in 0..bits {
+            let byte = self.data[self.pos / 8];
+            value |= (((byte >> (self.pos % 8)) & 1) as u64) << bit;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], ObjectPropertyError> {
+        if self.remaining() < N * 8 {
+            return Err(ObjectPropertyError::UnexpectedEof { needed: N * 8, remaining: self.remaining() });
+        }
+        let mut bytes = [0; N];
+        for byte in &mut bytes {
+            *byte = self.read_bits(8)? as u8;
+        }
+        Ok(bytes)
+    }
+
+    /// Reads a bool written with `BitWriter::write_flag`.
+    pub fn read_flag(&mut self) -> Result<bool, ObjectPropertyError> {
+        Ok(self.read_bits(if self.bit_packed { 1 } else { 8 })? != 0)
+    }
+
+    /// Reads a size field, returning the bit range it covers after checking the input holds it.
+    fn read_size(&mut self) -> Result<(usize, usize), ObjectPropertyError> {
+        let size = u32::from_le_bytes(self.read_array()?) as usize;
+        if size > self.remaining() {
+            return Err(ObjectPropertyError::UnexpectedEof { needed: size, remaining: self.remaining() });
+        }
+        Ok((self.pos, self.pos + size))
+    }
+}
+
+/// Types with an ObjectProperty encoding, captured for properties with
+/// `TypeRegistry::add_property_value`.
+///
+/// Every encoding must take at least one bit, readers rely on it to reject list lengths longer than
+/// the remaining input before reading their elements.
+pub trait PropertyValue: Any + Default {
+    fn write(&self, writer: &mut BitWriter) -> Result<(), ObjectPropertyError>;
+
+    fn read(reader: &mut BitReader) -> Result<Self, ObjectPropertyError>;
+}
+
+/// How a field is written, `Value` is the Rust type it stores.
+trait Wire {
+    type Value: Any + Default;
+
+    fn encode(value: &Self::Value, writer: &mut BitWriter) -> Result<(), ObjectPropertyError>;
+
+    fn decode(reader: &mut BitReader) -> Result<Self::Value, ObjectPropertyError>;
+}
+
+impl<T: PropertyValue> Wire for T {
+    type Value = T;
+
+    #[inline]
+    fn encode(value: &T, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+        value.write(writer)
+    }
+
+    #[inline]
+    fn decode(reader: &mut BitReader) -> Result<T, ObjectPropertyError> {
+        T::read(reader)
+    }
+}
+
+fn write_pointee<W: Wire>(pointee: Option<&W::Value>, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+    writer.write_flag(pointee.is_some());
+    match pointee {
+        Some(pointee) => W::encode(pointee, writer),
+        None => Ok(()),
+    }
+}
+
+fn read_pointee<W: Wire>(reader: &mut BitReader) -> Result<Option<W::Value>, ObjectPropertyError> {
+    match reader.read_flag()? {
+        true => W::decode(reader).map(Some),
+        false => Ok(None),
+    }
+}
+
+/// A raw pointer to `W`.
+struct PointerWire<W>(PhantomData<W>);
+
+impl<W: Wire> Wire for PointerWire<W> {
+    type Value = Option<Box<W::Value>>;
+
+    fn encode(value: &Self::Value, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+        write_pointee::<W>(value.as_deref(), writer)
+    }
+
+    fn decode(reader: &mut BitReader) -> Result<Self::Value, ObjectPropertyError> {
+        Ok(read_pointee::<W>(reader)?.map(Box::new))
+    }
+}
+
+/// A `SharedPointer` to `W`.
+struct SharedWire<W>(PhantomData<W>);
+
+impl<W: Wire> Wire for SharedWire<W> {
+    type Value = Option<Arc<W::Value>>;
+
+    fn encode(value: &Self::Value, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+        write_pointee::<W>(value.as_deref(), writer)
+    }
+
+    fn decode(reader: &mut BitReader) -> Result<Self::Value, ObjectPropertyError> {
+        Ok(read_pointee::<W>(reader)?.map(Arc::new))
+    }
+}
+
+/// A list of `W`, prefixed with its length.
+struct ListWire<W>(PhantomData<W>);
+
+impl<W: Wire> Wire for ListWire<W> {
+    type Value = Vec<W::Value>;
+
+    fn encode(value: &Self::Value, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+        let len = u32::try_from(value.len()).map_err(|_| ObjectPropertyError::ListTooLong { len: value.len() })?;
+        writer.write_bytes(&len.to_le_bytes());
+        value.iter().try_for_each(|element| W::encode(element, writer))
+    }
+
+    fn decode(reader: &mut BitReader) -> Result<Self::Value, ObjectPropertyError> {
+        let len = u32::from_le_bytes(reader.read_array()?) as usize;
+        if len > reader.remaining() {
+            return Err(ObjectPropertyError::UnexpectedEof { needed: len, remaining: reader.remaining() });
+        }
+        (0..len).map(|_| W::decode(reader)).collect()
+    }
+}
+
+macro_rules! le_wire {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl PropertyValue for $ty {
+                #[inline]
+                fn write(&self, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+                    writer.write_bytes(&self.to_le_bytes());
+                    Ok(())
+                }
+
+                #[inline]
+                fn read(reader: &mut BitReader) -> Result<$ty, ObjectPropertyError> {
+                    Ok(<$ty>::from_le_bytes(reader.read_array()?))
+                }
+            }
+        )*
+    };
+}
+
+le_wire!(u8, i8, i16, u16, i32, u32, i64, u64, f32, f64);
+
+impl PropertyValue for bool {
+    #[inline]
+    fn write(&self, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+        writer.write_flag(*self);
+        Ok(())
+    }
+
+    #[inline]
+    fn read(reader: &mut BitReader) -> Result<bool, ObjectPropertyError> {
+        reader.read_flag()
+    }
+}
+
+impl PropertyValue for GID {
+    fn write(&self, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+        self.id.write(writer)?;
+        self.ty.write(writer)
+    }
+
+    fn read(reader: &mut BitReader) -> Result<GID, ObjectPropertyError> {
+        Ok(GID { id: u32::read(reader)?, ty: u32::read(reader)? })
+    }
+}
+
+impl PropertyValue for Vector3D {
+    fn write(&self, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+        self.x.write(writer)?;
+        self.y.write(writer)?;
+        self.z.write(writer)
+    }
+
+    fn read(reader: &mut BitReader) -> Result<Vector3D, ObjectPropertyError> {
+        Ok(Vector3D { x: f32::read(reader)?, y: f32::read(reader)?, z: f32::read(reader)? })
+    }
+}
+
+impl PropertyValue for Color {
+    fn write(&self, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+        writer.write_bytes(&[self.r, self.g, self.b, self.a]);
+        Ok(())
+    }
+
+    fn read(reader: &mut BitReader) -> Result<Color, ObjectPropertyError> {
+        let [r, g, b, a] = reader.read_array()?;
+        Ok(Color { r, b, g, a })
+    }
+}
+
+impl PropertyValue for Point {
+    fn write(&self, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+        self.x.write(writer)?;
+        self.y.write(writer)
+    }
+
+    fn read(reader: &mut BitReader) -> Result<Point, ObjectPropertyError> {
+        Ok(Point { x: f32::read(reader)?, y: f32::read(reader)? })
+    }
+}
+
+/// `std::string`, prefixed with its length in bytes.
+impl PropertyValue for String {
+    fn write(&self, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+        let len = u16::try_from(self.len()).map_err(|_| ObjectPropertyError::StringTooLong { len: self.len() })?;
+        writer.write_bytes(&len.to_le_bytes());
+        writer.write_bytes(self.as_bytes());
+        Ok(())
+    }
+
+    fn read(reader: &mut BitReader) -> Result<String, ObjectPropertyError> {
+        let len = u16::read(reader)?;
+        let bytes = (0..len).map(|_| u8::read(reader)).collect::<Result<Vec<_>, _>>()?;
+        std::str::from_utf8(&bytes).map(Into::into).map_err(|_| ObjectPropertyError::InvalidString)
+    }
+}
+
+/// `std::wstring`, prefixed with its length in UTF-16 code units.
+struct Wide;
+
+impl Wire for Wide {
+    type Value = String;
+
+    fn encode(value: &String, writer: &mut BitWriter) -> Result<(), ObjectPropertyError> {
+        let units = value.encode_utf16().collect::<Vec<_>>();
+        let len = u16::try_from(units.len()).map_err(|_| ObjectPropertyError::StringTooLong { len: units.len() })?;
+        writer.write_bytes(&len.to_le_bytes());
+        for unit in units {
+            writer.write_bytes(&unit.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    fn decode(reader: &mut BitReader) -> Result<String, ObjectPropertyError> {
+        let len = u16::read(reader)?;
+        let units = (0..len).map(|_| u16::read(reader)).collect::<Result<Vec<_>, _>>()?;
+        std::string::String::from_utf16(&units)
+            .map(Into::into)
+            .map_err(|_| ObjectPropertyError::InvalidString)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::add_kitypes;
+
+    fn registry() -> TypeRegistry {
+        let registry = TypeRegistry::default();
+        registry.update_type_names(add_kitypes);
+        registry.add_default_property_values();
+        registry
+    }
+
+    fn item_class(registry: &TypeRegistry) -> ObjectPropertyClass {
+        ObjectPropertyClass::builder(registry, "class WizItem")
+            .property("m_templateID", "unsigned int", flags::SAVE | flags::TRANSMIT)
+            .property("m_displayName", "std::wstring", flags::SAVE)
+            .property("m_owner", "gid", flags::TRANSMIT)
+            .property("m_oldName", "std::string", flags::SAVE | flags::DEPRECATED)
+            .build()
+    }
+
+    fn flag_class(registry: &TypeRegistry) -> ObjectPropertyClass {
+        ObjectPropertyClass::builder(registry, "class Flags")
+            .property("m_visible", "bool", flags::SAVE)
+            .property("m_locked", "bool", flags::SAVE)
+            .property("m_count", "class SharedPointer<int>", flags::SAVE)
+            .property("m_tier", "unsigned char", flags::SAVE)
+            .build()
+    }
+
+    fn le(value: u32) -> [u8; 4] {
+        value.to_le_bytes()
+    }
+
+    #[test]
+    fn hashes_match_the_reference_values() {
+        assert_eq!(djb2(""), 5381);
+        assert_eq!(djb2("a"), 5381 * 33 + 97);
+        assert_eq!(string_id("a"), 65);
+        assert_eq!(string_id("ab"), 65 ^ (66 << 5));
+        assert_eq!(property_hash("m_id", "int"), string_id("int").wrapping_add(djb2("m_id")));
+        // Long names wrap the shift around without overflowing.
+        assert_ne!(string_id("class SharedPointer<class WizItemTemplate>"), 0);
+    }
+
+    #[test]
+    fn reads_and_writes_deep_objects() {
+        let registry = registry();
+        let class = item_class(&registry);
+        let mut blob = Vec::new();
+        blob.extend(le(string_id("class WizItem")));
+        blob.extend(le(3 * (32 + 32) + 32 + 16 + 2 * 16 + 64));
+        blob.extend(le(32 + 32));
+        blob.extend(le(property_hash("m_templateID", "unsigned int")));
+        blob.extend(le(1234));
+        blob.extend(le(32 + 16 + 2 * 16));
+        blob.extend(le(property_hash("m_displayName", "std::wstring")));
+        blob.extend([2, 0, b'H', 0, 0xe9, 0]);
+        blob.extend(le(32 + 64));
+        blob.extend(le(property_hash("m_owner", "gid")));
+        blob.extend(le(7).into_iter().chain(le(9)));
+
+        let item = class.deserialize(&blob, SerializerOptions::default()).unwrap();
+        assert_eq!(*item.get_field_ref::<u32>("m_templateID"), 1234);
+        assert_eq!(item.get_field_ref::<String>("m_displayName"), "Hé");
+        assert_eq!(item.get_field_ref::<GID>("m_owner").ty, 9);
+
+        assert_eq!(class.serialize(&item, SerializerOptions::default()).unwrap(), blob);
+    }
+
+    #[test]
+    fn deep_objects_skip_unknown_properties() {
+        let registry = registry();
+        let class = item_class(&registry);
+        let mut blob = Vec::new();
+        blob.extend(le(class.type_hash()));
+        blob.extend(le(2 * (32 + 32) + 24 + 32));
+        blob.extend(le(32 + 24));
+        blob.extend(le(0xdead));
+        blob.extend([1, 2, 3]);
+        blob.extend(le(32 + 32));
+        blob.extend(le(property_hash("m_templateID", "unsigned int")));
+        blob.extend(le(5));
+
+        let item = class.deserialize(&blob, SerializerOptions::default()).unwrap();
+        assert_eq!(*item.get_field_ref::<u32>("m_templateID"), 5);
+        assert_eq!(item.get_field_ref::<String>("m_displayName"), "");
+    }
+
+    #[test]
+    fn reads_and_writes_bit_packed_shallow_objects() {
+        let registry = registry();
+        let class = flag_class(&registry);
+        let options = SerializerOptions { bit_packed: true, shallow: true, ..Default::default() };
+        let mut blob = le(string_id("class Flags")).to_vec();
+        // visible, !locked, count present, then count = 5 and tier = 0xab straddling bytes.
+        blob.extend([0x2d, 0, 0, 0, 0x58, 0x05]);
+
+        let flags = class.deserialize(&blob, options).unwrap();
+        assert!(*flags.get_field_ref::<bool>("m_visible"));
+        assert!(!*flags.get_field_ref::<bool>("m_locked"));
+        assert_eq!(flags.get_field_ref::<Option<Arc<i32>>>("m_count").as_deref(), Some(&5));
+        assert_eq!(*flags.get_field_ref::<u8>("m_tier"), 0xab);
+        assert_eq!(class.serialize(&flags, options).unwrap(), blob);
+
+        let unpacked = SerializerOptions { shallow: true, ..Default::default() };
+        let mut blob = le(string_id("class Flags")).to_vec();
+        blob.extend([1, 0, 1, 5, 0, 0, 0, 0xab]);
+        assert_eq!(class.serialize(&flags, unpacked).unwrap(), blob);
+    }
+
+    #[test]
+    fn property_mask_and_deprecation_filter_shallow_objects() {
+        let registry = registry();
+        let class = item_class(&registry);
+        let mut item = DynamicStruct::new(class.layout().clone());
+        item.set_field("m_templateID", 3u32);
+        item.set_field("m_oldName", String::from("old"));
+
+        let options = SerializerOptions { shallow: true, property_mask: flags::TRANSMIT, ..Default::default() };
+        let mut blob = le(class.type_hash()).to_vec();
+        blob.extend(le(3));
+        blob.extend([0; 8]);
+        assert_eq!(class.serialize(&item, options).unwrap(), blob);
+        let read_back = class.deserialize(&blob, options).unwrap();
+        assert_eq!(*read_back.get_field_ref::<u32>("m_templateID"), 3);
+    }
+
+    #[test]
+    fn parses_every_spelling_of_supported_types() {
+        let registry = registry();
+        let class = ObjectPropertyClass::builder(&registry, "class Spellings")
+            .property("m_id", "unsigned __int64", flags::SAVE)
+            .property("m_kind", "enum Kind", flags::SAVE)
+            .property("m_count", "const long int *", flags::SAVE)
+            .build();
+        let options = SerializerOptions { shallow: true, ..Default::default() };
+        let mut blob = le(string_id("class Spellings")).to_vec();
+        blob.extend(u64::MAX.to_le_bytes());
+        blob.extend(le(2));
+        blob.extend([1]);
+        blob.extend(le(7));
+
+        let value = class.deserialize(&blob, options).unwrap();
+        assert_eq!(*value.get_field_ref::<u64>("m_id"), u64::MAX);
+        assert_eq!(*value.get_field_ref::<i32>("m_kind"), 2);
+        assert_eq!(value.get_field_ref::<Option<Box<i32>>>("m_count").as_deref(), Some(&7));
+        assert_eq!(class.serialize(&value, options).unwrap(), blob);
+
+        let err = ObjectPropertyClass::builder(&registry, "class Unknown")
+            .try_property("m_map", "std::map<int, int>", flags::SAVE)
+            .err()
+            .unwrap();
+        assert!(matches!(&err, ObjectPropertyError::UnknownType { property, .. } if property == "m_map"), "{}", err);
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        let registry = registry();
+        let class = item_class(&registry);
+        let item = DynamicStruct::new(class.layout().clone());
+        let blob = class.serialize(&item, SerializerOptions::default()).unwrap();
+
+        for len in 0..blob.len() {
+            assert!(class.deserialize(&blob[..len], SerializerOptions::default()).is_err(), "{} bytes", len);
+        }
+
+        let err = flag_class(&registry).deserialize(&blob, SerializerOptions::default()).unwrap_err();
+        assert!(matches!(err, ObjectPropertyError::TypeHashMismatch { .. }), "{}", err);
+
+        // Shrink the first property's size so its value overruns it.
+        let mut corrupt = blob.clone();
+        corrupt[8] -= 8;
+        let err = class.deserialize(&corrupt, SerializerOptions::default()).unwrap_err();
+        assert!(matches!(err, ObjectPropertyError::PropertySizeMismatch { declared: 56, read: 64, .. }), "{}", err);
+
+        let other = DynamicStruct::new(flag_class(&registry).layout().clone());
+        let err = class.serialize(&other, SerializerOptions::default()).unwrap_err();
+        assert!(matches!(err, ObjectPropertyError::LayoutMismatch { .. }), "{}", err);
+    }
+
+    #[test]
+    fn lists_and_registered_type_names_have_wire_encodings() {
+        let registry = registry();
+        registry.add_type_name::<u32>("DWORD");
+        let class = ObjectPropertyClass::builder(&registry, "class Inventory")
+            .property("m_ids", "std::vector<DWORD>", flags::SAVE)
+            .property("m_spots", "std::list<class Point*>", flags::SAVE)
+            .property("m_names", "std::vector<std::wstring>", flags::SAVE)
+            .build();
+        let options = SerializerOptions { shallow: true, ..Default::default() };
+        let mut blob = le(string_id("class Inventory")).to_vec();
+        blob.extend(le(2).into_iter().chain(le(7)).chain(le(9)));
+        blob.extend(le(2));
+        blob.extend([0, 1]);
+        blob.extend(1.5f32.to_le_bytes().into_iter().chain(2f32.to_le_bytes()));
+        blob.extend(le(1));
+        blob.extend([1, 0, b'A', 0]);
+
+        let value = class.deserialize(&blob, options).unwrap();
+        assert_eq!(value.get_field_ref::<Vec<u32>>("m_ids"), &[7, 9]);
+        let spots = value.get_field_ref::<Vec<Option<Box<Point>>>>("m_spots");
+        assert!(spots[0].is_none());
+        assert_eq!(spots[1].as_ref().map(|spot| spot.y), Some(2.0));
+        assert_eq!(value.get_field_ref::<Vec<String>>("m_names")[0], "A");
+        assert_eq!(class.serialize(&value, options).unwrap(), blob);
+
+        let mut blob = le(string_id("class Inventory")).to_vec();
+        blob.extend(le(u32::MAX));
+        let err = class.deserialize(&blob, options).unwrap_err();
+        assert!(matches!(err, ObjectPropertyError::UnexpectedEof { .. }), "{}", err);
+    }
+
+    #[test]
+    fn classes_may_be_empty_but_not_repeat_properties() {
+        let registry = registry();
+        let class = ObjectPropertyClass::builder(&registry, "class Marker").build();
+        let marker = DynamicStruct::new(class.layout().clone());
+        let blob = class.serialize(&marker, SerializerOptions::default()).unwrap();
+        assert_eq!(blob, [le(class.type_hash()), le(0)].concat());
+        assert!(class.deserialize(&blob, SerializerOptions::default()).is_ok());
+
+        let err = ObjectPropertyClass::builder(&registry, "class Twice")
+            .property("m_x", "int", flags::SAVE)
+            .property("m_x", "float", flags::SAVE)
+            .try_build()
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, ObjectPropertyError::DuplicateProperty { class, property } if class == "class Twice" && property == "m_x"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn registered_layouts_serialize_their_instances() {
+        let registry = registry();
+        let layout = DynamicTypeLayout::builder("class WizItem".into())
+            .field("m_globalID", &registry.resolve_type_name("gid").unwrap())
+            .field("m_templateID", &registry.resolve_type_name("unsigned int").unwrap())
+            .build();
+        registry.add_dyn(layout).unwrap();
+        let layout = registry.get_dynamic_layout("class WizItem").unwrap();
+
+        let class = ObjectPropertyClass::for_layout(&registry, &layout)
+            .property("m_templateID", "unsigned int", flags::SAVE)
+            .build();
+        assert_eq!(class.type_hash(), string_id("class WizItem"));
+        let mut item = registry.create_dynamic("class WizItem");
+        item.set_field("m_templateID", 4u32);
+        let options = SerializerOptions { shallow: true, ..Default::default() };
+        let blob = class.serialize(&item, options).unwrap();
+        assert_eq!(blob, [le(class.type_hash()), le(4)].concat());
+        assert_eq!(*class.deserialize(&blob, options).unwrap().get_field_ref::<u32>("m_templateID"), 4);
+
+        let err = ObjectPropertyClass::for_layout(&registry, &layout)
+            .try_property("m_globalID", "unsigned int", flags::SAVE)
+            .err()
+            .unwrap();
+        assert!(matches!(err, ObjectPropertyError::StorageMismatch { .. }), "{}", err);
+        let err = ObjectPropertyClass::for_layout(&registry, &layout)
+            .try_property("m_name", "std::string", flags::SAVE)
+            .err()
+            .unwrap();
+        assert!(matches!(err, ObjectPropertyError::UnknownProperty { .. }), "{}", err);
+
+        // Nested objects are out of scope, even for classes with a registered layout.
+        let err = ObjectPropertyClass::builder(&registry, "class Bag")
+            .try_property("m_item", "class WizItem*", flags::SAVE)
+            .err()
+            .unwrap();
+        assert!(matches!(&err, ObjectPropertyError::UnknownType { ctype, .. } if ctype == "class WizItem*"), "{}", err);
+    }
+}
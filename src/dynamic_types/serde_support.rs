@@ -0,0 +1,403 @@
+use std::{any::Any, fmt, sync::Arc};
+
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    ser::{self, SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::{DynamicFieldError, DynamicStruct, DynamicStructMut, DynamicStructRef, DynamicTypeLayout, FieldKind, StaticTypeLayout};
+
+impl StaticTypeLayout {
+    /// Captures `T`'s `Serialize` and `Deserialize` implementations so dynamic structs holding this
+    /// type can be serialized and read back with `DynamicStructSeed`. Values pass through a
+    /// `serde_json::Value` on the way, so `T` must be representable as JSON.
+    pub fn with_serde<T: Any + Serialize + DeserializeOwned>(mut self) -> Self {
+        self.check_type::<T>();
+        self.serialize_fn = Some(serialize_field::<T>);
+        self.deserialize_fn = Some(deserialize_field::<T>);
+        self
+    }
+}
+
+/// # Safety
+/// `src` must point to a valid `T`.
+unsafe fn serialize_field<T: Serialize>(src: *const u8) -> Result<serde_json::Value, serde_json::Error> {
+    serde_json::to_value(&*src.cast::<T>())
+}
+
+/// # Safety
+/// `dst` must point to a valid `T`, which is dropped and replaced.
+unsafe fn deserialize_field<T: DeserializeOwned>(value: serde_json::Value, dst: *mut u8) -> Result<(), serde_json::Error> {
+    *dst.cast::<T>() = serde_json::from_value(value)?;
+    Ok(())
+}
+
+/// Serializes as a map keyed by field name, see `DynamicStructRef`'s implementation.
+impl Serialize for DynamicStruct {
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.as_ref().serialize(serializer)
+    }
+}
+
+/// Serializes as a map keyed by field name in declaration order. Nested structs are maps too and
+/// arrays are sequences. Fails if any field's layout was created without
+/// `StaticTypeLayout::with_serde`.
+impl Serialize for DynamicStructRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let layout = self.type_layout;
+        let mut map = serializer.serialize_map(Some(layout.field_kinds.len()))?;
+        for (index, kind) in layout.field_kinds.iter().enumerate() {
+            let offset = layout.field_offsets[index];
+            let name = &layout.field_names[index];
+            map.serialize_entry(name.as_str(), &FieldSer { name, kind, data: &self.data[offset..offset + kind.size()] })?;
+        }
+        map.end()
+    }
+}
+
+/// A single field value, `name` is only used in errors.
+struct FieldSer<'a> {
+    name: &'a str,
+    kind: &'a FieldKind,
+    data: &'a [u8],
+}
+
+impl Serialize for FieldSer<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.kind {
+            FieldKind::Static(layout) => match layout.serialize_fn {
+                Some(serialize) => unsafe { serialize(self.data.as_ptr()) }
+                    .map_err(ser::Error::custom)?
+                    .serialize(serializer),
+                None => Err(ser::Error::custom(DynamicFieldError::<()>::FieldNotSerializable {
+                    name: self.name.into(),
+                    type_name: layout.name.into(),
+                })),
+            },
+            FieldKind::Dynamic(layout) => DynamicStructRef { type_layout: layout, data: self.data }.serialize(serializer),
+            FieldKind::Array { element, len } => {
+                let size = element.size();
+                let mut seq = serializer.serialize_seq(Some(*len))?;
+                for index in 0..*len {
+                    seq.serialize_element(&FieldSer {
+                        name: self.name,
+                        kind: element,
+                        data: &self.data[index * size..(index + 1) * size],
+                    })?;
+                }
+                seq.end()
+            }
+        }
+    }
+}
+
+/// What `DynamicStructSeed` does with keys that name no field of the layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownFields {
+    /// Skip the value, like `#[derive(Deserialize)]` does.
+    #[default]
+    Ignore,
+    Error,
+}
+
+/// What `DynamicStructSeed` does with fields the input doesn't mention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingFields {
+    /// Fail, like `#[derive(Deserialize)]` does.
+    #[default]
+    Error,
+    /// Keep the field's default, including any override given to the layout builder.
+    Default,
+}
+
+/// How a `DynamicStructSeed` treats input that doesn't match its layout, applied to nested structs too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeserializeOptions {
+    pub unknown_fields: UnknownFields,
+    pub missing_fields: MissingFields,
+}
+
+/// Deserializes a `DynamicStruct` of `layout` from the map `DynamicStruct`'s `Serialize`
+/// implementation writes. The struct starts from its layout's defaults and each field found in the
+/// input replaces its default. Fails on any field whose layout was created without
+/// `StaticTypeLayout::with_serde`.
+#[derive(Clone)]
+pub struct DynamicStructSeed {
+    layout: Arc<DynamicTypeLayout>,
+    options: DeserializeOptions,
+}
+
+impl DynamicStructSeed {
+    pub fn new(layout: Arc<DynamicTypeLayout>) -> Self {
+        Self { layout, options: DeserializeOptions::default() }
+    }
+
+    pub fn with_options(layout: Arc<DynamicTypeLayout>, options: DeserializeOptions) -> Self {
+        Self { layout, options }
+    }
+
+    #[inline]
+    pub fn unknown_fields(mut self, policy: UnknownFields) -> Self {
+        self.options.unknown_fields = policy;
+        self
+    }
+
+    #[inline]
+    pub fn missing_fields(mut self, policy: MissingFields) -> Self {
+        self.options.missing_fields = policy;
+        self
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for DynamicStructSeed {
+    type Value = DynamicStruct;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<DynamicStruct, D::Error> {
+        let mut value = DynamicStruct::new(self.layout);
+        StructDe { target: value.as_mut(), options: self.options }.deserialize(deserializer)?;
+        Ok(value)
+    }
+}
+
+/// Deserializes into an initialised struct in place, replacing each field the input contains.
+struct StructDe<'a> {
+    target: DynamicStructMut<'a>,
+    options: DeserializeOptions,
+}
+
+impl<'de> DeserializeSeed<'de> for StructDe<'_> {
+    type Value = ();
+
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StructDe<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a map of the fields of {}", self.target.type_layout.name)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let layout = self.target.type_layout;
+        let data = self.target.data;
+        let mut seen = vec![false; layout.field_kinds.len()];
+
+        while let Some(key) = map.next_key::<std::string::String>()? {
+            let Some(&index) = layout.name_to_index.get(&key) else {
+                match self.options.unknown_fields {
+                    UnknownFields::Ignore => {
+                        map.next_value::<IgnoredAny>()?;
+                        continue;
+                    }
+                    UnknownFields::Error => {
+                        return Err(de::Error::custom(format_args!("unknown field `{}` in {}", key, layout.name)))
+                    }
+                }
+            };
+            if seen[index] {
+                return Err(de::Error::custom(format_args!("duplicate field `{}` in {}", key, layout.name)));
+            }
+            seen[index] = true;
+
+            let kind = &layout.field_kinds[index];
+            let offset = layout.field_offsets[index];
+            map.next_value_seed(FieldDe {
+                name: &layout.field_names[index],
+                kind,
+                data: &mut data[offset..offset + kind.size()],
+                options: self.options,
+            })?;
+        }
+
+        if self.options.missing_fields == MissingFields::Error {
+            if let Some(index) = seen.iter().position(|seen| !seen) {
+                return Err(de::Error::custom(format_args!(
+                    "missing field `{}` in {}",
+                    layout.field_names[index], layout.name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Deserializes a single initialised field in place, `name` is only used in errors.
+struct FieldDe<'a> {
+    name: &'a str,
+    kind: &'a FieldKind,
+    data: &'a mut [u8],
+    options: DeserializeOptions,
+}
+
+impl<'de> DeserializeSeed<'de> for FieldDe<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        match self.kind {
+            FieldKind::Static(layout) => match layout.deserialize_fn {
+                Some(deserialize) => {
+                    let value = serde_json::Value::deserialize(deserializer)?;
+                    unsafe { deserialize(value, self.data.as_mut_ptr()) }.map_err(de::Error::custom)
+                }
+                None => Err(de::Error::custom(DynamicFieldError::<()>::FieldNotDeserializable {
+                    name: self.name.into(),
+                    type_name: layout.name.into(),
+                })),
+            },
+            FieldKind::Dynamic(layout) => StructDe {
+                target: DynamicStructMut { type_layout: layout, data: self.data },
+                options: self.options,
+            }
+            .deserialize(deserializer),
+            FieldKind::Array { .. } => deserializer.deserialize_seq(self),
+        }
+    }
+}
+
+/// Only reached for array fields, which expect exactly `len` elements.
+impl<'de> Visitor<'de> for FieldDe<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            FieldKind::Array { len, .. } => write!(f, "an array of {} elements for field {}", len, self.name),
+            _ => write!(f, "field {}", self.name),
+        }
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let FieldKind::Array { element, len } = self.kind else {
+            return Err(de::Error::invalid_type(de::Unexpected::Seq, &self));
+        };
+        let size = element.size();
+        for index in 0..*len {
+            let found = seq.next_element_seed(FieldDe {
+                name: self.name,
+                kind: element,
+                data: &mut self.data[index * size..(index + 1) * size],
+                options: self.options,
+            })?;
+            if found.is_none() {
+                return Err(de::Error::invalid_length(index, &self));
+            }
+        }
+        if seq.next_element::<IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(len + 1, &self));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smartstring::alias::String;
+
+    fn serializable<T: Any + Default + Serialize + DeserializeOwned>() -> StaticTypeLayout {
+        StaticTypeLayout::of::<T>().with_serde::<T>()
+    }
+
+    /// `Window { title, scale = 1, tags, pos: Pos { x, y }, margins: [Option<u8>; 2] }`.
+    fn window_layout() -> Arc<DynamicTypeLayout> {
+        let pos = Arc::new(
+            DynamicTypeLayout::builder("Pos".into())
+                .field("x", &serializable::<i16>())
+                .field("y", &serializable::<i16>())
+                .build(),
+        );
+        Arc::new(
+            DynamicTypeLayout::builder("Window".into())
+                .field("title", &serializable::<String>())
+                .field_with_default("scale", &serializable::<u32>(), 1u32)
+                .field("tags", &serializable::<Vec<std::string::String>>())
+                .nested("pos", &pos)
+                .array("margins", &serializable::<Option<u8>>(), 2)
+                .build(),
+        )
+    }
+
+    fn read(seed: DynamicStructSeed, json: &str) -> Result<DynamicStruct, serde_json::Error> {
+        seed.deserialize(&mut serde_json::Deserializer::from_str(json))
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let layout = window_layout();
+        let mut window = DynamicStruct::new(layout.clone());
+        window.set_field("title", String::from("Main"));
+        window.set_field("scale", 2u32);
+        window.set_field("tags", vec!["dock".to_string()]);
+        window.get_struct_mut("pos").set_field("x", -3i16);
+        window.get_array_mut::<Option<u8>>("margins")[1] = Some(7);
+
+        let json = serde_json::to_string(&window).unwrap();
+        assert_eq!(json, r#"{"title":"Main","scale":2,"tags":["dock"],"pos":{"x":-3,"y":0},"margins":[null,7]}"#);
+
+        let read_back = read(DynamicStructSeed::new(layout), &json).unwrap();
+        assert_eq!(serde_json::to_string(&read_back).unwrap(), json);
+        assert_eq!(read_back.get_field_ref::<String>("title"), "Main");
+        assert_eq!(read_back.get_array_ref::<Option<u8>>("margins"), &[None, Some(7)]);
+    }
+
+    #[test]
+    fn unknown_fields_follow_the_policy() {
+        let layout = window_layout();
+        let json = r#"{"title":"Main","scale":2,"tags":[],"pos":{"x":1,"y":2,"z":3},"margins":[1,2],"theme":"dark"}"#;
+
+        let window = read(DynamicStructSeed::new(layout.clone()), json).unwrap();
+        assert_eq!(*window.get_struct_ref("pos").get_field_ref::<i16>("x"), 1);
+
+        let err = read(DynamicStructSeed::new(layout).unknown_fields(UnknownFields::Error), json).unwrap_err();
+        assert!(err.to_string().contains("unknown field `z` in Pos"), "{}", err);
+    }
+
+    #[test]
+    fn missing_fields_follow_the_policy() {
+        let layout = window_layout();
+        let json = r#"{"title":"Main","pos":{"x":5}}"#;
+
+        let err = read(DynamicStructSeed::new(layout.clone()), json).unwrap_err();
+        assert!(err.to_string().contains("missing field `y` in Pos"), "{}", err);
+
+        let window = read(DynamicStructSeed::new(layout).missing_fields(MissingFields::Default), json).unwrap();
+        assert_eq!(*window.get_field_ref::<u32>("scale"), 1);
+        assert_eq!(*window.get_struct_ref("pos").get_field_ref::<i16>("x"), 5);
+        assert!(window.get_field_ref::<Vec<std::string::String>>("tags").is_empty());
+    }
+
+    #[test]
+    fn rejects_duplicates_and_wrong_array_lengths() {
+        let options = DeserializeOptions { missing_fields: MissingFields::Default, ..Default::default() };
+        let seed = DynamicStructSeed::with_options(window_layout(), options);
+
+        let err = read(seed.clone(), r#"{"scale":1,"scale":2}"#).unwrap_err();
+        assert!(err.to_string().contains("duplicate field `scale`"), "{}", err);
+
+        let err = read(seed.clone(), r#"{"margins":[1]}"#).unwrap_err();
+        assert!(err.to_string().contains("invalid length 1"), "{}", err);
+
+        let err = read(seed, r#"{"margins":[1,2,3]}"#).unwrap_err();
+        assert!(err.to_string().contains("invalid length 3"), "{}", err);
+    }
+
+    #[test]
+    fn fields_without_serde_fail() {
+        let layout = Arc::new(
+            DynamicTypeLayout::builder("Secret".into())
+                .field("key", &StaticTypeLayout::of::<u64>())
+                .build(),
+        );
+
+        let err = serde_json::to_string(&DynamicStruct::new(layout.clone())).unwrap_err();
+        assert!(err.to_string().contains("Field key of type u64 cannot be serialized."), "{}", err);
+
+        let err = read(DynamicStructSeed::new(layout), r#"{"key":1}"#).unwrap_err();
+        assert!(err.to_string().contains("Field key of type u64 cannot be deserialized."), "{}", err);
+    }
+}
@@ -0,0 +1,420 @@
+//! Loads dynamic layouts from the JSON type dumps of the game client.
+//!
+//! A dump is an array of classes, each with its `name`, an optional `base` class name and its
+//! `properties` keyed by name. Properties carry their C++ `type` and their `offset` in the client's
+//! object:
+//!
+//! ```json
+//! [{ "name": "class Foo", "base": "class Bar",
+//!    "properties": { "m_x": { "type": "int", "offset": 72, "flags": 31, "hash": 1234 } } }]
+//! ```
+//!
+//! Other keys are ignored, dumps carry much more than layouts need, such as the `flags` and `hash`
+//! of the ObjectProperty format.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use ahash::{AHashMap, AHashSet};
+use serde::Deserialize;
+use smartstring::alias::String;
+use thiserror::Error;
+
+use super::{
+    type_expr::parse_type,
+    type_names::{unwrap_expr, wrap},
+    DynamicStruct, DynamicTypeLayout, FieldKind, TypeRegistry, TypeRegistryError,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClassDump {
+    pub name: String,
+    #[serde(default)]
+    pub base: Option<String>,
+    #[serde(default)]
+    pub properties: BTreeMap<String, PropertyDump>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PropertyDump {
+    #[serde(rename = "type")]
+    pub ctype: String,
+    pub offset: usize,
+}
+
+#[derive(Debug, Error)]
+pub enum TypeDumpError {
+    #[error("Type dump is not valid: {source}")]
+    Json {
+        source: serde_json::Error
+    },
+    #[error("Class {class} is declared multiple times.")]
+    DuplicateClass {
+        class: String
+    },
+    #[error("Property {property} of {class} has unhandled type {ctype}.")]
+    UnknownType {
+        class: String,
+        property: String,
+        ctype: String
+    },
+    #[error("Base {base} of {class} is neither in the dump nor registered.")]
+    UnknownBase {
+        class: String,
+        base: String
+    },
+    #[error("Class {class} was skipped, its base {base} failed to load.")]
+    BaseNotLoaded {
+        class: String,
+        base: String
+    },
+    #[error("Class {class} was skipped, the class {referenced} of its property {property} failed to load.")]
+    PropertyNotLoaded {
+        class: String,
+        property: String,
+        referenced: String
+    },
+    #[error("Class {class} inherits from or contains itself.")]
+    InheritanceCycle {
+        class: String
+    },
+    #[error("Class {class} can't be registered: {source}")]
+    Registry {
+        class: String,
+        source: TypeRegistryError
+    }
+}
+
+/// What `load_type_dump` registered, and why everything else wasn't.
+#[derive(Debug, Default)]
+pub struct TypeDumpReport {
+    /// Names of the registered classes, in dump order.
+    pub registered: Vec<String>,
+    pub errors: Vec<TypeDumpError>,
+}
+
+impl TypeDumpReport {
+    /// Whether every class of the dump was registered.
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Parses a type dump and registers a layout for every class in `registry`. Property types are
+/// resolved with `TypeRegistry::resolve_field_kind`, see `add_kitypes` for the KingsIsle types dumps
+/// use, so properties can hold other classes of the dump.
+///
+/// Properties are declared in `offset` order, and those a class inherits from its base are left to
+/// the base layout. Bases and the classes properties hold are loaded first wherever they appear in
+/// the dump, or taken from the registry if the dump doesn't declare them. Classes behind pointers
+/// and lists may still be loading, so classes can point at each other. Classes without properties
+/// get empty layouts. Every class that loads is registered even when others fail, the errors of all
+/// failing classes are collected. Nothing is registered if the dump isn't valid JSON or declares a
+/// class twice.
+pub fn load_type_dump(registry: &TypeRegistry, json: &str) -> TypeDumpReport {
+    let classes: Vec<ClassDump> = match serde_json::from_str(json) {
+        Ok(classes) => classes,
+        Err(source) => return TypeDumpReport { registered: Vec::new(), errors: vec![TypeDumpError::Json { source }] },
+    };
+
+    let mut loader = Loader {
+        registry,
+        classes: AHashMap::with_capacity(classes.len()),
+        loaded: AHashMap::with_capacity(classes.len()),
+        loading: AHashSet::new(),
+        errors: Vec::new(),
+    };
+    for class in &classes {
+        if loader.classes.insert(class.name.as_str(), class).is_some() {
+            loader.errors.push(TypeDumpError::DuplicateClass { class: class.name.clone() });
+        }
+    }
+    if !loader.errors.is_empty() {
+        return TypeDumpReport { registered: Vec::new(), errors: loader.errors };
+    }
+
+    let registered = classes
+        .iter()
+        .filter(|class| loader.load(&class.name).is_some())
+        .map(|class| class.name.clone())
+        .collect();
+    TypeDumpReport { registered, errors: loader.errors }
+}
+
+struct Loader<'a> {
+    registry: &'a TypeRegistry,
+    classes: AHashMap<&'a str, &'a ClassDump>,
+    /// Classes already attempted, `None` for those that failed.
+    loaded: AHashMap<&'a str, Option<Arc<DynamicTypeLayout>>>,
+    loading: AHashSet<&'a str>,
+    errors: Vec<TypeDumpError>,
+}
+
+impl<'a> Loader<'a> {
+    /// Loads `name` from the dump, `None` if it failed and its errors have been recorded.
+    fn load(&mut self, name: &'a str) -> Option<Arc<DynamicTypeLayout>> {
+        if let Some(layout) = self.loaded.get(name) {
+            return layout.clone();
+        }
+        let class = self.classes[name];
+        if !self.loading.insert(name) {
+            self.errors.push(TypeDumpError::InheritanceCycle { class: class.name.clone() });
+            return None;
+        }
+        let layout = self.build(class);
+        self.loading.remove(name);
+        self.loaded.insert(name, layout.clone());
+        layout
+    }
+
+    fn build(&mut self, class: &'a ClassDump) -> Option<Arc<DynamicTypeLayout>> {
+        let base = match class.base.as_deref().filter(|base| !base.is_empty()) {
+            Some(base) => Some(self.base(class, base)?),
+            None => None,
+        };
+
+        let mut properties: Vec<_> = class.properties.iter().collect();
+        properties.sort_by_key(|(_, property)| property.offset);
+
+        let mut builder = DynamicTypeLayout::builder(class.name.clone());
+        let mut failed = false;
+        for (name, property) in properties {
+            if base.as_ref().is_some_and(|base| base.name_to_index.contains_key(name.as_str())) {
+                continue;
+            }
+            match self.field_kind(class, name, &property.ctype) {
+                Some(FieldKind::Static(layout)) => builder = builder.field(name, &layout),
+                Some(FieldKind::Dynamic(layout)) => builder = builder.nested(name, &layout),
+                _ => failed = true,
+            }
+        }
+        if failed {
+            return None;
+        }
+        if let Some(base) = &base {
+            builder = builder.base(base);
+        }
+
+        // Classes without properties of their own are common in dumps, e.g. as markers or bases.
+        let registered = builder
+            .build_sized()
+            .and_then(|layout| self.registry.add_dyn(layout));
+        if let Err(source) = registered {
+            self.errors.push(TypeDumpError::Registry { class: class.name.clone(), source });
+            return None;
+        }
+        // The registry wraps the layout in its own `Arc`, derived layouts share that one.
+        self.registry.get_dynamic_layout(&class.name)
+    }
+
+    /// What property `name` of type `ctype` stores, after loading the dump class it holds. `None`
+    /// if the type is unknown or its class failed to load, the error has been recorded then.
+    fn field_kind(&mut self, class: &ClassDump, name: &str, ctype: &str) -> Option<FieldKind> {
+        let referenced = parse_type(ctype).ok().and_then(|expr| {
+            let (wrappers, inner) = unwrap_expr(&expr);
+            let (&referenced, _) = self.classes.get_key_value(inner.to_string().as_str())?;
+            Some((referenced, wrappers))
+        });
+        if let Some((referenced, wrappers)) = referenced {
+            if !wrappers.is_empty() && self.loading.contains(referenced) {
+                // Pointers and lists hold `DynamicStruct`s of any layout, so classes that point at
+                // each other don't need the other one registered first.
+                if let Some(layout) = wrap::<DynamicStruct>(&wrappers) {
+                    return Some(FieldKind::Static(layout));
+                }
+            } else if self.load(referenced).is_none() {
+                self.errors.push(TypeDumpError::PropertyNotLoaded {
+                    class: class.name.clone(),
+                    property: name.into(),
+                    referenced: referenced.into(),
+                });
+                return None;
+            }
+        }
+
+        match self.registry.resolve_field_kind(ctype) {
+            Ok(kind) => Some(kind),
+            Err(_) => {
+                self.errors.push(TypeDumpError::UnknownType {
+                    class: class.name.clone(),
+                    property: name.into(),
+                    ctype: ctype.into(),
+                });
+                None
+            }
+        }
+    }
+
+    fn base(&mut self, class: &ClassDump, base: &'a str) -> Option<Arc<DynamicTypeLayout>> {
+        if self.classes.contains_key(base) {
+            let layout = self.load(base);
+            if layout.is_none() {
+                self.errors.push(TypeDumpError::BaseNotLoaded { class: class.name.clone(), base: base.into() });
+            }
+            return layout;
+        }
+        let layout = self.registry.get_dynamic_layout(base);
+        if layout.is_none() {
+            self.errors.push(TypeDumpError::UnknownBase { class: class.name.clone(), base: base.into() });
+        }
+        layout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::add_kitypes;
+    use std::any::TypeId;
+
+    fn registry() -> TypeRegistry {
+        let registry = TypeRegistry::default();
+        registry.update_type_names(add_kitypes);
+        registry
+    }
+
+    const DUMP: &str = r#"[
+        {
+            "name": "class WizItem",
+            "base": "class CoreObject",
+            "hash": 99,
+            "properties": {
+                "m_templateID": { "type": "unsigned int", "offset": 80, "flags": 31, "hash": 1 },
+                "m_displayName": { "type": "std::wstring", "offset": 88, "flags": 7, "hash": 2 },
+                "m_globalID": { "type": "gid", "offset": 72, "flags": 31, "hash": 3 }
+            }
+        },
+        {
+            "name": "class CoreObject",
+            "properties": {
+                "m_globalID": { "type": "gid", "offset": 72, "flags": 31, "hash": 3 }
+            }
+        }
+    ]"#;
+
+    #[test]
+    fn registers_classes_with_their_bases() {
+        let registry = registry();
+        let report = load_type_dump(&registry, DUMP);
+        assert!(report.is_ok(), "{:?}", report.errors);
+        assert_eq!(report.registered, ["class WizItem", "class CoreObject"]);
+
+        let item = registry.get_dynamic_layout("class WizItem").unwrap();
+        assert_eq!(item.field_names, ["m_globalID", "m_templateID", "m_displayName"]);
+        assert!(registry.is_a("class WizItem", "class CoreObject"));
+        assert!(Arc::ptr_eq(item.base.as_ref().unwrap(), &registry.get_dynamic_layout("class CoreObject").unwrap()));
+
+        let item = registry.create_dynamic("class WizItem");
+        assert_eq!(item.get_field_ref::<String>("m_displayName"), "");
+    }
+
+    #[test]
+    fn collects_every_unknown_type() {
+        let dump = r#"[
+            { "name": "class A", "properties": {
+                "m_map": { "type": "std::map<int, int>", "offset": 0 },
+                "m_ptr": { "type": "int&", "offset": 4 },
+                "m_ok": { "type": "int", "offset": 8 }
+            } },
+            { "name": "class B", "base": "class A", "properties": {} },
+            { "name": "class C", "properties": { "m_x": { "type": "float", "offset": 0 } } }
+        ]"#;
+        let registry = registry();
+        let report = load_type_dump(&registry, dump);
+        assert_eq!(report.registered, ["class C"]);
+
+        let messages: Vec<_> = report.errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "Property m_map of class A has unhandled type std::map<int, int>.",
+                "Property m_ptr of class A has unhandled type int&.",
+                "Class class B was skipped, its base class A failed to load.",
+            ]
+        );
+        assert!(registry.get_dynamic_layout("class A").is_none());
+        assert!(registry.get_dynamic_layout("class C").is_some());
+    }
+
+    #[test]
+    fn bases_come_from_the_registry_or_fail() {
+        let registry = registry();
+        let report =
+            load_type_dump(&registry, r#"[{ "name": "class Base", "properties": { "m_id": { "type": "int", "offset": 0 } } }]"#);
+        assert!(report.is_ok());
+
+        let dump = r#"[
+            { "name": "class Derived", "base": "class Base", "properties": {} },
+            { "name": "class Orphan", "base": "class Missing" },
+            { "name": "class X", "base": "class Y" },
+            { "name": "class Y", "base": "class X" }
+        ]"#;
+        let TypeDumpReport { registered, errors } = load_type_dump(&registry, dump);
+        assert_eq!(registered, ["class Derived"]);
+        assert!(registry.is_a("class Derived", "class Base"));
+        assert!(matches!(&errors[0], TypeDumpError::UnknownBase { base, .. } if base == "class Missing"));
+        assert!(matches!(&errors[1], TypeDumpError::InheritanceCycle { class } if class == "class X"));
+        assert!(matches!(&errors[2], TypeDumpError::BaseNotLoaded { class, .. } if class == "class Y"));
+        assert!(matches!(&errors[3], TypeDumpError::BaseNotLoaded { class, .. } if class == "class X"));
+    }
+
+    #[test]
+    fn malformed_dumps_are_errors() {
+        let registry = registry();
+        let report = load_type_dump(&registry, r#"[{ "properties": {} }]"#);
+        assert!(matches!(&report.errors[..], [TypeDumpError::Json { .. }]));
+
+        let report = load_type_dump(&registry, r#"[{ "name": "class A" }, { "name": "class A" }]"#);
+        assert!(matches!(&report.errors[..], [TypeDumpError::DuplicateClass { .. }]));
+        assert!(report.registered.is_empty());
+        assert!(registry.get_dynamic_layout("class A").is_none());
+    }
+
+    #[test]
+    fn classes_without_properties_load() {
+        let dump = r#"[
+            { "name": "class Derived", "base": "class Marker", "properties": { "m_id": { "type": "int", "offset": 8 } } },
+            { "name": "class Marker", "properties": {} },
+            { "name": "class Tag" }
+        ]"#;
+        let registry = registry();
+        let report = load_type_dump(&registry, dump);
+        assert!(report.is_ok(), "{:?}", report.errors);
+        assert_eq!(registry.get_dynamic_layout("class Marker").unwrap().total_size, 0);
+        assert!(registry.is_a("class Derived", "class Marker"));
+
+        let derived = registry.create_dynamic("class Derived");
+        assert_eq!(*derived.get_field_ref::<i32>("m_id"), 0);
+        assert_eq!(registry.create_dynamic("class Tag").size_of(), 0);
+    }
+
+    #[test]
+    fn properties_hold_classes_of_the_dump() {
+        let dump = r#"[
+            { "name": "class Inventory", "properties": {
+                "m_first": { "type": "class Item", "offset": 0 },
+                "m_shared": { "type": "class SharedPointer<class Item>", "offset": 16 },
+                "m_items": { "type": "std::vector<class Item*>", "offset": 24 }
+            } },
+            { "name": "class Item", "properties": {
+                "m_id": { "type": "unsigned int", "offset": 0 },
+                "m_owner": { "type": "class Inventory*", "offset": 8 }
+            } },
+            { "name": "class Loop", "properties": { "m_self": { "type": "class Loop", "offset": 0 } } }
+        ]"#;
+        let registry = registry();
+        let report = load_type_dump(&registry, dump);
+        assert_eq!(report.registered, ["class Inventory", "class Item"]);
+        assert!(matches!(&report.errors[0], TypeDumpError::InheritanceCycle { class } if class == "class Loop"));
+        assert!(matches!(&report.errors[1], TypeDumpError::PropertyNotLoaded { property, .. } if property == "m_self"));
+
+        let item = registry.get_dynamic_layout("class Item").unwrap();
+        assert_eq!(item.field_types[1], TypeId::of::<Option<Box<DynamicStruct>>>());
+
+        let mut inventory = registry.create_dynamic("class Inventory");
+        assert_eq!(inventory.get_struct_ref("m_first").layout().name, "class Item");
+        assert!(inventory.get_field_ref::<Option<Arc<DynamicStruct>>>("m_shared").is_none());
+        let items = inventory.get_field_mut::<Vec<Option<Box<DynamicStruct>>>>("m_items");
+        items.push(Some(Box::new(registry.create_dynamic("class Item"))));
+    }
+}
@@ -0,0 +1,511 @@
+//! Parses C++ type names from the client's type dumps, e.g. `class SharedPointer<class Foo>*`.
+
+use std::fmt;
+
+use smartstring::alias::String;
+use thiserror::Error;
+
+/// A parsed C++ type. `Display` spells it the way `TypeNameResolver` looks names up, so
+/// `unsigned long` displays as `unsigned int` and `struct Foo` as `class Foo`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeExpr {
+    Primitive(PrimitiveType),
+    /// `std::string`, or `std::wstring` if `wide`.
+    String { wide: bool },
+    /// `class Foo` or `struct Foo`, namespaces included in the name.
+    Class(String),
+    /// `enum Foo`.
+    Enum(String),
+    /// A name without a keyword, usually a typedef such as `gid`.
+    Named(String),
+    /// `T*`.
+    Pointer(Box<TypeExpr>),
+    /// `class SharedPointer<T>`.
+    SharedPointer(Box<TypeExpr>),
+    /// `std::vector<T>` or `std::list<T>`.
+    List(Box<TypeExpr>),
+    /// Any other template, `class` is implied.
+    Template { name: String, args: Vec<TypeExpr> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrimitiveType {
+    Bool,
+    Int { signed: bool, bits: u8 },
+    Float { bits: u8 },
+}
+
+impl PrimitiveType {
+    /// The spelling `TypeNameResolver` registers this primitive under.
+    pub fn canonical_name(self) -> &'static str {
+        match self {
+            PrimitiveType::Bool => "bool",
+            PrimitiveType::Int { signed: true, bits: 8 } => "char",
+            PrimitiveType::Int { signed: false, bits: 8 } => "unsigned char",
+            PrimitiveType::Int { signed: true, bits: 16 } => "short",
+            PrimitiveType::Int { signed: false, bits: 16 } => "unsigned short",
+            PrimitiveType::Int { signed: true, bits: 32 } => "int",
+            PrimitiveType::Int { signed: false, bits: 32 } => "unsigned int",
+            PrimitiveType::Int { signed: true, .. } => "__int64",
+            PrimitiveType::Int { signed: false, .. } => "unsigned __int64",
+            PrimitiveType::Float { bits: 32 } => "float",
+            PrimitiveType::Float { .. } => "double",
+        }
+    }
+}
+
+impl fmt::Display for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeExpr::Primitive(primitive) => f.write_str(primitive.canonical_name()),
+            TypeExpr::String { wide: false } => f.write_str("std::string"),
+            TypeExpr::String { wide: true } => f.write_str("std::wstring"),
+            TypeExpr::Class(name) => write!(f, "class {}", name),
+            TypeExpr::Enum(name) => write!(f, "enum {}", name),
+            TypeExpr::Named(name) => f.write_str(name),
+            TypeExpr::Pointer(inner) => write!(f, "{}*", inner),
+            TypeExpr::SharedPointer(inner) => write!(f, "class SharedPointer<{}>", inner),
+            TypeExpr::List(inner) => write!(f, "std::vector<{}>", inner),
+            TypeExpr::Template { name, args } => {
+                write!(f, "class {}<", name)?;
+                for (index, arg) in args.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                f.write_str(">")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TypeExprError {
+    #[error("Unexpected {found} at byte {position} of `{input}`, expected {expected}.")]
+    UnexpectedToken {
+        input: String,
+        position: usize,
+        found: String,
+        expected: &'static str
+    },
+    #[error("Unexpected end of `{input}`, expected {expected}.")]
+    UnexpectedEnd {
+        input: String,
+        expected: &'static str
+    },
+    #[error("No type registered as {name}.")]
+    UnknownType {
+        name: String
+    },
+    #[error("Type {name} nests more than two pointers or lists.")]
+    TooManyWrappers {
+        name: String
+    },
+    #[error("Templates and pointers in `{input}` nest more than {} levels deep at byte {position}.", MAX_NESTING)]
+    TooDeep {
+        input: String,
+        position: usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Ident(&'a str),
+    Scope,
+    Less,
+    Greater,
+    Comma,
+    Star,
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(ident) => write!(f, "`{}`", ident),
+            Token::Scope => f.write_str("`::`"),
+            Token::Less => f.write_str("`<`"),
+            Token::Greater => f.write_str("`>`"),
+            Token::Comma => f.write_str("`,`"),
+            Token::Star => f.write_str("`*`"),
+        }
+    }
+}
+
+/// Keywords that make up primitive types, in any valid order.
+const PRIMITIVE_WORDS: &[&str] = &[
+    "signed", "unsigned", "bool", "char", "wchar_t", "short", "int", "long", "float", "double", "__int8",
+    "__int16", "__int32", "__int64",
+];
+
+/// How many templates and pointers may wrap the innermost type, deeper types are rejected rather than
+/// parsed and resolved recursively.
+pub const MAX_NESTING: usize = 64;
+
+/// Parses a whole C++ type name, failing on the first token that doesn't fit.
+pub fn parse_type(input: &str) -> Result<TypeExpr, TypeExprError> {
+    let mut parser = Parser { input, tokens: tokenize(input)?, pos: 0, depth: 0, levels: 0 };
+    let expr = parser.type_expr()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(_) => Err(parser.unexpected("the end of the type")),
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(Token<'_>, usize)>, TypeExprError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '<' => Token::Less,
+            '>' => Token::Greater,
+            ',' => Token::Comma,
+            '*' => Token::Star,
+            ':' if chars.next_if(|&(_, c)| c == ':').is_some() => Token::Scope,
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = position + 1;
+                while let Some((next, _)) = chars.next_if(|&(_, c)| c.is_ascii_alphanumeric() || c == '_') {
+                    end = next + 1;
+                }
+                Token::Ident(&input[position..end])
+            }
+            c => {
+                return Err(TypeExprError::UnexpectedToken {
+                    input: input.into(),
+                    position,
+                    found: format!("`{}`", c).into(),
+                    expected: "a type",
+                })
+            }
+        };
+        tokens.push((token, position));
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<(Token<'a>, usize)>,
+    pos: usize,
+    /// Templates whose arguments are currently being parsed, see `MAX_NESTING`.
+    depth: usize,
+    /// Templates and pointers around the innermost type of the expression parsed last.
+    levels: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).map(|(token, _)| *token)
+    }
+
+    fn unexpected(&self, expected: &'static str) -> TypeExprError {
+        match self.tokens.get(self.pos) {
+            Some((token, position)) => TypeExprError::UnexpectedToken {
+                input: self.input.into(),
+                position: *position,
+                found: token.to_string().into(),
+                expected,
+            },
+            None => TypeExprError::UnexpectedEnd { input: self.input.into(), expected },
+        }
+    }
+
+    fn expect(&mut self, token: Token<'a>, expected: &'static str) -> Result<(), TypeExprError> {
+        if self.peek() != Some(token) {
+            return Err(self.unexpected(expected));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn skip_qualifiers(&mut self) {
+        while let Some(Token::Ident("const" | "volatile")) = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn type_expr(&mut self) -> Result<TypeExpr, TypeExprError> {
+        self.skip_qualifiers();
+        self.levels = 0;
+        let mut expr = match self.peek() {
+            Some(Token::Ident("class" | "struct")) => {
+                self.pos += 1;
+                self.named(false)?
+            }
+            Some(Token::Ident("enum")) => {
+                self.pos += 1;
+                TypeExpr::Enum(self.path()?)
+            }
+            Some(Token::Ident(word)) if PRIMITIVE_WORDS.contains(&word) => TypeExpr::Primitive(self.primitive()?),
+            Some(Token::Ident(_)) => self.named(true)?,
+            _ => return Err(self.unexpected("a type")),
+        };
+        loop {
+            self.skip_qualifiers();
+            if self.peek() != Some(Token::Star) {
+                return Ok(expr);
+            }
+            self.nest()?;
+            self.pos += 1;
+            expr = TypeExpr::Pointer(Box::new(expr));
+        }
+    }
+
+    /// A name with its namespaces, e.g. `std::vector`.
+    fn path(&mut self) -> Result<String, TypeExprError> {
+        let mut path = String::new();
+        loop {
+            match self.peek() {
+                Some(Token::Ident(ident)) => path.push_str(ident),
+                _ => return Err(self.unexpected("a name")),
+            }
+            self.pos += 1;
+            if self.peek() != Some(Token::Scope) {
+                return Ok(path);
+            }
+            self.pos += 1;
+            path.push_str("::");
+        }
+    }
+
+    fn named(&mut self, bare: bool) -> Result<TypeExpr, TypeExprError> {
+        let name = self.path()?;
+        if self.peek() != Some(Token::Less) {
+            return Ok(match name.as_str() {
+                "std::string" => TypeExpr::String { wide: false },
+                "std::wstring" => TypeExpr::String { wide: true },
+                _ if bare => TypeExpr::Named(name),
+                _ => TypeExpr::Class(name),
+            });
+        }
+        if self.depth == MAX_NESTING {
+            return Err(self.too_deep());
+        }
+        self.pos += 1;
+        self.depth += 1;
+        let expr = self.template(name);
+        self.depth -= 1;
+        expr
+    }
+
+    /// Adds a level around the expression parsed last, failing at the current token if that nests
+    /// too deep.
+    fn nest(&mut self) -> Result<(), TypeExprError> {
+        if self.levels == MAX_NESTING {
+            return Err(self.too_deep());
+        }
+        self.levels += 1;
+        Ok(())
+    }
+
+    fn too_deep(&self) -> TypeExprError {
+        let position = self.tokens.get(self.pos).map_or(self.input.len(), |(_, position)| *position);
+        TypeExprError::TooDeep { input: self.input.into(), position }
+    }
+
+    /// The arguments of template `name`, after its `<`.
+    fn template(&mut self, name: String) -> Result<TypeExpr, TypeExprError> {
+        let wrapper: Option<fn(Box<TypeExpr>) -> TypeExpr> = match name.as_str() {
+            "std::vector" | "std::list" => Some(TypeExpr::List),
+            _ if name.rsplit("::").next() == Some("SharedPointer") => Some(TypeExpr::SharedPointer),
+            _ => None,
+        };
+        if let Some(wrapper) = wrapper {
+            let inner = self.type_expr()?;
+            self.nest()?;
+            self.expect(Token::Greater, "`>`")?;
+            return Ok(wrapper(Box::new(inner)));
+        }
+
+        let mut args = Vec::new();
+        let mut levels = 0;
+        loop {
+            args.push(self.type_expr()?);
+            levels = levels.max(self.levels);
+            match self.peek() {
+                Some(Token::Comma) => self.pos += 1,
+                Some(Token::Greater) => break,
+                _ => return Err(self.unexpected("`,` or `>`")),
+            }
+        }
+        self.levels = levels;
+        self.nest()?;
+        self.pos += 1;
+        Ok(TypeExpr::Template { name, args })
+    }
+
+    /// A run of primitive keywords such as `unsigned long long int`, rejecting contradictions.
+    fn primitive(&mut self) -> Result<PrimitiveType, TypeExprError> {
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum Base {
+            Bool,
+            Char,
+            WChar,
+            Short,
+            Int,
+            Long,
+            LongLong,
+            Sized(u8),
+            Float,
+            Double,
+        }
+
+        let mut signed = None;
+        let mut base = None;
+        loop {
+            self.skip_qualifiers();
+            let Some(Token::Ident(word)) = self.peek() else { break };
+            let next = match (word, base) {
+                ("signed" | "unsigned", _) if signed.is_some() => None,
+                ("signed" | "unsigned", Some(Base::Bool | Base::WChar | Base::Float | Base::Double)) => None,
+                ("signed" | "unsigned", _) => {
+                    signed = Some(word == "signed");
+                    Some(base)
+                }
+                ("bool" | "wchar_t" | "float" | "double", _) if signed.is_some() => None,
+                ("bool", None) => Some(Some(Base::Bool)),
+                ("char", None) => Some(Some(Base::Char)),
+                ("wchar_t", None) => Some(Some(Base::WChar)),
+                ("short", None | Some(Base::Int)) => Some(Some(Base::Short)),
+                ("long", None | Some(Base::Int)) => Some(Some(Base::Long)),
+                ("long", Some(Base::Long)) => Some(Some(Base::LongLong)),
+                ("int", None) => Some(Some(Base::Int)),
+                ("int", Some(Base::Short | Base::Long | Base::LongLong)) => Some(base),
+                ("__int8", None) => Some(Some(Base::Sized(8))),
+                ("__int16", None) => Some(Some(Base::Sized(16))),
+                ("__int32", None) => Some(Some(Base::Sized(32))),
+                ("__int64", None) => Some(Some(Base::Sized(64))),
+                ("float", None) => Some(Some(Base::Float)),
+                ("double", None | Some(Base::Long)) => Some(Some(Base::Double)),
+                (word, _) if !PRIMITIVE_WORDS.contains(&word) => break,
+                _ => None,
+            };
+            match next {
+                Some(next) => base = next,
+                None => return Err(self.unexpected("a type specifier that fits the ones before it")),
+            }
+            self.pos += 1;
+        }
+
+        let int = |bits| PrimitiveType::Int { signed: signed.unwrap_or(true), bits };
+        Ok(match base {
+            Some(Base::Bool) => PrimitiveType::Bool,
+            Some(Base::Char) | Some(Base::Sized(8)) => int(8),
+            Some(Base::WChar) => PrimitiveType::Int { signed: false, bits: 16 },
+            Some(Base::Short) | Some(Base::Sized(16)) => int(16),
+            // `long` is 32 bits in the client, as on every Windows target.
+            None | Some(Base::Int | Base::Long) | Some(Base::Sized(32)) => int(32),
+            Some(Base::LongLong) | Some(Base::Sized(_)) => int(64),
+            Some(Base::Float) => PrimitiveType::Float { bits: 32 },
+            Some(Base::Double) => PrimitiveType::Float { bits: 64 },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(signed: bool, bits: u8) -> TypeExpr {
+        TypeExpr::Primitive(PrimitiveType::Int { signed, bits })
+    }
+
+    fn class(name: &str) -> TypeExpr {
+        TypeExpr::Class(name.into())
+    }
+
+    #[test]
+    fn parses_primitives_with_their_width() {
+        for (input, expected) in [
+            ("bool", TypeExpr::Primitive(PrimitiveType::Bool)),
+            ("char", int(true, 8)),
+            ("unsigned char", int(false, 8)),
+            ("wchar_t", int(false, 16)),
+            ("short int", int(true, 16)),
+            ("unsigned long", int(false, 32)),
+            ("unsigned", int(false, 32)),
+            ("long long", int(true, 64)),
+            ("unsigned __int64", int(false, 64)),
+            ("const unsigned int const", int(false, 32)),
+            ("long double", TypeExpr::Primitive(PrimitiveType::Float { bits: 64 })),
+        ] {
+            assert_eq!(parse_type(input), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parses_classes_wrappers_and_templates() {
+        assert_eq!(
+            parse_type("class SharedPointer<class Foo>*"),
+            Ok(TypeExpr::Pointer(Box::new(TypeExpr::SharedPointer(Box::new(class("Foo"))))))
+        );
+        assert_eq!(
+            parse_type("std::vector<class SharedPointer<struct ns::Bar>>"),
+            Ok(TypeExpr::List(Box::new(TypeExpr::SharedPointer(Box::new(class("ns::Bar"))))))
+        );
+        assert_eq!(parse_type("std::list<enum Kind>"), Ok(TypeExpr::List(Box::new(TypeExpr::Enum("Kind".into())))));
+        assert_eq!(parse_type("const std::wstring"), Ok(TypeExpr::String { wide: true }));
+        assert_eq!(parse_type("gid"), Ok(TypeExpr::Named("gid".into())));
+        assert_eq!(
+            parse_type("class Map<int, std::string>"),
+            Ok(TypeExpr::Template { name: "Map".into(), args: vec![int(true, 32), TypeExpr::String { wide: false }] })
+        );
+    }
+
+    #[test]
+    fn displays_canonical_names() {
+        for (input, canonical) in [
+            ("unsigned long", "unsigned int"),
+            ("struct Foo*", "class Foo*"),
+            ("std::list<long long>", "std::vector<__int64>"),
+            ("class SharedPointer<const class Foo>", "class SharedPointer<class Foo>"),
+            ("class Map<char,bool>", "class Map<char, bool>"),
+        ] {
+            assert_eq!(parse_type(input).unwrap().to_string(), canonical);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_unexpected_token() {
+        let unexpected = |input: &str, position: usize, found: &str, expected: &'static str| {
+            assert_eq!(
+                parse_type(input),
+                Err(TypeExprError::UnexpectedToken { input: input.into(), position, found: found.into(), expected })
+            );
+        };
+        unexpected("unsigned float", 9, "`float`", "a type specifier that fits the ones before it");
+        unexpected("short char", 6, "`char`", "a type specifier that fits the ones before it");
+        unexpected("int&", 3, "`&`", "a type");
+        unexpected("std::vector<int, int>", 15, "`,`", "`>`");
+        unexpected("class Foo bar", 10, "`bar`", "the end of the type");
+        unexpected("class <int>", 6, "`<`", "a name");
+        unexpected("std:vector", 3, "`:`", "a type");
+
+        assert_eq!(
+            parse_type("class SharedPointer<int"),
+            Err(TypeExprError::UnexpectedEnd { input: "class SharedPointer<int".into(), expected: "`>`" })
+        );
+        assert!(parse_type("").is_err());
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let nested = |depth: usize| format!("{}int{}", "std::vector<".repeat(depth), ">".repeat(depth));
+        assert!(parse_type(&nested(MAX_NESTING)).is_ok());
+        assert_eq!(
+            parse_type(&nested(MAX_NESTING + 1)),
+            Err(TypeExprError::TooDeep { input: nested(MAX_NESTING + 1).into(), position: 12 * MAX_NESTING + 11 })
+        );
+
+        let pointers = |depth: usize| format!("std::vector<int>{}", "*".repeat(depth));
+        assert!(parse_type(&pointers(MAX_NESTING - 1)).is_ok());
+        assert_eq!(
+            parse_type(&pointers(MAX_NESTING)),
+            Err(TypeExprError::TooDeep { input: pointers(MAX_NESTING).into(), position: 16 + MAX_NESTING - 1 })
+        );
+
+        assert!(matches!(parse_type(&"std::vector<".repeat(200_000)), Err(TypeExprError::TooDeep { .. })));
+        assert!(matches!(parse_type(&format!("int{}", "*".repeat(200_000))), Err(TypeExprError::TooDeep { .. })));
+    }
+}
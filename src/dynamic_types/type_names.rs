@@ -0,0 +1,419 @@
+//! Resolves C++ type names from the client's type dumps to `StaticTypeLayout`s.
+
+use std::{any::Any, sync::Arc};
+
+use ahash::AHashMap;
+use smartstring::alias::String;
+
+use super::{
+    type_expr::{parse_type, TypeExpr, TypeExprError, MAX_NESTING},
+    DynamicStruct, FieldKind, StaticTypeLayout, TypeRegistry,
+};
+
+/// The Rust type a wrapper puts around the type it wraps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypeWrapper {
+    /// `Option<Box<T>>`, for raw pointers.
+    Pointer,
+    /// `Option<Arc<T>>`, for reference counted pointers.
+    SharedPointer,
+    /// `Vec<T>`, for containers.
+    List,
+}
+
+/// A C++ type name with the layout to store it as, and the layout of every wrapper around it.
+#[derive(Clone)]
+struct NamedType {
+    name: String,
+    layout: StaticTypeLayout,
+    wrap: fn(&[TypeWrapper]) -> Option<StaticTypeLayout>,
+}
+
+fn wrap_one<T: Any>(wrapper: TypeWrapper) -> StaticTypeLayout {
+    match wrapper {
+        TypeWrapper::Pointer => StaticTypeLayout::of::<Option<Box<T>>>(),
+        TypeWrapper::SharedPointer => StaticTypeLayout::of::<Option<Arc<T>>>(),
+        TypeWrapper::List => StaticTypeLayout::of::<Vec<T>>(),
+    }
+}
+
+/// Wraps `T` in `wrappers`, outermost first. Only two levels are supported since every level
+/// multiplies the Rust types instantiated per registered name.
+pub(super) fn wrap<T: Any>(wrappers: &[TypeWrapper]) -> Option<StaticTypeLayout> {
+    match *wrappers {
+        [wrapper] => Some(wrap_one::<T>(wrapper)),
+        [outer, TypeWrapper::Pointer] => Some(wrap_one::<Option<Box<T>>>(outer)),
+        [outer, TypeWrapper::SharedPointer] => Some(wrap_one::<Option<Arc<T>>>(outer)),
+        [outer, TypeWrapper::List] => Some(wrap_one::<Vec<T>>(outer)),
+        _ => None,
+    }
+}
+
+/// Splits `expr` into its pointer and list wrappers, outermost first, and the type they wrap.
+pub(super) fn unwrap_expr(mut expr: &TypeExpr) -> (Vec<TypeWrapper>, &TypeExpr) {
+    let mut wrappers = Vec::new();
+    loop {
+        let (wrapper, inner) = match expr {
+            TypeExpr::Pointer(inner) => (TypeWrapper::Pointer, inner),
+            TypeExpr::SharedPointer(inner) => (TypeWrapper::SharedPointer, inner),
+            TypeExpr::List(inner) => (TypeWrapper::List, inner),
+            _ => return (wrappers, expr),
+        };
+        wrappers.push(wrapper);
+        expr = inner;
+    }
+}
+
+/// A resolved C++ type split into its wrappers and the registered type they wrap, for callers that
+/// handle each level themselves.
+#[derive(Debug, Clone)]
+pub struct WrappedType {
+    /// Outermost first.
+    pub wrappers: Vec<TypeWrapper>,
+    /// The name the wrapped type is registered under, `int` for enums without a registered name.
+    pub name: String,
+    pub layout: StaticTypeLayout,
+}
+
+#[derive(Clone)]
+struct WrapperRule {
+    prefix: String,
+    suffix: String,
+    wrapper: TypeWrapper,
+}
+
+/// Maps C++ type names to layouts. Names are looked up exactly, then with each wrapper rule in the
+/// order they were added, stripping its prefix and suffix and wrapping the inner type, itself
+/// resolved like any other name. Anything else is parsed with `parse_type` and resolved with
+/// `resolve_expr`, so spelling variants such as `unsigned long int` or `const struct Foo` find
+/// their registered names, and rules apply inside parsed pointers and templates too.
+///
+/// The default resolver knows the C++ primitives, `std::string` and `std::wstring`.
+#[derive(Clone)]
+pub struct TypeNameResolver {
+    types: AHashMap<String, NamedType>,
+    rules: Vec<WrapperRule>,
+}
+
+impl Default for TypeNameResolver {
+    fn default() -> Self {
+        let mut resolver = Self::empty();
+        resolver.add::<bool>("bool");
+        resolver.add::<u8>("unsigned char");
+        resolver.add::<i8>("char");
+        resolver.add::<i16>("short");
+        resolver.add::<u16>("unsigned short");
+        resolver.add::<i32>("int");
+        resolver.add::<u32>("unsigned int");
+        resolver.add::<i32>("long");
+        resolver.add::<u32>("unsigned long");
+        resolver.add::<i64>("__int64");
+        resolver.add::<u64>("unsigned __int64");
+        resolver.add::<f32>("float");
+        resolver.add::<f64>("double");
+        resolver.add::<String>("std::string");
+        resolver.add::<String>("std::wstring");
+        resolver
+    }
+}
+
+impl TypeNameResolver {
+    /// A resolver without any names or wrapper rules.
+    pub fn empty() -> Self {
+        Self { types: AHashMap::new(), rules: Vec::new() }
+    }
+
+    /// Maps `name` to `T`, replacing any previous mapping of `name`.
+    pub fn add<T: Any + Default>(&mut self, name: &str) {
+        self.add_layout::<T>(name, StaticTypeLayout::of::<T>());
+    }
+
+    /// Maps `name` to a prebuilt layout of `T`, e.g. one extended with `StaticTypeLayout::with_debug`.
+    /// Wrappers around `name` get plain layouts. Panics if `layout` is not a layout of `T`.
+    pub fn add_layout<T: Any>(&mut self, name: &str, layout: StaticTypeLayout) {
+        layout.check_type::<T>();
+        self.types.insert(name.into(), NamedType { name: name.into(), layout, wrap: wrap::<T> });
+    }
+
+    /// Resolves names starting with `prefix` and ending with `suffix` to `wrapper` around the
+    /// registered type between them.
+    pub fn add_wrapper(&mut self, prefix: &str, suffix: &str, wrapper: TypeWrapper) {
+        self.rules.push(WrapperRule { prefix: prefix.into(), suffix: suffix.into(), wrapper });
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<StaticTypeLayout> {
+        self.try_resolve(name).ok()
+    }
+
+    /// Like `resolve`, with the reason `name` failed to parse or resolve.
+    pub fn try_resolve(&self, name: &str) -> Result<StaticTypeLayout, TypeExprError> {
+        if let Some(named) = self.types.get(name) {
+            return Ok(named.layout.clone());
+        }
+        match self.unwrap_rule(name, 0) {
+            Some((wrappers, named)) => Self::wrap_named(named, &wrappers, name),
+            None => self.resolve_expr(&parse_type(name)?),
+        }
+    }
+
+    /// Resolves a parsed type. Its canonical spelling is looked up first, so whole expressions can
+    /// be registered, otherwise pointers, shared pointers, lists and wrapper rules wrap the
+    /// registered type inside them. Enums without a registered name are stored as `int`.
+    pub fn resolve_expr(&self, expr: &TypeExpr) -> Result<StaticTypeLayout, TypeExprError> {
+        let (wrappers, named) = self.unwrap_parsed(expr, 0)?;
+        Self::wrap_named(named, &wrappers, &expr.to_string())
+    }
+
+    /// Like `try_resolve`, without wrapping the registered type in its wrappers.
+    pub fn try_resolve_wrapped(&self, name: &str) -> Result<WrappedType, TypeExprError> {
+        let (wrappers, named) = self.unwrap_name(name, 0)?;
+        Self::wrap_named(named, &wrappers, name)?;
+        Ok(WrappedType { wrappers, name: named.name.clone(), layout: named.layout.clone() })
+    }
+
+    fn wrap_named(named: &NamedType, wrappers: &[TypeWrapper], name: &str) -> Result<StaticTypeLayout, TypeExprError> {
+        if wrappers.is_empty() {
+            return Ok(named.layout.clone());
+        }
+        (named.wrap)(wrappers).ok_or_else(|| TypeExprError::TooManyWrappers { name: name.into() })
+    }
+
+    /// Splits `name` into its wrappers, outermost first, and the registered type they wrap. Rules
+    /// resolve the text between their prefix and suffix the same way, so they nest with each other
+    /// and with the wrappers `parse_type` knows. `depth` counts the wrappers around `name`, past
+    /// `MAX_NESTING` rules no longer apply and the name is left to fail to parse.
+    fn unwrap_name(&self, name: &str, depth: usize) -> Result<(Vec<TypeWrapper>, &NamedType), TypeExprError> {
+        if let Some(named) = self.types.get(name) {
+            return Ok((Vec::new(), named));
+        }
+        if let Some(unwrapped) = self.unwrap_rule(name, depth) {
+            return Ok(unwrapped);
+        }
+        self.unwrap_parsed(&parse_type(name)?, depth)
+    }
+
+    /// `unwrap_name` for a parsed type, matching rules against the canonical spelling of each level.
+    fn unwrap_parsed(&self, expr: &TypeExpr, depth: usize) -> Result<(Vec<TypeWrapper>, &NamedType), TypeExprError> {
+        let name = expr.to_string();
+        if let Some(named) = self.types.get(name.as_str()) {
+            return Ok((Vec::new(), named));
+        }
+        let (wrapper, inner) = match expr {
+            TypeExpr::Pointer(inner) => (TypeWrapper::Pointer, inner),
+            TypeExpr::SharedPointer(inner) => (TypeWrapper::SharedPointer, inner),
+            TypeExpr::List(inner) => (TypeWrapper::List, inner),
+            _ => match self.unwrap_rule(&name, depth) {
+                Some(unwrapped) => return Ok(unwrapped),
+                None => return Ok((Vec::new(), self.named(expr)?)),
+            },
+        };
+        let (mut wrappers, named) = self.unwrap_parsed(inner, depth + 1)?;
+        wrappers.insert(0, wrapper);
+        Ok((wrappers, named))
+    }
+
+    /// The first rule, in the order they were added, whose prefix and suffix match `name` and whose
+    /// inner type resolves. Rules that strip nothing are skipped, they would never finish.
+    fn unwrap_rule(&self, name: &str, depth: usize) -> Option<(Vec<TypeWrapper>, &NamedType)> {
+        if depth == MAX_NESTING {
+            return None;
+        }
+        self.rules.iter().find_map(|rule| {
+            let inner = name.strip_prefix(rule.prefix.as_str())?.strip_suffix(rule.suffix.as_str())?.trim();
+            if inner.len() == name.len() {
+                return None;
+            }
+            let (mut wrappers, named) = self.unwrap_name(inner, depth + 1).ok()?;
+            wrappers.insert(0, rule.wrapper);
+            Some((wrappers, named))
+        })
+    }
+
+    fn named(&self, expr: &TypeExpr) -> Result<&NamedType, TypeExprError> {
+        let name = expr.to_string();
+        let named = self.types.get(name.as_str());
+        let named = match expr {
+            TypeExpr::Enum(_) => named.or_else(|| self.types.get("int")),
+            _ => named,
+        };
+        named.ok_or_else(|| TypeExprError::UnknownType { name: name.into() })
+    }
+
+    /// The Rust type `name` resolves to, as `std::any::type_name` spells it.
+    pub fn rust_type_name(&self, name: &str) -> Option<&'static str> {
+        self.resolve(name).map(|layout| layout.type_name())
+    }
+}
+
+impl TypeRegistry {
+    /// Maps the C++ type `name` to `T`, see `TypeNameResolver::add`.
+    pub fn add_type_name<T: Any + Default>(&self, name: &str) {
+        self.type_names.write().add::<T>(name);
+    }
+
+    /// See `TypeNameResolver::add_layout`.
+    pub fn add_type_name_layout<T: Any>(&self, name: &str, layout: StaticTypeLayout) {
+        self.type_names.write().add_layout::<T>(name, layout);
+    }
+
+    /// See `TypeNameResolver::add_wrapper`.
+    pub fn add_wrapper_rule(&self, prefix: &str, suffix: &str, wrapper: TypeWrapper) {
+        self.type_names.write().add_wrapper(prefix, suffix, wrapper);
+    }
+
+    /// Changes the resolver in one go, e.g. to add a whole set of names.
+    pub fn update_type_names<R>(&self, update: impl FnOnce(&mut TypeNameResolver) -> R) -> R {
+        update(&mut self.type_names.write())
+    }
+
+    /// Resolves a C++ type name to its layout, see `TypeNameResolver`.
+    pub fn resolve_type_name(&self, name: &str) -> Option<StaticTypeLayout> {
+        self.type_names.read().resolve(name)
+    }
+
+    /// See `TypeNameResolver::try_resolve`.
+    pub fn try_resolve_type_name(&self, name: &str) -> Result<StaticTypeLayout, TypeExprError> {
+        self.type_names.read().try_resolve(name)
+    }
+
+    /// See `TypeNameResolver::try_resolve_wrapped`.
+    pub fn try_resolve_wrapped_type_name(&self, name: &str) -> Result<WrappedType, TypeExprError> {
+        self.type_names.read().try_resolve_wrapped(name)
+    }
+
+    /// Resolves a C++ type name to what a field of that type stores. Classes without a registered
+    /// type name fall back to the dynamic layout registered under `class Name`, stored inline, or
+    /// as `DynamicStruct`s of any layout behind pointers and lists.
+    pub fn resolve_field_kind(&self, name: &str) -> Result<FieldKind, TypeExprError> {
+        let err = match self.try_resolve_type_name(name) {
+            Ok(layout) => return Ok(FieldKind::Static(layout)),
+            Err(err @ TypeExprError::UnknownType { .. }) => err,
+            Err(err) => return Err(err),
+        };
+        let expr = parse_type(name)?;
+        let (wrappers, inner) = unwrap_expr(&expr);
+        let Some(layout) = self.get_dynamic_layout(&inner.to_string()) else { return Err(err) };
+        if wrappers.is_empty() {
+            return Ok(FieldKind::Dynamic(layout));
+        }
+        wrap::<DynamicStruct>(&wrappers)
+            .map(FieldKind::Static)
+            .ok_or_else(|| TypeExprError::TooManyWrappers { name: expr.to_string().into() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamic_types::DynamicTypeLayout;
+    use std::any::{type_name, TypeId};
+
+    #[derive(Debug, Default, Clone, PartialEq)]
+    struct Foo(u32);
+
+    fn type_id(layout: Option<StaticTypeLayout>) -> Option<TypeId> {
+        layout.map(|layout| layout.type_id)
+    }
+
+    #[test]
+    fn resolves_primitives_and_wrappers() {
+        let resolver = TypeNameResolver::default();
+        assert_eq!(resolver.rust_type_name("unsigned short"), Some(type_name::<u16>()));
+        assert_eq!(resolver.rust_type_name("long"), Some(type_name::<i32>()));
+        assert_eq!(resolver.rust_type_name("std::wstring"), Some(type_name::<String>()));
+        assert_eq!(resolver.rust_type_name("float*"), Some(type_name::<Option<Box<f32>>>()));
+        assert_eq!(
+            resolver.rust_type_name("class SharedPointer<std::string>"),
+            Some(type_name::<Option<Arc<String>>>())
+        );
+        assert_eq!(resolver.rust_type_name("const unsigned __int64"), Some(type_name::<u64>()));
+        assert_eq!(resolver.rust_type_name("enum Kind"), Some(type_name::<i32>()));
+        assert_eq!(
+            resolver.rust_type_name("std::vector<unsigned long>*"),
+            Some(type_name::<Option<Box<Vec<u32>>>>())
+        );
+        assert_eq!(resolver.rust_type_name("class Foo"), None);
+        assert_eq!(
+            resolver.try_resolve("std::list<class SharedPointer<int>*>").map(|layout| layout.type_name()),
+            Err(TypeExprError::TooManyWrappers { name: "std::vector<class SharedPointer<int>*>".into() })
+        );
+        assert_eq!(
+            resolver.try_resolve("class SharedPointer<class Foo>*").map(|layout| layout.type_name()),
+            Err(TypeExprError::UnknownType { name: "class Foo".into() })
+        );
+        assert!(matches!(
+            resolver.try_resolve("unsigned bool"),
+            Err(TypeExprError::UnexpectedToken { position: 9, .. })
+        ));
+    }
+
+    #[test]
+    fn wrappers_compose_with_registered_types() {
+        let registry = TypeRegistry::default();
+        registry.add_type_name_layout::<Foo>("class Foo", StaticTypeLayout::of::<Foo>().with_debug::<Foo>());
+        registry.add_wrapper_rule("std::vector<", ">", TypeWrapper::List);
+
+        assert!(registry.resolve_type_name("class Foo").unwrap().debug_fn.is_some());
+        assert_eq!(type_id(registry.resolve_type_name("class Foo*")), Some(TypeId::of::<Option<Box<Foo>>>()));
+        assert_eq!(
+            type_id(registry.resolve_type_name("class SharedPointer<class Foo>")),
+            Some(TypeId::of::<Option<Arc<Foo>>>())
+        );
+        assert_eq!(type_id(registry.resolve_type_name("std::vector<class Foo>")), Some(TypeId::of::<Vec<Foo>>()));
+        assert_eq!(type_id(registry.resolve_type_name("std::vector<int>")), Some(TypeId::of::<Vec<i32>>()));
+
+        assert_eq!(
+            type_id(registry.resolve_type_name("class SharedPointer<const struct Foo>*")),
+            Some(TypeId::of::<Option<Box<Option<Arc<Foo>>>>>())
+        );
+
+        registry.add_wrapper_rule("class List<", ">", TypeWrapper::List);
+        assert_eq!(type_id(registry.resolve_type_name("class List<int>*")), Some(TypeId::of::<Option<Box<Vec<i32>>>>()));
+        assert_eq!(
+            type_id(registry.resolve_type_name("class SharedPointer<class List<int>>")),
+            Some(TypeId::of::<Option<Arc<Vec<i32>>>>())
+        );
+        assert_eq!(type_id(registry.resolve_type_name("class List<int*>")), Some(TypeId::of::<Vec<Option<Box<i32>>>>()));
+        assert_eq!(type_id(registry.resolve_type_name("class List<class Foo*>")), Some(TypeId::of::<Vec<Option<Box<Foo>>>>()));
+        let wrapped = registry.try_resolve_wrapped_type_name("class List<class Foo*>").unwrap();
+        assert_eq!(wrapped.wrappers, [TypeWrapper::List, TypeWrapper::Pointer]);
+        assert_eq!(wrapped.name, "class Foo");
+        assert_eq!(registry.try_resolve_wrapped_type_name("enum Kind*").unwrap().name, "int");
+        assert_eq!(
+            registry.try_resolve_type_name("class List<class List<int>*>").map(|layout| layout.type_name()),
+            Err(TypeExprError::TooManyWrappers { name: "class List<class List<int>*>".into() })
+        );
+
+        let nested = format!("{}int{}", "class List<".repeat(10_000), ">".repeat(10_000));
+        assert!(matches!(registry.try_resolve_type_name(&nested), Err(TypeExprError::TooDeep { .. })));
+
+        registry.update_type_names(|resolver| resolver.add::<u64>("int"));
+        assert_eq!(type_id(registry.resolve_type_name("int*")), Some(TypeId::of::<Option<Box<u64>>>()));
+        assert_eq!(type_id(registry.resolve_type_name("long int*")), Some(TypeId::of::<Option<Box<u64>>>()));
+    }
+
+    #[test]
+    fn classes_fall_back_to_dynamic_layouts() {
+        let registry = TypeRegistry::default();
+        let layout = DynamicTypeLayout::builder("class Bar".into())
+            .field("m_id", &StaticTypeLayout::of::<u32>())
+            .build();
+        registry.add_dyn(layout).unwrap();
+
+        let kind = registry.resolve_field_kind("const class Bar").unwrap();
+        assert!(matches!(kind, FieldKind::Dynamic(layout) if layout.name == "class Bar"));
+        let kind = registry.resolve_field_kind("std::vector<class SharedPointer<class Bar>>").unwrap();
+        assert_eq!(kind.type_id(), TypeId::of::<Vec<Option<Arc<DynamicStruct>>>>());
+        assert!(matches!(registry.resolve_field_kind("unsigned short"), Ok(FieldKind::Static(_))));
+        assert_eq!(
+            registry.resolve_field_kind("class Baz*").err(),
+            Some(TypeExprError::UnknownType { name: "class Baz".into() })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid type")]
+    fn layouts_must_match_their_type() {
+        TypeNameResolver::empty().add_layout::<Foo>("class Foo", StaticTypeLayout::of::<u32>());
+    }
+}
@@ -0,0 +1,326 @@
+use std::{any::Any, sync::Arc};
+
+use parking_lot::{Mutex, RwLock};
+
+use super::{DynamicStruct, DynamicStructRef};
+
+/// A primitive value passed to `DynamicVisitor::visit_primitive`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Primitive {
+    Bool(bool),
+    Char(char),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    Isize(isize),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    Usize(usize),
+    F32(f32),
+    F64(f64),
+}
+
+/// Receives the values of a dynamic struct as `DynamicStruct::visit` walks it.
+///
+/// The walk drives the traversal, every `enter_*` call is matched by its `exit_*` call once the
+/// children have been visited, so a visitor only overrides the callbacks it cares about.
+#[allow(unused_variables)]
+pub trait DynamicVisitor {
+    fn visit_primitive(&mut self, value: Primitive) {}
+
+    fn visit_str(&mut self, value: &str) {}
+
+    /// A `Vec` or inline array, followed by `len` element visits.
+    fn enter_seq(&mut self, len: usize) {}
+
+    fn exit_seq(&mut self) {}
+
+    fn visit_none(&mut self) {}
+
+    /// A `Some`, followed by a visit of its value.
+    fn enter_some(&mut self) {}
+
+    fn exit_some(&mut self) {}
+
+    /// An `Arc` pointing at `ptr`, followed by a visit of the shared value. `ptr` identifies the
+    /// allocation, so visitors can detect values shared between fields.
+    fn enter_shared(&mut self, ptr: *const ()) {}
+
+    fn exit_shared(&mut self) {}
+
+    /// A dynamic struct, followed by `enter_field`/`exit_field` around each of its fields.
+    fn enter_struct(&mut self, value: DynamicStructRef<'_>) {}
+
+    fn exit_struct(&mut self) {}
+
+    fn enter_field(&mut self, name: &str, index: usize) {}
+
+    fn exit_field(&mut self) {}
+
+    /// A value whose layout was created without `StaticTypeLayout::with_visit`.
+    fn visit_opaque(&mut self, type_name: &'static str, value: &dyn Any) {}
+}
+
+/// Types that can describe themselves to a `DynamicVisitor`, captured for dynamic fields with
+/// `StaticTypeLayout::with_visit`.
+pub trait Visitable {
+    fn visit(&self, visitor: &mut dyn DynamicVisitor);
+}
+
+macro_rules! visit_primitive {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl Visitable for $ty {
+                #[inline]
+                fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+                    visitor.visit_primitive(Primitive::$variant(*self));
+                }
+            }
+        )*
+    };
+}
+
+visit_primitive! {
+    bool => Bool,
+    char => Char,
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    i128 => I128,
+    isize => Isize,
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+    u128 => U128,
+    usize => Usize,
+    f32 => F32,
+    f64 => F64,
+}
+
+impl Visitable for str {
+    #[inline]
+    fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        visitor.visit_str(self);
+    }
+}
+
+impl Visitable for std::string::String {
+    #[inline]
+    fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        visitor.visit_str(self);
+    }
+}
+
+impl Visitable for smartstring::alias::String {
+    #[inline]
+    fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        visitor.visit_str(self);
+    }
+}
+
+impl<T: Visitable> Visitable for [T] {
+    fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        visitor.enter_seq(self.len());
+        for element in self {
+            element.visit(visitor);
+        }
+        visitor.exit_seq();
+    }
+}
+
+impl<T: Visitable> Visitable for Vec<T> {
+    #[inline]
+    fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        self.as_slice().visit(visitor);
+    }
+}
+
+impl<T: Visitable> Visitable for Option<T> {
+    fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        match self {
+            Some(value) => {
+                visitor.enter_some();
+                value.visit(visitor);
+                visitor.exit_some();
+            }
+            None => visitor.visit_none(),
+        }
+    }
+}
+
+impl<T: Visitable + ?Sized> Visitable for Arc<T> {
+    fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        visitor.enter_shared(Arc::as_ptr(self).cast::<()>());
+        (**self).visit(visitor);
+        visitor.exit_shared();
+    }
+}
+
+/// Boxes own their value, so they are visited as the value itself.
+impl<T: Visitable + ?Sized> Visitable for Box<T> {
+    #[inline]
+    fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        (**self).visit(visitor);
+    }
+}
+
+/// Locks are held for the duration of the visit.
+impl<T: Visitable> Visitable for RwLock<T> {
+    #[inline]
+    fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        self.read().visit(visitor);
+    }
+}
+
+impl<T: Visitable> Visitable for Mutex<T> {
+    #[inline]
+    fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        self.lock().visit(visitor);
+    }
+}
+
+impl Visitable for DynamicStruct {
+    #[inline]
+    fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        self.as_ref().visit(visitor);
+    }
+}
+
+impl Visitable for DynamicStructRef<'_> {
+    #[inline]
+    fn visit(&self, visitor: &mut dyn DynamicVisitor) {
+        DynamicStructRef::visit(self, visitor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamic_types::{DynamicTypeLayout, StaticTypeLayout};
+    use std::fmt::Write;
+
+    /// Writes every callback into a compact string, enough to check the order of the walk.
+    #[derive(Default)]
+    struct Recorder(std::string::String);
+
+    impl DynamicVisitor for Recorder {
+        fn visit_primitive(&mut self, value: Primitive) {
+            write!(self.0, "{:?} ", value).unwrap();
+        }
+
+        fn visit_str(&mut self, value: &str) {
+            write!(self.0, "{:?} ", value).unwrap();
+        }
+
+        fn enter_seq(&mut self, len: usize) {
+            write!(self.0, "[{} ", len).unwrap();
+        }
+
+        fn exit_seq(&mut self) {
+            self.0.push_str("] ");
+        }
+
+        fn visit_none(&mut self) {
+            self.0.push_str("None ");
+        }
+
+        fn enter_some(&mut self) {
+            self.0.push_str("Some( ");
+        }
+
+        fn exit_some(&mut self) {
+            self.0.push_str(") ");
+        }
+
+        fn enter_shared(&mut self, _ptr: *const ()) {
+            self.0.push_str("Arc( ");
+        }
+
+        fn exit_shared(&mut self) {
+            self.0.push_str(") ");
+        }
+
+        fn enter_struct(&mut self, value: DynamicStructRef<'_>) {
+            write!(self.0, "{} {{ ", value.layout().name).unwrap();
+        }
+
+        fn exit_struct(&mut self) {
+            self.0.push_str("} ");
+        }
+
+        fn enter_field(&mut self, name: &str, _index: usize) {
+            write!(self.0, "{}: ", name).unwrap();
+        }
+
+        fn visit_opaque(&mut self, type_name: &'static str, _value: &dyn Any) {
+            write!(self.0, "<{}> ", type_name).unwrap();
+        }
+    }
+
+    fn visitable<T: Any + Default + Visitable>() -> StaticTypeLayout {
+        StaticTypeLayout::of::<T>().with_visit::<T>()
+    }
+
+    #[test]
+    fn visit_walks_every_field_in_order() {
+        let unit = Arc::new(
+            DynamicTypeLayout::builder("Unit".into())
+                .field("name", &visitable::<smartstring::alias::String>())
+                .field("hp", &visitable::<u16>())
+                .build(),
+        );
+        let stats = Arc::new(
+            DynamicTypeLayout::builder("Stats".into())
+                .field("level", &visitable::<u8>())
+                .build(),
+        );
+        let layout = Arc::new(
+            DynamicTypeLayout::builder("Hero".into())
+                .base(&unit)
+                .nested("stats", &stats)
+                .field("scores", &visitable::<Vec<i32>>())
+                .field("title", &visitable::<Option<Arc<std::string::String>>>())
+                .field("pet", &visitable::<Option<Box<u8>>>())
+                .array("flags", &visitable::<bool>(), 2)
+                .field("secret", &StaticTypeLayout::of::<u64>())
+                .build(),
+        );
+
+        let mut hero = DynamicStruct::new(layout);
+        hero.set_field("name", smartstring::alias::String::from("Ash"));
+        hero.set_field("hp", 7u16);
+        hero.get_struct_mut("stats").set_field("level", 3u8);
+        hero.set_field("scores", vec![1, 2]);
+        hero.set_field("title", Some(Arc::new(std::string::String::from("Sir"))));
+        hero.get_array_mut::<bool>("flags")[1] = true;
+
+        let mut recorder = Recorder::default();
+        hero.visit(&mut recorder);
+        assert_eq!(
+            recorder.0,
+            "Hero { name: \"Ash\" hp: U16(7) stats: Stats { level: U8(3) } scores: [2 I32(1) I32(2) ] \
+             title: Some( Arc( \"Sir\" ) ) pet: None flags: [2 Bool(false) Bool(true) ] secret: <u64> } "
+        );
+    }
+
+    #[test]
+    fn vectors_of_dynamic_structs_are_visited() {
+        let item = Arc::new(
+            DynamicTypeLayout::builder("Item".into())
+                .field("id", &visitable::<u32>())
+                .build(),
+        );
+        let items = vec![DynamicStruct::new(item.clone()), DynamicStruct::new(item)];
+
+        let mut recorder = Recorder::default();
+        items.visit(&mut recorder);
+        assert_eq!(recorder.0, "[2 Item { id: U32(0) } Item { id: U32(0) } ] ");
+    }
+}
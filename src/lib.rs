@@ -0,0 +1,71 @@
+use dynamic_types::{type_names::TypeNameResolver, StaticTypeLayout, TypeRegistry};
+
+pub mod dynamic_types;
+
+/// Registers the KingsIsle types of the client's type dumps on top of the C++ primitives, e.g. with
+/// `TypeRegistry::update_type_names(add_kitypes)`.
+pub fn add_kitypes(resolver: &mut TypeNameResolver) {
+    resolver.add::<GID>("gid");
+    resolver.add::<Vector3D>("class Vector3D");
+    resolver.add::<Color>("class Color");
+    resolver.add::<Point>("class Point");
+}
+
+/// The Rust type `registry` stores the C++ type `ctype` as, or `"unknown"`.
+pub fn kitype_to_rusttype(registry: &TypeRegistry, ctype: &str) -> &'static str {
+    registry.resolve_type_name(ctype).map_or("unknown", |layout| layout.type_name())
+}
+
+pub fn kitype_to_dyn_type_layout(registry: &TypeRegistry, ctype: &str) -> StaticTypeLayout {
+    try_kitype_to_dyn_type_layout(registry, ctype).unwrap_or_else(|| panic!("Unhandled type: {}", ctype))
+}
+
+/// Maps a C++ type from the client's type dumps to its layout in `registry`, `None` if it isn't
+/// handled.
+pub fn try_kitype_to_dyn_type_layout(registry: &TypeRegistry, ctype: &str) -> Option<StaticTypeLayout> {
+    registry.resolve_type_name(ctype)
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Vector3D {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Color {
+    pub r: u8,
+    pub b: u8,
+    pub g: u8,
+    pub a: u8,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct GID {
+    pub id: u32,
+    pub ty: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{any::type_name, sync::Arc};
+
+    #[test]
+    fn kitypes_resolve_through_the_registry() {
+        let registry = TypeRegistry::default();
+        assert_eq!(kitype_to_rusttype(&registry, "class Vector3D"), "unknown");
+
+        registry.update_type_names(add_kitypes);
+        assert_eq!(kitype_to_rusttype(&registry, "class SharedPointer<class Vector3D>"), type_name::<Option<Arc<Vector3D>>>());
+        assert_eq!(kitype_to_dyn_type_layout(&registry, "gid*").type_name(), type_name::<Option<Box<GID>>>());
+        assert!(try_kitype_to_dyn_type_layout(&registry, "class Missing").is_none());
+    }
+}
@@ -1,18 +1,22 @@
-#![feature(vec_into_raw_parts)]
-
 use std::{
+    any::Any,
+    fmt::Debug,
     hint::black_box,
     sync::Arc,
     time::{Duration, Instant},
 };
 
 use smartstring::alias::String;
-use dynamic_types::*;
-
-mod dynamic_types;
+use testing_unsafe::dynamic_types::*;
 
 fn main() {
     let type_registry = TypeRegistry::default();
+    register_debug::<u8>(&type_registry);
+    register_debug::<i32>(&type_registry);
+    register_debug::<f32>(&type_registry);
+    register_debug::<String>(&type_registry);
+    register_debug::<Vec<i32>>(&type_registry);
+    register_debug::<Arc<TestCrap>>(&type_registry);
 
     let type_layout = DynamicTypeLayout::new(
         "Test".into(),
@@ -27,7 +31,7 @@ fn main() {
         ],
     );
 
-    type_registry.add_dyn(type_layout);
+    type_registry.add_dyn(type_layout).unwrap();
 
     let mut dyn_type = type_registry.create_dynamic("Test");
 
@@ -37,6 +41,8 @@ fn main() {
     dyn_type.set_field("d", vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
     dyn_type.set_field("e", Arc::new(TestCrap));
 
+    println!("{:?}", dyn_type);
+
     let mut timer = TimeCollection::with_capacity(100000);
     for _ in 0..100000 {
         timer.start();
@@ -64,7 +70,24 @@ fn main() {
 
     timer.clear();
 
-    #[derive(Debug)]
+    let layout = dyn_type.layout();
+    let a_handle = layout.field_handle::<i32>("a").unwrap();
+    let b_handle = layout.field_handle::<f32>("b").unwrap();
+    let c_handle = layout.field_handle::<String>("c").unwrap();
+    let d_handle = layout.field_handle::<Vec<i32>>("d").unwrap();
+    for _ in 0..100000 {
+        timer.start();
+        let _a = black_box(dyn_type.get(&a_handle));
+        let _b = black_box(dyn_type.get(&b_handle));
+        let _c = black_box(dyn_type.get(&c_handle));
+        let _d = black_box(dyn_type.get(&d_handle));
+        timer.end();
+    }
+
+    let handle_average = timer.average();
+
+    timer.clear();
+
     #[repr(C)]
     pub struct TestLayout {
         o: u8,
@@ -89,11 +112,13 @@ fn main() {
     let casted_average = timer.average();
 
     println!(
-        "name get: {:?}, index get: {:?}, casted get: {:?}",
-        name_average, index_average, casted_average
+        "name get: {:?}, index get: {:?}, handle get: {:?}, casted get: {:?}",
+        name_average, index_average, handle_average, casted_average
     );
+}
 
-    println!("{:?}", data);
+fn register_debug<T: Any + Default + Debug>(type_registry: &TypeRegistry) {
+    type_registry.add_layout(StaticTypeLayout::of::<T>().with_debug::<T>());
 }
 
 struct TimeCollection {
@@ -129,172 +154,5 @@ impl TimeCollection {
     }
 }
 
-pub fn kitype_to_rusttype(ctype: &str) -> &'static str {
-    use std::any::type_name;
-    if ctype.starts_with("class SharedPointer") {
-        let ctype = ctype
-            .trim_start_matches("class SharedPointer<")
-            .trim_end_matches('>');
-        match ctype {
-            "unsigned char" => type_name::<Option<Arc<u8>>>(),
-            "char" => type_name::<Option<Arc<i8>>>(),
-            "short" => type_name::<Option<Arc<i16>>>(),
-            "unsigned short" => type_name::<Option<Arc<u16>>>(),
-            "int" => type_name::<Option<Arc<i32>>>(),
-            "unsigned int" => type_name::<Option<Arc<u32>>>(),
-            "long" => type_name::<Option<Arc<i32>>>(),
-            "unsigned long" => type_name::<Option<Arc<u32>>>(),
-            "gid" => type_name::<Option<Arc<GID>>>(),
-            "float" => type_name::<Option<Arc<f32>>>(),
-            "double" => type_name::<Option<Arc<f64>>>(),
-            "std::string" => type_name::<Option<Arc<String>>>(),
-            "std::wstring" => type_name::<Option<Arc<String>>>(),
-            "class Vector3D" => type_name::<Option<Arc<Vector3D>>>(),
-            "class Color" => type_name::<Option<Arc<Color>>>(),
-            "class Point" => type_name::<Option<Arc<Point>>>(),
-            _ => "unknown",
-        }
-    } else if ctype.ends_with('*') {
-        let ctype = ctype.trim_end_matches('*');
-        match ctype {
-            "unsigned char" => type_name::<Option<Box<u8>>>(),
-            "char" => type_name::<Option<Box<i8>>>(),
-            "short" => type_name::<Option<Box<i16>>>(),
-            "unsigned short" => type_name::<Option<Box<u16>>>(),
-            "int" => type_name::<Option<Box<i32>>>(),
-            "unsigned int" => type_name::<Option<Box<u32>>>(),
-            "long" => type_name::<Option<Box<i32>>>(),
-            "unsigned long" => type_name::<Option<Box<u32>>>(),
-            "gid" => type_name::<Option<Box<GID>>>(),
-            "float" => type_name::<Option<Box<f32>>>(),
-            "double" => type_name::<Option<Box<f64>>>(),
-            "std::string" => type_name::<Option<Box<String>>>(),
-            "std::wstring" => type_name::<Option<Box<String>>>(),
-            "class Vector3D" => type_name::<Option<Box<Vector3D>>>(),
-            "class Color" => type_name::<Option<Box<Color>>>(),
-            "class Point" => type_name::<Option<Box<Point>>>(),
-            _ => "unknown",
-        }
-    } else {
-        match ctype {
-            "unsigned char" => type_name::<u8>(),
-            "char" => type_name::<i8>(),
-            "short" => type_name::<i16>(),
-            "unsigned short" => type_name::<u16>(),
-            "int" => type_name::<i32>(),
-            "unsigned int" => type_name::<u32>(),
-            "long" => type_name::<i32>(),
-            "unsigned long" => type_name::<u32>(),
-            "gid" => type_name::<GID>(),
-            "float" => type_name::<f32>(),
-            "double" => type_name::<f64>(),
-            "std::string" => type_name::<String>(),
-            "std::wstring" => type_name::<String>(),
-            "class Vector3D" => type_name::<Vector3D>(),
-            "class Color" => type_name::<Color>(),
-            "class Point" => type_name::<Point>(),
-            _ => "unknown",
-        }
-    }
-}
-
-pub fn kitype_to_dyn_type_layout(ctype: &str) -> StaticTypeLayout {
-    if ctype.starts_with("class SharedPointer") {
-        //Shared pointers aka Arcs
-        let ctype = ctype
-            .trim_start_matches("class SharedPointer<")
-            .trim_end_matches('>');
-        match ctype {
-            "unsigned char" => StaticTypeLayout::of::<Option<Arc<u8>>>(),
-            "char" => StaticTypeLayout::of::<Option<Arc<i8>>>(),
-            "short" => StaticTypeLayout::of::<Option<Arc<i16>>>(),
-            "unsigned short" => StaticTypeLayout::of::<Option<Arc<u16>>>(),
-            "int" => StaticTypeLayout::of::<Option<Arc<i32>>>(),
-            "unsigned int" => StaticTypeLayout::of::<Option<Arc<u32>>>(),
-            "long" => StaticTypeLayout::of::<Option<Arc<i32>>>(),
-            "unsigned long" => StaticTypeLayout::of::<Option<Arc<u32>>>(),
-            "gid" => StaticTypeLayout::of::<Option<Arc<GID>>>(),
-            "float" => StaticTypeLayout::of::<Option<Arc<f32>>>(),
-            "double" => StaticTypeLayout::of::<Option<Arc<f64>>>(),
-            "std::string" => StaticTypeLayout::of::<Option<Arc<String>>>(),
-            "std::wstring" => StaticTypeLayout::of::<Option<Arc<String>>>(),
-            "class Vector3D" => StaticTypeLayout::of::<Option<Arc<Vector3D>>>(),
-            "class Color" => StaticTypeLayout::of::<Option<Arc<Color>>>(),
-            "class Point" => StaticTypeLayout::of::<Option<Arc<Point>>>(),
-            _ => panic!("Unhandled type: {}", ctype),
-        }
-    } else if ctype.ends_with('*') {
-        //Raw pointers
-        let ctype = ctype.trim_end_matches('*');
-        match ctype {
-            "unsigned char" => StaticTypeLayout::of::<Option<Box<u8>>>(),
-            "char" => StaticTypeLayout::of::<Option<Box<i8>>>(),
-            "short" => StaticTypeLayout::of::<Option<Box<i16>>>(),
-            "unsigned short" => StaticTypeLayout::of::<Option<Box<u16>>>(),
-            "int" => StaticTypeLayout::of::<Option<Box<i32>>>(),
-            "unsigned int" => StaticTypeLayout::of::<Option<Box<u32>>>(),
-            "long" => StaticTypeLayout::of::<Option<Box<i32>>>(),
-            "unsigned long" => StaticTypeLayout::of::<Option<Box<u32>>>(),
-            "gid" => StaticTypeLayout::of::<Option<Box<GID>>>(),
-            "float" => StaticTypeLayout::of::<Option<Box<f32>>>(),
-            "double" => StaticTypeLayout::of::<Option<Box<f64>>>(),
-            "std::string" => StaticTypeLayout::of::<Option<Box<String>>>(),
-            "std::wstring" => StaticTypeLayout::of::<Option<Box<String>>>(),
-            "class Vector3D" => StaticTypeLayout::of::<Option<Box<Vector3D>>>(),
-            "class Color" => StaticTypeLayout::of::<Option<Box<Color>>>(),
-            "class Point" => StaticTypeLayout::of::<Option<Box<Point>>>(),
-            _ => panic!("Unhandled type: {}", ctype),
-        }
-    } else {
-        match ctype {
-            //Value types
-            "unsigned char" => StaticTypeLayout::of::<u8>(),
-            "char" => StaticTypeLayout::of::<i8>(),
-            "short" => StaticTypeLayout::of::<i16>(),
-            "unsigned short" => StaticTypeLayout::of::<u16>(),
-            "int" => StaticTypeLayout::of::<i32>(),
-            "unsigned int" => StaticTypeLayout::of::<u32>(),
-            "long" => StaticTypeLayout::of::<i32>(),
-            "unsigned long" => StaticTypeLayout::of::<u32>(),
-            "gid" => StaticTypeLayout::of::<GID>(),
-            "float" => StaticTypeLayout::of::<f32>(),
-            "double" => StaticTypeLayout::of::<f64>(),
-            "std::string" => StaticTypeLayout::of::<String>(),
-            "std::wstring" => StaticTypeLayout::of::<String>(),
-            "class Vector3D" => StaticTypeLayout::of::<Vector3D>(),
-            "class Color" => StaticTypeLayout::of::<Color>(),
-            "class Point" => StaticTypeLayout::of::<Point>(),
-            _ => panic!("Unhandled type: {}", ctype),
-        }
-    }
-}
-
-#[derive(Debug, Copy, Clone, Default)]
-pub struct Vector3D {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
-}
-
-#[derive(Debug, Copy, Clone, Default)]
-pub struct Color {
-    pub r: u8,
-    pub b: u8,
-    pub g: u8,
-    pub a: u8,
-}
-
-#[derive(Debug, Copy, Clone, Default)]
-pub struct Point {
-    pub x: f32,
-    pub y: f32,
-}
-
-#[derive(Debug, Copy, Clone, Default)]
-pub struct GID {
-    pub id: u32,
-    pub ty: u32,
-}
-
 #[derive(Debug, Default)]
 pub struct TestCrap;
\ No newline at end of file